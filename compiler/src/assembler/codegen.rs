@@ -1,3 +1,4 @@
+use emulator_core::memory_layout::{BANK_SELECT, BANK_WINDOW, GLYPHS, KBD, LEDS, SCREEN, STACK_BASE, STATIC_BASE, WRITE_PROTECT};
 use serde::Serialize;
 use ts_rs::TS;
 
@@ -29,9 +30,13 @@ fn predefined_symbol_code(sym: &str) -> Option<u16> {
         "R13" => Some(13),
         "R14" => Some(14),
         "R15" => Some(15),
-        "SCREEN" => Some(18432),
-        "KBD" => Some(26624),
-        "GLYPHS" => Some(26625),
+        "SCREEN" => Some(SCREEN as u16),
+        "KBD" => Some(KBD as u16),
+        "GLYPHS" => Some(GLYPHS as u16),
+        "LEDS" => Some(LEDS as u16),
+        "BANK" => Some(BANK_SELECT as u16),
+        "BANK_WINDOW" => Some(BANK_WINDOW as u16),
+        "WRITE_PROTECT" => Some(WRITE_PROTECT as u16),
         _ => None,
     }
 }
@@ -134,6 +139,7 @@ pub struct CodeGenerator<'a> {
     resolved_symbols: HashMap<&'a str, u16>,
     commands: &'a [ASMInstruction],
     address_next_static_variable: u16,
+    static_addresses: HashMap<String, u16>,
 }
 
 #[derive(Default, Serialize, TS)]
@@ -150,14 +156,18 @@ pub struct AssemblySourcemap {
 pub struct AssemblyResult {
     pub instructions: Vec<u16>,
     pub sourcemap: AssemblySourcemap,
+    // The address given to each symbol which wasn't a label, which is how
+    // statics get placed.
+    pub static_addresses: HashMap<String, u16>,
 }
 
 impl<'a> CodeGenerator<'a> {
     pub fn new(first_pass_result: FirstPassResult<'a>, commands: &'a [ASMInstruction]) -> Self {
         Self {
-            address_next_static_variable: 16,
+            address_next_static_variable: STATIC_BASE as u16,
             resolved_symbols: first_pass_result.resolved_symbols,
             commands,
+            static_addresses: HashMap::new(),
         }
     }
 
@@ -176,10 +186,11 @@ impl<'a> CodeGenerator<'a> {
                     .or_else(|| self.resolved_symbols.get(sym.as_str()).copied())
                     .unwrap_or_else(|| {
                         let address = self.address_next_static_variable;
-                        if address > 255 {
+                        if address as usize >= STACK_BASE {
                             panic!("too many static variables - ran out of place while trying to place \"{}\"", sym)
                         }
                         self.resolved_symbols.insert(sym.as_str(), address);
+                        self.static_addresses.insert(sym.clone(), address);
                         self.address_next_static_variable += 1;
                         address
                     });
@@ -200,7 +211,11 @@ impl<'a> CodeGenerator<'a> {
                 instructions.push(instruction);
             }
         }
-        AssemblyResult { instructions, sourcemap }
+        AssemblyResult {
+            instructions,
+            sourcemap,
+            static_addresses: self.static_addresses.clone(),
+        }
     }
 }
 
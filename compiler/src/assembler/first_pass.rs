@@ -7,7 +7,7 @@ pub struct FirstPassResult<'a> {
     pub resolved_symbols: HashMap<&'a str, u16>,
 }
 
-pub fn first_pass(commands: &[ASMInstruction]) -> FirstPassResult {
+pub fn first_pass(commands: &[ASMInstruction]) -> FirstPassResult<'_> {
     let mut resolved_symbols = HashMap::new();
     let mut index = 0;
     for command in commands {
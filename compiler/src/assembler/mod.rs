@@ -22,13 +22,8 @@ pub fn assemble(source: &[ASMInstruction], rom_depth: usize) -> AssemblyResult {
     assembly_result
 }
 
-pub fn assemble_file(source_path: &Path, dest_path: &Path, rom_depth: usize) {
+pub fn assemble_file(source_path: &Path, rom_depth: usize) -> Vec<u16> {
     let string = fs::read_to_string(source_path).expect("failed to read source file");
     let parsed_instructions = parse(&string);
-    let machine_code_strings: Vec<_> = assemble(&parsed_instructions, rom_depth)
-        .instructions
-        .into_iter()
-        .map(|instruction| format!("{:016b}", instruction))
-        .collect();
-    fs::write(dest_path, machine_code_strings.join("\n")).expect("failed to write output");
+    assemble(&parsed_instructions, rom_depth).instructions
 }
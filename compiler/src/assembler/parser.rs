@@ -417,15 +417,15 @@ mod tests {
     fn test_parse() {
         let line = "     ";
         let result = parse(line);
-        assert_eq!(result.get(0), None);
+        assert_eq!(result.first(), None);
 
         let line = "  // hello this is a comment   ";
         let result = parse(line);
-        assert_eq!(result.get(0), None);
+        assert_eq!(result.first(), None);
 
         let line = "// hello this is a comment";
         let result = parse(line);
-        assert_eq!(result.get(0), None);
+        assert_eq!(result.first(), None);
 
         let line = "@1234";
         let result = parse(line);
@@ -10,6 +10,8 @@ use std::{
 
 use itertools::Itertools;
 
+use emulator_core::{memory_layout::GLYPHS, ram_image::RamImage};
+
 use crate::assembler::parser::{ASMInstruction, AValue};
 
 #[derive(PartialEq, Debug)]
@@ -148,9 +150,10 @@ fn parse_psf_file() -> HashMap<u16, [u8; 9]> {
     result
 }
 
-pub fn glyphs_asm() -> Vec<ASMInstruction> {
+// The glyph table, as it's laid out in RAM starting at GLYPHS.
+fn glyph_words() -> Vec<i16> {
     let glyph_map = parse_psf_file();
-    let glyph_loading_asm = glyph_map
+    glyph_map
         .into_iter()
         // sort to keep things deterministic when iterating over hashmap
         .sorted()
@@ -167,102 +170,112 @@ pub fn glyphs_asm() -> Vec<ASMInstruction> {
             // when converting the bytes into 16-bit chunks.
             let sixteen_bit_chunks = bitmap.chunks_exact(2);
 
-            #[allow(clippy::needless_collect)] // clippy is wrong - this collect isn't needless
-            let words: Vec<_> = sixteen_bit_chunks
+            sixteen_bit_chunks
                 .map(|chunk| i16::from_be_bytes(<[u8; 2]>::try_from(chunk).unwrap()))
-                .collect();
-
-            words.into_iter().flat_map(|word| {
-                fn load_and_increment_address() -> impl Iterator<Item = ASMInstruction> {
-                    vec![
-                        ASMInstruction::A(AValue::Symbolic("R7".to_string())),
-                        ASMInstruction::C {
-                            expr: "M+1".to_string(),
-                            dest: Some("AM".to_string()),
-                            jump: None,
-                        },
-                    ]
-                    .into_iter()
-                }
-
-                let alu_constants = vec![-1, 0, 1];
-
-                let instructions: Vec<_> = if alu_constants.contains(&word) {
-                    load_and_increment_address()
-                        .chain(vec![ASMInstruction::C {
-                            expr: word.to_string(),
-                            dest: Some("M".to_string()),
-                            jump: None,
-                        }])
-                        .collect()
-                } else if let Some(alu_constant) = alu_constants.iter().find(|&&alu_const| !alu_const == word) {
-                    load_and_increment_address()
-                        .chain(vec![
-                            ASMInstruction::C {
-                                expr: alu_constant.to_string(),
-                                dest: Some("M".to_string()),
-                                jump: None,
-                            },
-                            ASMInstruction::C {
-                                expr: "!M".to_string(),
-                                dest: Some("M".to_string()),
-                                jump: None,
-                            },
-                        ])
-                        .collect()
-                } else if let Some(alu_constant) = alu_constants.iter().find(|&&alu_const| -alu_const == word) {
-                    load_and_increment_address()
-                        .chain(vec![
-                            ASMInstruction::C {
-                                expr: alu_constant.to_string(),
-                                dest: Some("M".to_string()),
-                                jump: None,
-                            },
-                            ASMInstruction::C {
-                                expr: "-M".to_string(),
-                                dest: Some("M".to_string()),
-                                jump: None,
-                            },
-                        ])
-                        .collect()
-                } else if word < 0 {
-                    vec![
-                        ASMInstruction::A(AValue::Numeric((!word).to_string())),
-                        ASMInstruction::C {
-                            expr: "!A".to_string(),
-                            dest: Some("D".to_string()),
-                            jump: None,
-                        },
-                    ]
-                    .into_iter()
-                    .chain(load_and_increment_address())
-                    .chain(vec![ASMInstruction::C {
-                        expr: "D".to_string(),
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+pub fn glyphs_ram_image() -> RamImage {
+    let mut ram_image = RamImage::default();
+    ram_image.add_segment(GLYPHS, glyph_words().into_iter().map(|word| word as u16).collect());
+    ram_image
+}
+
+// Writes the glyph table into RAM one word at a time, for when there's no way
+// of loading a RAM image.
+pub fn glyphs_asm() -> Vec<ASMInstruction> {
+    let glyph_loading_asm = glyph_words().into_iter().flat_map(|word| {
+        fn load_and_increment_address() -> impl Iterator<Item = ASMInstruction> {
+            vec![
+                ASMInstruction::A(AValue::Symbolic("R7".to_string())),
+                ASMInstruction::C {
+                    expr: "M+1".to_string(),
+                    dest: Some("AM".to_string()),
+                    jump: None,
+                },
+            ]
+            .into_iter()
+        }
+
+        let alu_constants = [-1, 0, 1];
+
+        let instructions: Vec<_> = if alu_constants.contains(&word) {
+            load_and_increment_address()
+                .chain(vec![ASMInstruction::C {
+                    expr: word.to_string(),
+                    dest: Some("M".to_string()),
+                    jump: None,
+                }])
+                .collect()
+        } else if let Some(alu_constant) = alu_constants.iter().find(|&&alu_const| !alu_const == word) {
+            load_and_increment_address()
+                .chain(vec![
+                    ASMInstruction::C {
+                        expr: alu_constant.to_string(),
                         dest: Some("M".to_string()),
                         jump: None,
-                    }])
-                    .collect()
-                } else {
-                    vec![
-                        ASMInstruction::A(AValue::Numeric((word).to_string())),
-                        ASMInstruction::C {
-                            expr: "A".to_string(),
-                            dest: Some("D".to_string()),
-                            jump: None,
-                        },
-                    ]
-                    .into_iter()
-                    .chain(load_and_increment_address())
-                    .chain(vec![ASMInstruction::C {
-                        expr: "D".to_string(),
+                    },
+                    ASMInstruction::C {
+                        expr: "!M".to_string(),
                         dest: Some("M".to_string()),
                         jump: None,
-                    }])
-                    .collect()
-                };
-                instructions
-            })
-        });
+                    },
+                ])
+                .collect()
+        } else if let Some(alu_constant) = alu_constants.iter().find(|&&alu_const| -alu_const == word) {
+            load_and_increment_address()
+                .chain(vec![
+                    ASMInstruction::C {
+                        expr: alu_constant.to_string(),
+                        dest: Some("M".to_string()),
+                        jump: None,
+                    },
+                    ASMInstruction::C {
+                        expr: "-M".to_string(),
+                        dest: Some("M".to_string()),
+                        jump: None,
+                    },
+                ])
+                .collect()
+        } else if word < 0 {
+            vec![
+                ASMInstruction::A(AValue::Numeric((!word).to_string())),
+                ASMInstruction::C {
+                    expr: "!A".to_string(),
+                    dest: Some("D".to_string()),
+                    jump: None,
+                },
+            ]
+            .into_iter()
+            .chain(load_and_increment_address())
+            .chain(vec![ASMInstruction::C {
+                expr: "D".to_string(),
+                dest: Some("M".to_string()),
+                jump: None,
+            }])
+            .collect()
+        } else {
+            vec![
+                ASMInstruction::A(AValue::Numeric((word).to_string())),
+                ASMInstruction::C {
+                    expr: "A".to_string(),
+                    dest: Some("D".to_string()),
+                    jump: None,
+                },
+            ]
+            .into_iter()
+            .chain(load_and_increment_address())
+            .chain(vec![ASMInstruction::C {
+                expr: "D".to_string(),
+                dest: Some("M".to_string()),
+                jump: None,
+            }])
+            .collect()
+        };
+        instructions
+    });
 
     let prelude = vec![
         ASMInstruction::A(AValue::Symbolic("GLYPHS".to_string())),
@@ -280,3 +293,24 @@ pub fn glyphs_asm() -> Vec<ASMInstruction> {
     ];
     prelude.into_iter().chain(glyph_loading_asm).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use emulator_core::computer::{tick_until, Computer};
+
+    use super::*;
+    use crate::{assembler::assemble, config::ROM_DEPTH};
+
+    #[test]
+    fn test_glyphs_asm_matches_ram_image() {
+        let asm = glyphs_asm();
+        let instruction_count = asm.len() as u16;
+        let rom = assemble(&asm, ROM_DEPTH).instructions;
+        let mut computer = Computer::new(rom.try_into().unwrap());
+        tick_until(&mut computer, &|computer| computer.cpu.pc == instruction_count);
+
+        let ram_image = glyphs_ram_image();
+        let words = &ram_image.segments[0].words;
+        assert_eq!(computer.ram.lock()[GLYPHS..GLYPHS + words.len()], words[..]);
+    }
+}
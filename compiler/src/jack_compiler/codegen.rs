@@ -29,9 +29,23 @@ struct Symbol {
     kind: SymbolKind,
 }
 
+// How +, -, *, / and negation are compiled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    // Straight to the vm's arithmetic commands and Math, which wrap around on
+    // overflow.
+    #[default]
+    Wrapping,
+    // Through the checked functions in Math, which call Sys.arithmeticError
+    // on overflow or division by zero. Only for user code, since the std_lib
+    // relies on wrapping around, and Math can't check itself.
+    Checked,
+}
+
 #[derive(Default)]
 pub struct CodeGenerator {
     pub class_name: Option<String>,
+    arithmetic: Arithmetic,
     class_fields: HashMap<String, Symbol>,
     class_statics: HashMap<String, Symbol>,
     sourcemap: JackCodegenSourceMap,
@@ -42,6 +56,20 @@ pub struct CodeGenerator {
     subroutine_kind: Option<SubroutineKind>,
 }
 
+// The symbols in a table, in the order they're laid out in memory.
+fn variables(symbols: &HashMap<String, Symbol>) -> Vec<Variable> {
+    let mut variables: Vec<_> = symbols
+        .iter()
+        .map(|(name, symbol)| Variable {
+            name: name.clone(),
+            var_type: symbol.symbol_type.clone(),
+            index: symbol.offset,
+        })
+        .collect();
+    variables.sort_by_key(|variable| variable.index);
+    variables
+}
+
 pub fn full_subroutine_name(class_name: &str, subroutine_name: &str) -> String {
     format!("{}.{}", class_name, subroutine_name)
 }
@@ -290,10 +318,22 @@ impl CodeGenerator {
                 Command::Arithmetic(ArithmeticCommandVariant::Binary(BinaryArithmeticCommandVariant::Gt)),
                 Command::Arithmetic(ArithmeticCommandVariant::Unary(UnaryArithmeticCommandVariant::Not)),
             ],
+            BinaryOperator::Minus if self.arithmetic == Arithmetic::Checked => {
+                vec![Command::Function(FunctionCommandVariant::Call("Math.checkedSubtract".to_string(), 2))]
+            }
             BinaryOperator::Minus => vec![Command::Arithmetic(ArithmeticCommandVariant::Binary(BinaryArithmeticCommandVariant::Sub))],
             BinaryOperator::Or => vec![Command::Arithmetic(ArithmeticCommandVariant::Binary(BinaryArithmeticCommandVariant::Or))],
+            BinaryOperator::Plus if self.arithmetic == Arithmetic::Checked => {
+                vec![Command::Function(FunctionCommandVariant::Call("Math.checkedAdd".to_string(), 2))]
+            }
             BinaryOperator::Plus => vec![Command::Arithmetic(ArithmeticCommandVariant::Binary(BinaryArithmeticCommandVariant::Add))],
+            BinaryOperator::Multiply if self.arithmetic == Arithmetic::Checked => {
+                vec![Command::Function(FunctionCommandVariant::Call("Math.checkedMultiply".to_string(), 2))]
+            }
             BinaryOperator::Multiply => vec![Command::Function(FunctionCommandVariant::Call("Math.multiply".to_string(), 2))],
+            BinaryOperator::Divide if self.arithmetic == Arithmetic::Checked => {
+                vec![Command::Function(FunctionCommandVariant::Call("Math.checkedDivide".to_string(), 2))]
+            }
             BinaryOperator::Divide => vec![Command::Function(FunctionCommandVariant::Call("Math.divide".to_string(), 2))],
         };
 
@@ -443,7 +483,7 @@ impl CodeGenerator {
     fn compile_direct_subroutine_call_expression(
         &mut self,
         subroutine_name: &str,
-        arguments: &Vec<ASTNode<Expression>>,
+        arguments: &[ASTNode<Expression>],
         subroutine_call_node_idx: usize,
     ) -> Vec<SourcemappedCommand> {
         let arg_count = arguments.len();
@@ -479,6 +519,9 @@ impl CodeGenerator {
         unary_expression_node_idx: usize,
     ) -> Vec<SourcemappedCommand> {
         let perform_op = match operator {
+            UnaryOperator::Minus if self.arithmetic == Arithmetic::Checked => {
+                Command::Function(FunctionCommandVariant::Call("Math.checkedNegate".to_string(), 1))
+            }
             UnaryOperator::Minus => Command::Arithmetic(ArithmeticCommandVariant::Unary(UnaryArithmeticCommandVariant::Neg)),
             UnaryOperator::Not => Command::Arithmetic(ArithmeticCommandVariant::Unary(UnaryArithmeticCommandVariant::Not)),
         };
@@ -587,6 +630,9 @@ impl CodeGenerator {
     }
 
     fn compile_statement(&mut self, statement: &ASTNode<Statement>) -> Vec<SourcemappedCommand> {
+        if !matches!(*statement.node, Statement::Block(_)) {
+            self.sourcemap.record_statement(statement.node_idx);
+        }
         match &*statement.node {
             Statement::Block(statements) => self.compile_statements(statements),
             Statement::Do(subroutine_call) => self.compile_do_statement(subroutine_call),
@@ -601,7 +647,7 @@ impl CodeGenerator {
                 else_statement,
             } => self.compile_if_statement(condition, if_statement, else_statement, statement.node_idx),
             Statement::Return(expression) => self.compile_return_statement(expression, statement.node_idx),
-            Statement::While { condition, statement } => self.compile_while_statement(condition, statement, statement.node_idx),
+            Statement::While { condition, statement: body } => self.compile_while_statement(condition, body, statement.node_idx),
         }
     }
 
@@ -643,6 +689,23 @@ impl CodeGenerator {
             .collect()
     }
 
+    // The arguments of the current subroutine, in order. For methods, the
+    // first argument is the object.
+    fn subroutine_arguments(&self) -> Vec<Variable> {
+        let mut arguments = variables(&self.subroutine_parameters);
+        if self.subroutine_kind == Some(SubroutineKind::Method) {
+            arguments.insert(
+                0,
+                Variable {
+                    name: "this".to_string(),
+                    var_type: Type::ClassName(self.get_class_name().to_string()),
+                    index: 0,
+                },
+            );
+        }
+        arguments
+    }
+
     fn compile_subroutine(&mut self, subroutine_declaration: &ASTNode<SubroutineDeclaration>, instance_size: usize) -> CompiledSubroutine {
         let subroutine = &subroutine_declaration.node;
         self.clear_subroutine();
@@ -696,6 +759,8 @@ impl CodeGenerator {
         } else {
             subroutine.parameters.len()
         };
+        let arguments = self.subroutine_arguments();
+        let locals = variables(&self.subroutine_vars);
 
         if let Some(ast_node) = subroutine.body.node.statements.last() {
             if matches!(*ast_node.node, Statement::Return(_)) {
@@ -704,6 +769,8 @@ impl CodeGenerator {
                     commands,
                     locals_count,
                     arg_count,
+                    arguments,
+                    locals,
                 };
             }
         }
@@ -713,6 +780,8 @@ impl CodeGenerator {
             commands,
             locals_count,
             arg_count,
+            arguments,
+            locals,
         }
     }
 
@@ -762,6 +831,41 @@ pub struct CompiledSubroutine {
     pub commands: Vec<SourcemappedCommand>,
     pub locals_count: usize,
     pub arg_count: usize,
+    pub arguments: Vec<Variable>,
+    pub locals: Vec<Variable>,
+}
+
+// A variable, and where it is in its segment.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+#[ts(export_to = "../web/bindings/")]
+pub struct Variable {
+    pub name: String,
+    pub var_type: Type,
+    pub index: usize,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+#[ts(export_to = "../web/bindings/")]
+pub struct StaticVariable {
+    pub name: String,
+    pub var_type: Type,
+    pub index: usize,
+    // Statics are only given an address by the assembler, and only if they're
+    // used.
+    pub address: Option<u16>,
+}
+
+// Where the fields of an object are, relative to the start of the object, and
+// where the statics of a class are.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+#[ts(export_to = "../web/bindings/")]
+pub struct ClassLayout {
+    pub name: String,
+    pub fields: Vec<Variable>,
+    pub statics: Vec<StaticVariable>,
 }
 
 #[derive(Clone, Debug, Serialize, TS)]
@@ -776,11 +880,13 @@ pub struct SourcemappedCommand {
 pub struct JackCodegenResult {
     pub subroutines: Vec<CompiledSubroutine>,
     pub sourcemap: JackCodegenSourceMap,
+    pub class_layout: ClassLayout,
 }
 
-pub fn generate_vm_code(class: Class) -> JackCodegenResult {
+pub fn generate_vm_code(class: Class, arithmetic: Arithmetic) -> JackCodegenResult {
     let mut code_generator = CodeGenerator {
         class_name: Some(class.name.clone()),
+        arithmetic,
         ..Default::default()
     };
     let class_instance_size = code_generator.compile_var_declarations(&class.var_declarations);
@@ -793,8 +899,23 @@ pub fn generate_vm_code(class: Class) -> JackCodegenResult {
         }
     }
 
+    let class_layout = ClassLayout {
+        name: class.name,
+        fields: variables(&code_generator.class_fields),
+        statics: variables(&code_generator.class_statics)
+            .into_iter()
+            .map(|Variable { name, var_type, index }| StaticVariable {
+                name,
+                var_type,
+                index,
+                address: None,
+            })
+            .collect(),
+    };
+
     JackCodegenResult {
         subroutines: compiled_subroutines,
         sourcemap: code_generator.sourcemap,
+        class_layout,
     }
 }
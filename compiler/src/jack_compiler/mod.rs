@@ -1,12 +1,12 @@
 use serde::Serialize;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
 };
 use ts_rs::TS;
 
 use self::{
-    codegen::{generate_vm_code, CompiledSubroutine},
+    codegen::{generate_vm_code, Arithmetic, ClassLayout, CompiledSubroutine},
     parser::{parse, JackParserResult},
     sourcemap::JackCompilerSourceMap,
     tokenizer::{token_defs, TokenKind},
@@ -15,6 +15,7 @@ use super::utils::{
     source_modules::{get_source_modules, SourceModule},
     tokenizer::{Token, Tokenizer},
 };
+use super::vm_compiler::codegen::static_symbol;
 
 pub mod codegen;
 pub mod jack_node_types;
@@ -30,6 +31,19 @@ pub struct JackCompilerResult {
     pub tokens: HashMap<PathBuf, Vec<Token<TokenKind>>>,
     #[ts(type = "Record<string, Array<CompiledSubroutine>>")]
     pub subroutines: HashMap<PathBuf, Vec<CompiledSubroutine>>,
+    #[ts(type = "Record<string, ClassLayout>")]
+    pub class_layouts: HashMap<PathBuf, ClassLayout>,
+}
+
+impl JackCompilerResult {
+    // Fills in the addresses which the assembler gave to statics.
+    pub fn resolve_static_addresses(&mut self, static_addresses: &HashMap<String, u16>) {
+        for (filename, class_layout) in self.class_layouts.iter_mut() {
+            for static_variable in class_layout.statics.iter_mut() {
+                static_variable.address = static_addresses.get(&static_symbol(filename, static_variable.index)).copied();
+            }
+        }
+    }
 }
 
 fn get_all_source_modules(user_code: HashMap<PathBuf, SourceModule>) -> HashMap<PathBuf, SourceModule> {
@@ -55,13 +69,19 @@ fn parse_jack_program(jack_program_tokens: &HashMap<PathBuf, Vec<Token<TokenKind
         .collect()
 }
 
-pub fn compile_jack(user_code: HashMap<PathBuf, SourceModule>) -> JackCompilerResult {
+pub fn compile_jack(user_code: HashMap<PathBuf, SourceModule>, arithmetic: Arithmetic) -> JackCompilerResult {
     let mut result = JackCompilerResult::default();
+    let user_filenames: HashSet<_> = user_code.keys().cloned().collect();
     let jack_program_tokens = tokenize_jack_program(get_all_source_modules(user_code));
     let parsed_jack_program = parse_jack_program(&jack_program_tokens);
     result.tokens = jack_program_tokens;
     for (filename, parse_result) in parsed_jack_program {
-        let codegen_result = generate_vm_code(parse_result.class);
+        let class_arithmetic = if user_filenames.contains(&filename) {
+            arithmetic
+        } else {
+            Arithmetic::Wrapping
+        };
+        let codegen_result = generate_vm_code(parse_result.class, class_arithmetic);
         result.sourcemaps.insert(
             filename.clone(),
             JackCompilerSourceMap {
@@ -70,7 +90,8 @@ pub fn compile_jack(user_code: HashMap<PathBuf, SourceModule>) -> JackCompilerRe
             },
         );
 
-        result.subroutines.insert(filename, codegen_result.subroutines);
+        result.subroutines.insert(filename.clone(), codegen_result.subroutines);
+        result.class_layouts.insert(filename, codegen_result.class_layout);
     }
 
     result
@@ -86,7 +107,7 @@ mod tests {
     };
 
     use crate::utils::{source_modules::SourceModule, testing::test_utils::*};
-    use emulator_core::computer::tick_until;
+    use emulator_core::{computer::tick_until, memory_layout::BANK_WINDOW};
     use itertools::Itertools;
 
     fn mock_source_directory(sources: Vec<(&str, &str)>) -> HashMap<PathBuf, SourceModule> {
@@ -163,7 +184,7 @@ mod tests {
         tick_until(&mut computer, &program_completed);
         for outer_idx in 0..14 {
             let start = outer_idx * 1000;
-            let nums: Vec<_> = (start..start + 1000).into_iter().collect();
+            let nums: Vec<_> = (start..start + 1000).collect();
             assert!(heap_includes(&computer, &nums));
         }
     }
@@ -379,6 +400,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_bank_alloc() {
+        let mut computer = computer_from_jack_code(mock_source_directory(vec![(
+            "Sys.jack",
+            "
+            class Sys {
+                function void init () {
+                    var int a, b, c;
+
+                    do Memory.selectBank(1);
+                    let a = Memory.bankAlloc(3);
+                    let a[0] = 11;
+                    let b = Memory.bankAlloc(2045);
+
+                    do Memory.selectBank(2);
+                    let c = Memory.bankAlloc(3);
+                    let c[2] = 22;
+                    do Memory.bankReset();
+                    let c = Memory.bankAlloc(2047);
+                    let c[2046] = 33;
+
+                    do Memory.selectBank(1);
+                    let a[1] = Memory.bank();
+                    let a[2] = b;
+                }
+            }
+            ",
+        )]));
+        tick_until(&mut computer, &program_completed);
+
+        let ram = computer.ram.lock();
+        // The second allocation doesn't fit in bank 1, as the first word is
+        // used by the allocator itself.
+        assert_eq!(ram[BANK_WINDOW..BANK_WINDOW + 4], [4, 11, 1, 0]);
+        let bank_2 = computer.banks().contents(&ram, 2);
+        assert_eq!(bank_2[0], 2048);
+        assert_eq!(bank_2[3], 22);
+        assert_eq!(bank_2[2047], 33);
+    }
+
     #[test]
     fn snapshot_tests() {
         let snapshots_dir_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshot_tests");
@@ -422,7 +483,7 @@ mod tests {
             let mut computer = computer_from_jack_code(mock_source_directory(jack_source_refs));
             tick_until(&mut computer, &program_completed);
             let screen_bytes = computer.screen_snapshot();
-            if let Some(image_file) = image_files.get(0) {
+            if let Some(image_file) = image_files.first() {
                 let expected_bytes = fs::read(image_file.path()).unwrap_or_else(|_| panic!("failed to read pbm snapshot"));
                 assert_eq!(screen_bytes, expected_bytes, "assertion failed for {}", snapshot_path.display());
             } else {
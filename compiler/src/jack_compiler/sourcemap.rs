@@ -49,6 +49,9 @@ pub struct NodeInfo {
 pub struct JackCodegenSourceMap {
     pub jack_node_idx_to_vm_command_idx: HashMap<usize, Vec<usize>>,
     pub vm_command_idx_to_jack_node_idx: HashMap<usize, usize>,
+    // The jack nodes which are statements (apart from blocks), for stepping
+    // through code a statement at a time.
+    pub statement_node_idxs: Vec<usize>,
 }
 
 impl JackCodegenSourceMap {
@@ -58,6 +61,10 @@ impl JackCodegenSourceMap {
 
         self.vm_command_idx_to_jack_node_idx.insert(vm_command_idx, jack_node_idx);
     }
+
+    pub fn record_statement(&mut self, statement_node_idx: usize) {
+        self.statement_node_idxs.push(statement_node_idx);
+    }
 }
 
 #[derive(Serialize, TS)]
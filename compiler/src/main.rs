@@ -2,26 +2,33 @@ mod assembler;
 mod config;
 mod fonts;
 mod jack_compiler;
+mod test_runner;
 mod utils;
 mod vm_compiler;
 
 use assembler::codegen::AssemblyResult;
 use clap::{Parser, Subcommand};
 use config::ROM_DEPTH;
+use emulator_core::{
+    image_format::{self, ImageFormat},
+    ram_image::RamImage,
+};
 use jack_compiler::JackCompilerResult;
 use serde::Serialize;
 use std::{
     collections::HashMap,
     fs,
     path::{Path, PathBuf},
+    process,
 };
+use test_runner::{Outcome, TestRunner};
 use ts_rs::TS;
 use utils::source_modules::{get_source_modules, SourceModule};
-use vm_compiler::codegen::VMCompilerResult;
+use vm_compiler::codegen::{PointerSaving, RamInit, VMCompilerResult};
 use {
     assembler::{assemble, assemble_file},
-    jack_compiler::{compile_jack, jack_node_types::Class},
-    vm_compiler::codegen::generate_asm,
+    jack_compiler::{codegen::Arithmetic, compile_jack, jack_node_types::Class},
+    vm_compiler::{call_graph_export::export_call_graph, codegen::generate_asm, stack_depth::analyse_stack_depth},
 };
 
 #[derive(Default, Serialize, TS)]
@@ -33,16 +40,35 @@ struct CompilerResult {
     pub assembly_result: AssemblyResult,
 }
 
+fn debug_compile(
+    user_code: HashMap<PathBuf, SourceModule>,
+    ram_init: RamInit,
+    pointer_saving: PointerSaving,
+    arithmetic: Arithmetic,
+) -> CompilerResult {
+    let mut jack_compiler_result = compile_jack(user_code, arithmetic);
+    let vm_compiler_result = generate_asm(&jack_compiler_result.subroutines, ram_init, pointer_saving);
+    let assembly_result = assemble(&vm_compiler_result.instructions, ROM_DEPTH);
+    jack_compiler_result.resolve_static_addresses(&assembly_result.static_addresses);
+    CompilerResult {
+        jack_compiler_result,
+        vm_compiler_result,
+        assembly_result,
+    }
+}
+
 // TODO - move into test module
-pub fn compile_to_machine_code(jack_code: HashMap<PathBuf, SourceModule>) -> Vec<u16> {
-    let jack_compiler_results = compile_jack(jack_code);
-    let vm_compiler_result = vm_compiler::codegen::generate_asm(&jack_compiler_results.subroutines);
-    assemble(&vm_compiler_result.instructions, config::ROM_DEPTH).instructions
+pub fn compile_to_machine_code(jack_code: HashMap<PathBuf, SourceModule>) -> (Vec<u16>, RamImage) {
+    let jack_compiler_results = compile_jack(jack_code, Arithmetic::Wrapping);
+    let vm_compiler_result = vm_compiler::codegen::generate_asm(&jack_compiler_results.subroutines, RamInit::Image, PointerSaving::Analysed);
+    let instructions = assemble(&vm_compiler_result.instructions, config::ROM_DEPTH).instructions;
+    (instructions, vm_compiler_result.ram_image)
 }
 
 #[derive(Serialize, TS)]
 #[ts(export)]
 #[ts(export_to = "../web/bindings/")]
+#[allow(dead_code)] // only used for the typescript bindings
 struct ParserVizData {
     source: String,
     parsed_class: Class,
@@ -62,12 +88,105 @@ enum Commands {
         source_dir_path: Option<String>,
         dest_path: Option<String>,
         debug_output_path: Option<String>,
+        /// Where to write the initial contents of RAM, like the glyphs
+        #[clap(long)]
+        ram_image_path: Option<String>,
+        /// Write initial RAM contents with instructions at startup instead of
+        /// using a RAM image, for hardware that can't load one
+        #[clap(long)]
+        init_ram_with_instructions: bool,
+        /// Save and restore all of the caller's pointers on every call,
+        /// rather than only the ones that the call graph analysis says are
+        /// needed
+        #[clap(long)]
+        save_all_pointers: bool,
+        /// Check for overflow in +, -, * and negation, and for division by
+        /// zero, in the program's own classes. Violations call
+        /// Sys.arithmeticError, which the debugger stops at
+        #[clap(long)]
+        checked_arithmetic: bool,
+        /// Format of the machine code and ram image, one of bin-be, bin-le,
+        /// ihex, memb, memh or coe
+        #[clap(long, default_value = "memb")]
+        format: ImageFormat,
+    },
+    /// Export the call graph analysis as Graphviz DOT and/or JSON, showing
+    /// which subroutines are dead and which pointers each one saves
+    CallGraph {
+        source_dir_path: String,
+        #[clap(long)]
+        dot: Option<String>,
+        #[clap(long)]
+        json: Option<String>,
+    },
+    /// Work out the most stack that each subroutine can need, including
+    /// what it calls, and whether the program can run out of stack
+    StackDepth {
+        source_dir_path: String,
+        /// Save and restore all of the caller's pointers on every call, as
+        /// debug-compile --save-all-pointers does
+        #[clap(long)]
+        save_all_pointers: bool,
+    },
+    /// Run the tests in the *Test.jack classes of a program, which are the
+    /// void functions with no arguments whose names start with test_, each
+    /// on a fresh computer
+    Test {
+        source_dir_path: String,
+        /// Check arithmetic in the program's own classes and the tests, as
+        /// debug-compile --checked-arithmetic does
+        #[clap(long)]
+        checked_arithmetic: bool,
+        /// Fail tests which run for more than this many instructions
+        #[clap(long, default_value = "10000000")]
+        max_ticks: u64,
+        /// Write the coverage of all the tests together as an lcov tracefile
+        #[clap(long)]
+        lcov: Option<String>,
     },
     /// Compile assembly to machine code
     Assemble {
         source_path: Option<String>,
         dest_path: Option<String>,
+        /// Format of the machine code, one of bin-be, bin-le, ihex, memb, memh
+        /// or coe
+        #[clap(long, default_value = "memb")]
+        format: ImageFormat,
     },
+    /// Convert a ROM or RAM image from one format to another
+    ConvertImage {
+        source_path: String,
+        dest_path: String,
+        /// Format of the source image. Detected from the file if not given
+        #[clap(long)]
+        from: Option<ImageFormat>,
+        #[clap(long, default_value = "memb")]
+        to: ImageFormat,
+        /// Pad the image with zeros up to this many words
+        #[clap(long)]
+        depth: Option<usize>,
+    },
+}
+
+fn pointer_saving(save_all_pointers: bool) -> PointerSaving {
+    if save_all_pointers {
+        PointerSaving::All
+    } else {
+        PointerSaving::Analysed
+    }
+}
+
+fn arithmetic(checked_arithmetic: bool) -> Arithmetic {
+    if checked_arithmetic {
+        Arithmetic::Checked
+    } else {
+        Arithmetic::Wrapping
+    }
+}
+
+fn write_image(path: &str, image: &RamImage, format: ImageFormat) {
+    let bytes = image_format::encode(image, format).unwrap_or_else(|err| panic!("failed to encode {}: {}", path, err));
+    fs::write(path, bytes).unwrap_or_else(|err| panic!("failed to write {}: {}", path, err));
 }
 
 fn main() {
@@ -78,36 +197,133 @@ fn main() {
             source_dir_path,
             dest_path: dest_path_maybe,
             debug_output_path: debug_output_path_maybe,
+            ram_image_path,
+            init_ram_with_instructions,
+            save_all_pointers,
+            checked_arithmetic,
+            format,
         } => {
             let debug_output_path = debug_output_path_maybe.as_ref().expect("debug output path is required");
             let dest_path = dest_path_maybe.as_ref().expect("dest path is required");
             let user_code = get_source_modules(Path::new(source_dir_path.as_ref().unwrap())).unwrap();
-            let jack_compiler_result = compile_jack(user_code);
-            let vm_compiler_result = generate_asm(&jack_compiler_result.subroutines);
-            let assembly_result = assemble(&vm_compiler_result.instructions, ROM_DEPTH);
-            let compiler_result = CompilerResult {
-                jack_compiler_result,
-                vm_compiler_result,
-                assembly_result,
+            let user_filenames = user_code.keys().cloned().collect();
+            let ram_init = if *init_ram_with_instructions {
+                RamInit::Instructions
+            } else {
+                RamInit::Image
             };
+            let compiler_result = debug_compile(user_code, ram_init, pointer_saving(*save_all_pointers), arithmetic(*checked_arithmetic));
+            let stack_depth = analyse_stack_depth(&compiler_result.jack_compiler_result.subroutines, pointer_saving(*save_all_pointers));
+            for warning in stack_depth.warnings(&user_filenames) {
+                eprintln!("warning: {}", warning);
+            }
+            if let Some(error) = stack_depth.error() {
+                eprintln!("error: {}", error);
+                process::exit(1);
+            }
+            let ram_image = &compiler_result.vm_compiler_result.ram_image;
+            if let Some(ram_image_path) = ram_image_path {
+                write_image(ram_image_path, ram_image, *format);
+            } else if !ram_image.segments.is_empty() {
+                eprintln!("warning: the program needs a ram image, but no ram image path was given");
+            }
             let json = serde_json::to_string_pretty(&compiler_result).expect("failed to serialize jack compiler result");
             fs::write(debug_output_path, json).expect("failed to write result to debug output path");
-            let machine_code_strings: Vec<_> = compiler_result
-                .assembly_result
-                .instructions
-                .into_iter()
-                .map(|instruction| format!("{:016b}", instruction))
-                .collect();
-            fs::write(dest_path, machine_code_strings.join("\n")).expect("failed to write result to dest path");
+            let mut machine_code = RamImage::default();
+            machine_code.add_segment(0, compiler_result.assembly_result.instructions);
+            write_image(dest_path, &machine_code, *format);
+        }
+        Commands::CallGraph { source_dir_path, dot, json } => {
+            if dot.is_none() && json.is_none() {
+                panic!("at least one of --dot and --json is required");
+            }
+            let user_code = get_source_modules(Path::new(source_dir_path)).unwrap();
+            let export = export_call_graph(&compile_jack(user_code, Arithmetic::Wrapping).subroutines);
+            if let Some(dot_path) = dot {
+                fs::write(dot_path, export.to_dot()).expect("failed to write dot output");
+            }
+            if let Some(json_path) = json {
+                let json = serde_json::to_string_pretty(&export).expect("failed to serialize call graph");
+                fs::write(json_path, json).expect("failed to write json output");
+            }
+        }
+        Commands::StackDepth {
+            source_dir_path,
+            save_all_pointers,
+        } => {
+            let user_code = get_source_modules(Path::new(source_dir_path)).unwrap();
+            let user_filenames = user_code.keys().cloned().collect();
+            let report = analyse_stack_depth(
+                &compile_jack(user_code, Arithmetic::Wrapping).subroutines,
+                pointer_saving(*save_all_pointers),
+            );
+            println!("{}", report.format());
+            for warning in report.warnings(&user_filenames) {
+                eprintln!("warning: {}", warning);
+            }
+            if let Some(error) = report.error() {
+                eprintln!("error: {}", error);
+                process::exit(1);
+            }
+        }
+        Commands::Test {
+            source_dir_path,
+            checked_arithmetic,
+            max_ticks,
+            lcov,
+        } => {
+            let user_code = get_source_modules(Path::new(source_dir_path)).unwrap();
+            let mut runner = TestRunner::new(user_code, arithmetic(*checked_arithmetic));
+            if lcov.is_some() {
+                runner.record_coverage();
+            }
+            let mut failed = 0;
+            for idx in 0..runner.tests.len() {
+                let name = runner.tests[idx].to_string();
+                match runner.run(idx, *max_ticks) {
+                    Outcome::Passed => println!("{} ... ok", name),
+                    Outcome::Failed(problem) => {
+                        println!("{} ... FAILED: {}", name, problem);
+                        failed += 1;
+                    }
+                }
+            }
+            println!("{} passed, {} failed", runner.tests.len() - failed, failed);
+            if let (Some(lcov_path), Some(coverage)) = (lcov, runner.coverage()) {
+                fs::write(lcov_path, coverage.to_lcov()).expect("failed to write lcov output");
+            }
+            if failed > 0 {
+                process::exit(1);
+            }
         }
         Commands::Assemble {
             source_path: source_path_maybe,
             dest_path: dest_path_maybe,
+            format,
         } => {
             let source_path = source_path_maybe.as_ref().expect("source path is required");
             let dest_path = dest_path_maybe.as_ref().expect("dest path is required");
             println!("assembling {} to {}", source_path, dest_path);
-            assemble_file(Path::new(source_path), Path::new(dest_path), config::ROM_DEPTH);
+            let instructions = assemble_file(Path::new(source_path), config::ROM_DEPTH);
+            let mut machine_code = RamImage::default();
+            machine_code.add_segment(0, instructions);
+            write_image(dest_path, &machine_code, *format);
+        }
+        Commands::ConvertImage {
+            source_path,
+            dest_path,
+            from,
+            to,
+            depth,
+        } => {
+            let mut image = image_format::read(Path::new(source_path), *from).unwrap_or_else(|err| panic!("{}", err));
+            if let Some(depth) = depth {
+                image = image_format::padded(&image, *depth);
+            }
+            write_image(dest_path, &image, *to);
         }
     }
 }
+
+#[cfg(test)]
+mod tests;
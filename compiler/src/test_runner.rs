@@ -0,0 +1,196 @@
+// Runs tests written in jack. Tests are the void functions of `*Test.jack`
+// classes whose names start with `test_` and which take no arguments. They're
+// compiled along with the rest of the program and a Sys.init which, instead of
+// Main.main, runs the test that the runner selects through the test port, then
+// reports a pass. Each test runs on a freshly switched on computer, so tests
+// can't affect each other. Assertions are in std_lib/Test.jack, and stop the
+// test as soon as one fails, reporting back through the test port too.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use emulator_core::{
+    coverage::CoverageReport,
+    debug_info::DebugInfo,
+    debugger::{Debugger, RunMode, StopReason},
+    memory_layout::TEST_PORT,
+};
+
+use crate::{
+    debug_compile,
+    jack_compiler::{codegen::Arithmetic, jack_node_types::SubroutineKind, parser::parse, tokenizer::token_defs},
+    utils::{source_modules::SourceModule, tokenizer::Tokenizer},
+    vm_compiler::codegen::{PointerSaving, RamInit},
+};
+
+// How often to look at the test port.
+const TICKS_PER_CHECK: u64 = 10_000;
+
+// What Test.report writes to the test port.
+const PASSED: u16 = 1;
+const FAILED: u16 = 2;
+const NOT_EQUAL: u16 = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JackTest {
+    pub class: String,
+    pub function: String,
+}
+
+impl fmt::Display for JackTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.class, self.function)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+}
+
+// The tests in the `*Test.jack` files, by class name and then in the order
+// they're written.
+pub fn find_tests(user_code: &HashMap<PathBuf, SourceModule>) -> Vec<JackTest> {
+    let mut test_files: Vec<_> = user_code
+        .iter()
+        .filter(|(filename, _)| filename.to_string_lossy().ends_with("Test.jack"))
+        .collect();
+    test_files.sort_by_key(|(filename, _)| *filename);
+    test_files
+        .into_iter()
+        .flat_map(|(_, source_module)| {
+            let tokens = Tokenizer::new(token_defs()).tokenize(&source_module.source);
+            let class = parse(&tokens).class;
+            class
+                .subroutine_declarations
+                .iter()
+                .filter(|subroutine| {
+                    subroutine.node.subroutine_kind == SubroutineKind::Function
+                        && subroutine.node.name.starts_with("test_")
+                        && subroutine.node.return_type.is_none()
+                        && subroutine.node.parameters.is_empty()
+                })
+                .map(|subroutine| JackTest {
+                    class: class.name.clone(),
+                    function: subroutine.node.name.clone(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// Replaces the std_lib's Sys.jack with a copy whose Sys.init sets everything
+// up as usual, then runs the selected test instead of Main.main.
+fn harness(tests: &[JackTest]) -> SourceModule {
+    let std_lib_sys = Path::new(env!("CARGO_MANIFEST_DIR")).join("../std_lib/Sys.jack");
+    let source = fs::read_to_string(std_lib_sys).expect("failed to read std_lib Sys.jack");
+    let calls: String = tests
+        .iter()
+        .enumerate()
+        .map(|(idx, test)| format!("    if (Test.selected() = {}) {{\n      do {}();\n    }}\n", idx, test))
+        .collect();
+    let main_call = "    do Main.main();\n";
+    assert!(source.contains(main_call), "std_lib Sys.init doesn't call Main.main");
+    SourceModule {
+        filename: "Sys.jack".into(),
+        source: source.replacen(main_call, &format!("{}    do Test.pass();\n", calls), 1),
+    }
+}
+
+pub struct TestRunner {
+    pub debug_info: DebugInfo,
+    pub tests: Vec<JackTest>,
+    coverage: Option<CoverageReport>,
+}
+
+impl TestRunner {
+    // Compiles the program with a harness for the tests in it. Arithmetic
+    // applies to the program's own classes, tests included.
+    pub fn new(mut user_code: HashMap<PathBuf, SourceModule>, arithmetic: Arithmetic) -> Self {
+        let tests = find_tests(&user_code);
+        user_code.insert("Sys.jack".into(), harness(&tests));
+        let compiler_result = debug_compile(user_code, RamInit::Image, PointerSaving::Analysed, arithmetic);
+        let json = serde_json::to_string(&compiler_result).expect("failed to serialize compiler result");
+        let debug_info = DebugInfo::from_json(&json).expect("failed to load debug info");
+        Self {
+            debug_info,
+            tests,
+            coverage: None,
+        }
+    }
+
+    // Adds up the coverage of every test that's run from now on.
+    pub fn record_coverage(&mut self) {
+        self.coverage = Some(CoverageReport::default());
+    }
+
+    pub fn coverage(&self) -> Option<&CoverageReport> {
+        self.coverage.as_ref()
+    }
+
+    // Runs the test at `idx` in `tests`, failing it if it hasn't finished
+    // after `max_ticks` instructions.
+    pub fn run(&mut self, idx: usize, max_ticks: u64) -> Outcome {
+        let mut debugger = Debugger::new(self.debug_info.clone());
+        if self.coverage.is_some() {
+            debugger.record_coverage();
+        }
+        debugger.computer.ram.lock()[TEST_PORT] = idx as u16;
+        let outcome = run_test(&mut debugger, max_ticks);
+        if let (Some(coverage), Some(recorded)) = (&mut self.coverage, debugger.coverage()) {
+            coverage.merge(&recorded.report(&self.debug_info));
+        }
+        outcome
+    }
+}
+
+fn run_test(debugger: &mut Debugger, max_ticks: u64) -> Outcome {
+    let mut ticks = 0;
+    while ticks < max_ticks {
+        let chunk = TICKS_PER_CHECK.min(max_ticks - ticks);
+        let stop_reason = debugger.run(RunMode::Continue, chunk as usize);
+        ticks += chunk;
+        if let Some(outcome) = reported_outcome(debugger) {
+            return outcome;
+        }
+        match stop_reason {
+            Some(StopReason::Fault(fault)) => return Outcome::Failed(format!("computer faulted: {}", fault)),
+            Some(StopReason::ArithmeticError(error)) => return Outcome::Failed(error),
+            Some(StopReason::Exited) => return Outcome::Failed("Sys.init returned without a result".to_string()),
+            _ => {}
+        }
+    }
+    Outcome::Failed(format!("didn't finish within {} instructions", max_ticks))
+}
+
+fn reported_outcome(debugger: &Debugger) -> Option<Outcome> {
+    let [result, actual, expected] = {
+        let ram = debugger.computer.ram.lock();
+        [1, 2, 3].map(|offset| ram[TEST_PORT + offset])
+    };
+    let problem = match result {
+        0 => return None,
+        PASSED => return Some(Outcome::Passed),
+        FAILED => "assertion failed".to_string(),
+        NOT_EQUAL => format!("expected {}, got {}", expected as i16, actual as i16),
+        _ => format!("unknown test result {}", result),
+    };
+    Some(Outcome::Failed(match failure_location(debugger) {
+        Some(location) => format!("{} at {}", problem, location),
+        None => problem,
+    }))
+}
+
+// Where the test called the assertion that failed.
+fn failure_location(debugger: &Debugger) -> Option<String> {
+    let (_, pc) = debugger
+        .stack()
+        .into_iter()
+        .find(|(frame, _)| !debugger.debug_info.subroutines[frame.subroutine_idx].name.starts_with("Test."))?;
+    let location = debugger.debug_info.location(pc)?;
+    Some(format!("{}:{}", location.filename, location.line))
+}
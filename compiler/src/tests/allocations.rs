@@ -0,0 +1,88 @@
+use emulator_core::debugger::{RunMode, StopReason};
+
+use crate::utils::testing::test_utils::*;
+
+const POINT: &str = "class Point {
+    field int x, y;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        return this;
+    }
+
+    method void dispose() {
+        do Memory.deAlloc(this);
+        return;
+    }
+}";
+
+const MAIN: &str = "class Main {
+    function void main() {
+        var Point p;
+        var String s;
+        var int i;
+        let p = Point.new(1, 2);
+        do p.dispose();
+        let i = 0;
+        while (i < 3) {
+            let s = \"hi\";
+            let i = i + 1;
+        }
+        let p = Point.new(3, 4);
+        return;
+    }
+}";
+
+// Each string literal allocates a String and its buffer, and nothing frees
+// them. They're grouped under the line in Main rather than String.new, but
+// objects of classes outside the standard library are grouped under their
+// constructors.
+#[test]
+fn test_leak_report() {
+    let mut debugger = debugger_for_files(&[("Main.jack", MAIN), ("Point.jack", POINT)]);
+    debugger.track_allocations();
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+
+    let allocations = debugger.allocations().unwrap();
+    let mut sites: Vec<_> = allocations.live().map(|allocation| (allocation.site.as_str(), allocation.size)).collect();
+    sites.sort();
+    assert_eq!(
+        sites,
+        [
+            ("Main.main at Main.jack:10", 2),
+            ("Main.main at Main.jack:10", 2),
+            ("Main.main at Main.jack:10", 2),
+            ("Main.main at Main.jack:10", 3),
+            ("Main.main at Main.jack:10", 3),
+            ("Main.main at Main.jack:10", 3),
+            ("Point.new at Point.jack:4", 2),
+        ]
+    );
+    assert!(allocations.bad_frees().is_empty());
+
+    let report = allocations.leak_report();
+    assert!(report.starts_with("7 blocks still allocated, 17 words\n6 blocks, 15 words, from Main.main at Main.jack:10\n"));
+    assert!(report.contains("1 blocks, 2 words, from Point.new at Point.jack:4\n"));
+    assert!(report.contains("    String.new at String.jack:4\n    Main.main at Main.jack:10\n    Sys.init at Sys.jack:14\n"));
+}
+
+#[test]
+fn test_double_free() {
+    let main = "class Main {
+    function void main() {
+        var Point p;
+        let p = Point.new(1, 2);
+        do p.dispose();
+        do p.dispose();
+        return;
+    }
+}";
+    let mut debugger = debugger_for_files(&[("Main.jack", main), ("Point.jack", POINT)]);
+    debugger.track_allocations();
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+    let allocations = debugger.allocations().unwrap();
+    assert_eq!(allocations.live().count(), 0);
+    assert_eq!(allocations.bad_frees().len(), 1);
+    assert!(allocations.bad_frees()[0].starts_with("Point.dispose at Point.jack:11 freed "));
+}
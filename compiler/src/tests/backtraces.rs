@@ -0,0 +1,88 @@
+use emulator_core::{
+    debug_info::CallEvent,
+    debugger::{Debugger, RunMode, StopReason},
+};
+
+use crate::utils::testing::test_utils::*;
+
+fn names_and_pcs(debugger: &Debugger) -> Vec<(String, u16)> {
+    debugger
+        .backtrace()
+        .frames
+        .iter()
+        .map(|(frame, pc)| (debugger.debug_info.subroutines[frame.subroutine_idx].name.clone(), *pc))
+        .collect()
+}
+
+fn tracked_names_and_pcs(debugger: &Debugger) -> Vec<(String, u16)> {
+    debugger
+        .stack()
+        .into_iter()
+        .map(|(frame, pc)| (debugger.debug_info.subroutines[frame.subroutine_idx].name.clone(), pc))
+        .collect()
+}
+
+const PROGRAM: &str = "class Main {
+    field int total;
+
+    constructor Main new() {
+        let total = 0;
+        return this;
+    }
+
+    method int add(int n) {
+        let total = total + Main.triangle(n);
+        return total;
+    }
+
+    function int triangle(int n) {
+        if (n = 0) {
+            return 0;
+        }
+        return n + Main.triangle(n - 1);
+    }
+
+    function void main() {
+        var Main main;
+        var int result;
+        let main = Main.new();
+        let result = 1 + (2 * main.add(3));
+        return;
+    }
+}";
+
+// Unwinding has to agree with the call tracker, which watched every call and
+// return to get there, at every instruction apart from those which take a
+// frame apart when returning.
+#[test]
+fn test_unwinding_matches_call_tracker() {
+    let mut debugger = debugger_for(PROGRAM);
+    loop {
+        let pc = debugger.computer.cpu.pc;
+        let returning = debugger.debug_info.pc_info(pc).map_or(false, |pc_info| pc_info.vm_command == "return");
+        if debugger.call_tracker.depth() > 0 && !returning {
+            assert_eq!(names_and_pcs(&debugger), tracked_names_and_pcs(&debugger), "at pc {}", pc);
+            assert_eq!(debugger.backtrace().error, None);
+        }
+        if matches!(debugger.tick(), Some(CallEvent::Return)) && debugger.call_tracker.depth() == 0 {
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_backtrace_format() {
+    let mut debugger = debugger_for(PROGRAM);
+    debugger.set_line_breakpoints("Main.jack", &[16]);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Breakpoint));
+    let backtrace = debugger.backtrace();
+    let formatted = backtrace.format(&debugger.debug_info, &debugger.computer);
+    let lines: Vec<_> = formatted.lines().collect();
+    assert_eq!(lines[0], "#0 Main.triangle(n = 0) at Main.jack:16");
+    assert_eq!(lines[3], "#3 Main.triangle(n = 3) at Main.jack:18");
+    assert!(lines[4].starts_with("#4 Main.add(this = Main @ "), "{}", formatted);
+    assert!(lines[4].ends_with(", n = 3) at Main.jack:10"), "{}", formatted);
+    assert_eq!(lines[5], "#5 Main.main() at Main.jack:25");
+    assert_eq!(lines[6], "#6 Sys.init() at Sys.jack:14");
+    assert_eq!(lines.len(), 7);
+}
@@ -0,0 +1,105 @@
+use emulator_core::debugger::{RunMode, StopReason};
+
+use crate::utils::testing::test_utils::*;
+
+fn main_class(main: &str) -> String {
+    format!("class Main {{\n    function void main() {{\n{}\n    }}\n}}", main)
+}
+
+// Why a program with a Main.main stops when its arithmetic is checked.
+fn stop_reason(main: &str) -> Option<StopReason> {
+    let mut debugger = debugger_checking_arithmetic(&main_class(main));
+    debugger.run(RunMode::Continue, 10_000_000)
+}
+
+fn arithmetic_error(main: &str) -> String {
+    match stop_reason(main) {
+        Some(StopReason::ArithmeticError(error)) => error,
+        stop_reason => panic!("expected an arithmetic error, got {:?}", stop_reason),
+    }
+}
+
+#[test]
+fn test_add_overflow() {
+    let error = arithmetic_error(
+        "        var int x;
+        let x = 32767;
+        let x = x + 1;
+        return;",
+    );
+    assert_eq!(error, "overflow in 32767 + 1 at Main.jack:5 in Main.main");
+}
+
+#[test]
+fn test_subtract_overflow() {
+    assert_eq!(
+        arithmetic_error("        var int x;\n        let x = -32767 - 2;\n        return;"),
+        "overflow in -32767 - 2 at Main.jack:4 in Main.main"
+    );
+}
+
+#[test]
+fn test_negate_overflow() {
+    assert_eq!(
+        arithmetic_error("        var int x;\n        let x = -32767 - 1;\n        let x = -x;\n        return;"),
+        "overflow in -(-32768) at Main.jack:5 in Main.main"
+    );
+}
+
+#[test]
+fn test_multiply_overflow() {
+    assert_eq!(
+        arithmetic_error("        var int x;\n        let x = 200 * -200;\n        return;"),
+        "overflow in 200 * -200 at Main.jack:4 in Main.main"
+    );
+    assert_eq!(
+        arithmetic_error("        var int x;\n        let x = (-16384) * -2;\n        return;"),
+        "overflow in -16384 * -2 at Main.jack:4 in Main.main"
+    );
+}
+
+#[test]
+fn test_division_by_zero() {
+    let error = arithmetic_error(
+        "        var int x, y;
+        let x = 7;
+        let y = 0;
+        let x = x / y;
+        return;",
+    );
+    assert_eq!(error, "division by zero in 7 / 0 at Main.jack:6 in Main.main");
+    assert_eq!(
+        arithmetic_error("        var int x;\n        let x = (-32767 - 1) / -1;\n        return;"),
+        "overflow in -32768 / -1 at Main.jack:4 in Main.main"
+    );
+}
+
+#[test]
+fn test_in_range_arithmetic() {
+    // The results are written somewhere in the middle of the heap, which
+    // nothing else uses.
+    let mut debugger = debugger_checking_arithmetic(&main_class(
+        "        var Array results;
+        let results = 7000;
+        let results[0] = 32766 + 1;
+        let results[1] = -32767 - 1;
+        let results[2] = (-16384) * 2;
+        let results[3] = (-181) * 181;
+        let results[4] = (-32767 - 1) / 2;
+        let results[5] = -(-32767);
+        let results[6] = (-32767 - 1) * 1;
+        let results[7] = (-32767 - 1) / (-32767 - 1);
+        let results[8] = 7 / (-32767 - 1);
+        let results[9] = (-32767 - 1) / 3;
+        return;",
+    ));
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+    let results: Vec<_> = debugger.computer.ram.lock()[7000..7010].iter().map(|&word| word as i16).collect();
+    assert_eq!(results, vec![32767, -32768, -32768, -32761, -16384, 32767, -32768, 1, 0, -10922]);
+}
+
+#[test]
+fn test_unchecked_arithmetic_wraps() {
+    let mut debugger = debugger_for(&main_class("        var int x;\n        let x = 32767 + 1;\n        return;"));
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+}
@@ -0,0 +1,93 @@
+use emulator_core::{
+    breakpoints::{Breakpoint, Expression, Scope},
+    debugger::{Debugger, RunMode, StopReason},
+};
+
+use crate::utils::testing::test_utils::*;
+
+fn summing_debugger() -> Debugger {
+    debugger_for(
+        "class Main {
+    static int total;
+
+    function int add(int x) {
+        let total = total + x;
+        return total;
+    }
+
+    function void main() {
+        var int i;
+        let i = 0;
+        while (i < 20) {
+            do Main.add(i);
+            let i = i + 1;
+        }
+        return;
+    }
+}",
+    )
+}
+
+fn breakpoint(condition: Option<&str>, hit_condition: Option<&str>, log_message: Option<&str>) -> Breakpoint {
+    Breakpoint::parse(condition, hit_condition, log_message).unwrap()
+}
+
+fn evaluate(debugger: &Debugger, source: &str) -> i16 {
+    let scope = Scope {
+        computer: &debugger.computer,
+        debug_info: Some(&debugger.debug_info),
+        frames: debugger.call_tracker.frames(),
+    };
+    Expression::parse(source).unwrap().evaluate(&scope).unwrap() as i16
+}
+
+#[test]
+fn test_condition_on_variables() {
+    let mut debugger = summing_debugger();
+    debugger.set_conditional_line_breakpoints("Main.jack", vec![(5, breakpoint(Some("Main.main:i == 10"), None, None))]);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Breakpoint));
+    assert_eq!(evaluate(&debugger, "x"), 10);
+    assert_eq!(evaluate(&debugger, "Main.total"), 45);
+    assert_eq!(evaluate(&debugger, "total == 45 && RAM[0] > 256"), -1);
+}
+
+#[test]
+fn test_hit_conditions() {
+    let mut debugger = summing_debugger();
+    debugger.set_conditional_line_breakpoints("Main.jack", vec![(13, breakpoint(None, Some("== 3"), None))]);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Breakpoint));
+    assert_eq!(evaluate(&debugger, "i"), 2);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+
+    // Hits only count when the condition holds.
+    let mut debugger = summing_debugger();
+    debugger.set_conditional_line_breakpoints("Main.jack", vec![(13, breakpoint(Some("i > 5"), Some("2"), None))]);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Breakpoint));
+    assert_eq!(evaluate(&debugger, "i"), 7);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Breakpoint));
+    assert_eq!(evaluate(&debugger, "i"), 8);
+}
+
+#[test]
+fn test_log_points() {
+    let mut debugger = summing_debugger();
+    let log_point = breakpoint(None, None, Some("x={x} total={total}"));
+    debugger.set_conditional_line_breakpoints("Main.jack", vec![(5, log_point)]);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+    let logs = debugger.take_logs();
+    assert_eq!(logs.len(), 20);
+    assert_eq!(logs[0], "x=0 total=0");
+    assert_eq!(logs[19], "x=19 total=171");
+}
+
+#[test]
+fn test_condition_errors() {
+    let mut debugger = summing_debugger();
+    debugger.set_conditional_line_breakpoints("Main.jack", vec![(5, breakpoint(Some("Main.add:i > 3"), None, None))]);
+    assert_eq!(
+        debugger.run(RunMode::Continue, 10_000_000),
+        Some(StopReason::ConditionError(
+            "can't check the condition Main.add:i > 3: there's no local or argument called i in Main.add".to_string()
+        ))
+    );
+}
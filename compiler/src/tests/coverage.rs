@@ -0,0 +1,80 @@
+use emulator_core::{
+    coverage::{BranchKind, CoverageReport},
+    debugger::{RunMode, StopReason},
+};
+
+use crate::utils::testing::test_utils::*;
+
+// Runs a program which calls Main.pick with a number, three times over.
+fn coverage_report(number: &str) -> CoverageReport {
+    let mut debugger = debugger_for(&format!(
+        "class Main {{
+    function int pick(int x) {{
+        if (x > 0) {{
+            return 1;
+        }} else {{
+            return Main.negate(x);
+        }}
+    }}
+
+    function int negate(int x) {{
+        return -x;
+    }}
+
+    function void main() {{
+        var int i;
+        let i = 0;
+        while (i < 3) {{
+            let i = i + Main.pick({});
+        }}
+        return;
+    }}
+}}",
+        number
+    ));
+    debugger.record_coverage();
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+    debugger.coverage().unwrap().report(&debugger.debug_info)
+}
+
+#[test]
+fn test_statement_coverage() {
+    let report = coverage_report("1");
+    let main = &report.files["Main.jack"];
+    assert_eq!(main.lines[&4], 3);
+    assert_eq!(main.lines[&6], 0);
+    assert_eq!(main.lines[&11], 0);
+    assert_eq!(main.lines[&16], 1);
+    assert_eq!(main.functions["Main.pick"], (2, 3));
+    assert_eq!(main.functions["Main.negate"].1, 0);
+    assert_eq!(main.functions["Main.main"].1, 1);
+    // The std_lib is covered too.
+    assert_eq!(report.files["Memory.jack"].functions["Memory.init"].1, 1);
+}
+
+#[test]
+fn test_branch_coverage() {
+    let report = coverage_report("1");
+    let main = &report.files["Main.jack"];
+    let branch = &main.branches[&(3, 0)];
+    assert_eq!((branch.kind, branch.arms), (BranchKind::If, [3, 0]));
+    let branch = &main.branches[&(17, 0)];
+    assert_eq!((branch.kind, branch.arms), (BranchKind::While, [3, 1]));
+
+    let lcov = report.to_lcov();
+    assert!(lcov.contains("SF:Main.jack\n"));
+    assert!(lcov.contains("BRDA:3,0,0,3\nBRDA:3,0,1,0\n"), "{}", lcov);
+    assert!(lcov.contains("FNDA:0,Main.negate\n"), "{}", lcov);
+}
+
+#[test]
+fn test_merged_coverage() {
+    let mut report = coverage_report("1");
+    report.merge(&coverage_report("-1"));
+    let main = &report.files["Main.jack"];
+    assert_eq!(main.branches[&(3, 0)].arms, [3, 3]);
+    assert_eq!(main.functions["Main.negate"].1, 3);
+    assert_eq!(main.lines[&11], 3);
+    assert!(report.summary().contains("Main.jack "));
+    assert!(!report.summary().contains("Main.negate"));
+}
@@ -0,0 +1,74 @@
+use emulator_core::{
+    debug_info::Variable,
+    debugger::{RunMode, StopReason},
+    memory_layout::{STACK_BASE, STATIC_BASE},
+};
+
+use crate::utils::testing::test_utils::*;
+
+fn names_and_types(variables: &[Variable]) -> Vec<(&str, &str, usize)> {
+    variables
+        .iter()
+        .map(|var| (var.name.as_str(), var.type_name.as_str(), var.index))
+        .collect()
+}
+
+#[test]
+fn test_debug_symbols() {
+    let mut debugger = debugger_for_files(&[
+        (
+            "Main.jack",
+            "class Main {
+    static int count;
+    static boolean unused;
+
+    function void main() {
+        var Counter counter;
+        let counter = Counter.new();
+        do counter.increment(5);
+        let count = counter.value();
+        return;
+    }
+}",
+        ),
+        (
+            "Counter.jack",
+            "class Counter {
+    field int total;
+    field Counter next;
+
+    constructor Counter new() {
+        let total = 0;
+        return this;
+    }
+
+    method void increment(int by) {
+        let total = total + by;
+        return;
+    }
+
+    method int value() {
+        return total;
+    }
+}",
+        ),
+    ]);
+    let debug_info = &debugger.debug_info;
+
+    let increment = debug_info.subroutine_by_name("Counter.increment").unwrap();
+    assert_eq!(names_and_types(&increment.arguments), [("this", "Counter", 0), ("by", "int", 1)]);
+    let main = debug_info.subroutine_by_name("Main.main").unwrap();
+    assert_eq!(names_and_types(&main.locals), [("counter", "Counter", 0)]);
+    assert_eq!(main.class_name(), "Main");
+
+    let counter_layout = debug_info.class_layout("Counter").unwrap();
+    assert_eq!(names_and_types(&counter_layout.fields), [("total", "int", 0), ("next", "Counter", 1)]);
+    let main_layout = debug_info.class_layout("Main").unwrap();
+    assert_eq!(main_layout.statics[1].name, "unused");
+    assert_eq!(main_layout.statics[1].address, None);
+    let count_address = main_layout.statics[0].address.unwrap() as usize;
+    assert!((STATIC_BASE..STACK_BASE).contains(&count_address));
+
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+    assert_eq!(debugger.computer.ram.lock()[count_address], 5);
+}
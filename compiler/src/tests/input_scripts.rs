@@ -0,0 +1,59 @@
+use emulator_core::{
+    breakpoints::{Expression, Scope},
+    debugger::{Debugger, RunMode},
+    input_script::InputScript,
+    memory_layout::SCREEN,
+};
+
+use crate::utils::testing::test_utils::*;
+
+// Types `hI`, a new line and then `x` into the text editor. Printing a
+// character takes a while, so each key is held until it has been printed.
+const SCRIPT: &str = "\
+down H at 100000
+up when Output.cursor_x = 1
+down shift+I after 1000
+up when Output.cursor_x = 2
+down Enter after 1000
+up when Output.cursor_y = 1
+down X after 1000
+up when Output.cursor_x = 1
+";
+
+fn text_editor(script: &str) -> Debugger {
+    let mut debugger = debugger_for(include_str!("../../../programs/jack/text_editor/Main.jack"));
+    debugger.play_input(InputScript::parse(script).unwrap());
+    // The editor never finishes.
+    assert_eq!(debugger.run(RunMode::Continue, 5_000_000), None);
+    debugger
+}
+
+fn cursor(debugger: &Debugger) -> (i16, i16) {
+    let scope = Scope {
+        computer: &debugger.computer,
+        debug_info: Some(&debugger.debug_info),
+        frames: debugger.call_tracker.frames(),
+    };
+    let evaluate = |source| Expression::parse(source).unwrap().evaluate(&scope).unwrap() as i16;
+    (evaluate("Output.cursor_x"), evaluate("Output.cursor_y"))
+}
+
+#[test]
+fn test_typing() {
+    let debugger = text_editor(SCRIPT);
+    assert!(debugger.input_player().unwrap().is_finished());
+    assert_eq!(cursor(&debugger), (1, 1));
+    let ram = debugger.computer.ram.lock();
+    // Each row of characters is 8 lines of 32 words.
+    assert!(ram[SCREEN..SCREEN + 256].iter().any(|&word| word != 0));
+    assert!(ram[SCREEN + 256..SCREEN + 512].iter().any(|&word| word != 0));
+    assert!(ram[SCREEN + 512..SCREEN + 768].iter().all(|&word| word == 0));
+}
+
+#[test]
+fn test_replay_is_deterministic() {
+    let screen = |debugger: &Debugger| debugger.computer.ram.lock()[SCREEN..SCREEN + 768].to_vec();
+    assert_eq!(screen(&text_editor(SCRIPT)), screen(&text_editor(SCRIPT)));
+    let without_input = text_editor("");
+    assert_eq!(cursor(&without_input), (0, 0));
+}
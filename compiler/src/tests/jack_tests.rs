@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use crate::{
+    jack_compiler::codegen::Arithmetic,
+    test_runner::{find_tests, Outcome, TestRunner},
+    utils::{source_modules::get_source_modules, testing::test_utils::*},
+};
+
+const MAX_TICKS: u64 = 1_000_000;
+
+const COUNTER_TEST: &str = "
+class CounterTest {
+  function void test_increments() {
+    do Counter.increment();
+    do Counter.increment();
+    do Test.assertEquals(Counter.count(), 2);
+  }
+
+  // Each test gets a fresh computer, so the count starts again.
+  function void test_starts_at_zero() {
+    do Test.assertEquals(Counter.count(), 0);
+  }
+
+  function void test_wrong_count() {
+    do Counter.increment();
+    do Test.assertEquals(Counter.count(), 5);
+  }
+
+  function void test_assert() {
+    do Test.assert(Counter.count() = 0);
+    do Test.assert(false);
+    do Test.fail();
+  }
+
+  function void test_overflow() {
+    var int x;
+
+    let x = 32767;
+    let x = x + 1;
+  }
+
+  function void test_forever() {
+    while (true) {}
+  }
+
+  function void helper() {}
+
+  function void testWithArgument(int x) {}
+}
+";
+
+const COUNTER: &str = "
+class Counter {
+  static int count;
+
+  function void increment() {
+    let count = count + 1;
+  }
+
+  function int count() {
+    return count;
+  }
+}
+";
+
+fn counter_runner(arithmetic: Arithmetic) -> TestRunner {
+    TestRunner::new(
+        source_modules(&[("CounterTest.jack", COUNTER_TEST), ("Counter.jack", COUNTER)]),
+        arithmetic,
+    )
+}
+
+fn run_all(runner: &mut TestRunner, max_ticks: u64) -> Vec<(String, Outcome)> {
+    (0..runner.tests.len())
+        .map(|idx| (runner.tests[idx].to_string(), runner.run(idx, max_ticks)))
+        .collect()
+}
+
+#[test]
+fn test_find_tests() {
+    let user_code = source_modules(&[
+        ("CounterTest.jack", COUNTER_TEST),
+        ("Counter.jack", COUNTER),
+        (
+            "AnotherTest.jack",
+            "class AnotherTest {\n  function void test_another() {}\n  function void test() {}\n  function int test_value() {\n    return 1;\n  }\n  method void test_method() {}\n}\n",
+        ),
+    ]);
+    let names: Vec<_> = find_tests(&user_code).iter().map(ToString::to_string).collect();
+    assert_eq!(
+        names,
+        [
+            "AnotherTest.test_another",
+            "CounterTest.test_increments",
+            "CounterTest.test_starts_at_zero",
+            "CounterTest.test_wrong_count",
+            "CounterTest.test_assert",
+            "CounterTest.test_overflow",
+            "CounterTest.test_forever",
+        ]
+    );
+}
+
+#[test]
+fn test_outcomes() {
+    let failed = |problem: &str| Outcome::Failed(problem.to_string());
+    assert_eq!(
+        run_all(&mut counter_runner(Arithmetic::Wrapping), MAX_TICKS),
+        [
+            ("CounterTest.test_increments".to_string(), Outcome::Passed),
+            ("CounterTest.test_starts_at_zero".to_string(), Outcome::Passed),
+            (
+                "CounterTest.test_wrong_count".to_string(),
+                failed("expected 5, got 1 at CounterTest.jack:16")
+            ),
+            ("CounterTest.test_assert".to_string(), failed("assertion failed at CounterTest.jack:21")),
+            ("CounterTest.test_overflow".to_string(), Outcome::Passed),
+            (
+                "CounterTest.test_forever".to_string(),
+                failed("didn't finish within 1000000 instructions")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_checked_arithmetic() {
+    let mut runner = counter_runner(Arithmetic::Checked);
+    let idx = runner.tests.iter().position(|test| test.function == "test_overflow").unwrap();
+    assert_eq!(
+        runner.run(idx, MAX_TICKS),
+        Outcome::Failed("overflow in 32767 + 1 at CounterTest.jack:29 in CounterTest.test_overflow".to_string())
+    );
+}
+
+#[test]
+fn test_coverage() {
+    let mut runner = counter_runner(Arithmetic::Wrapping);
+    runner.record_coverage();
+    runner.run(0, MAX_TICKS);
+    runner.run(3, MAX_TICKS);
+    let coverage = runner.coverage().unwrap();
+    let counter = &coverage.files["Counter.jack"];
+    assert_eq!(counter.functions["Counter.increment"], (5, 2));
+    assert_eq!(counter.functions["Counter.count"], (9, 2));
+}
+
+// The tests of the standard library itself, in std_lib/tests.
+#[test]
+fn test_std_lib() {
+    let tests_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../std_lib/tests");
+    let mut runner = TestRunner::new(get_source_modules(&tests_dir).unwrap(), Arithmetic::Wrapping);
+    assert!(runner.tests.len() > 5);
+    for (name, outcome) in run_all(&mut runner, 10_000_000) {
+        assert_eq!(outcome, Outcome::Passed, "{}", name);
+    }
+}
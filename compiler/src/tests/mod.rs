@@ -0,0 +1,18 @@
+// End to end tests, which compile jack programs and run them in emulator-core's
+// debugger with the debug info that comes out.
+
+mod allocations;
+mod backtraces;
+mod checked_arithmetic;
+mod conditional_breakpoints;
+mod coverage;
+mod debug_symbols;
+mod input_scripts;
+mod jack_tests;
+mod object_graphs;
+mod pointer_checks;
+mod pointer_saving;
+mod profiler;
+mod sanitizer;
+mod source_level_debugging;
+mod stack_checks;
@@ -0,0 +1,73 @@
+use emulator_core::{
+    debugger::{RunMode, StopReason},
+    object_graph::object_tree,
+};
+
+use crate::utils::testing::test_utils::*;
+
+const MAIN: &str = "class Main {
+    function void main() {
+        var Node first, second;
+        let first = Node.new(\"one\");
+        let second = Node.new(\"two\");
+        do first.link(second);
+        do second.link(first);
+        return;
+    }
+}";
+
+const NODE: &str = "class Node {
+    field String label;
+    field Node next;
+
+    constructor Node new(String name) {
+        let label = name;
+        let next = null;
+        return this;
+    }
+
+    method void link(Node other) {
+        let next = other;
+        return;
+    }
+}";
+
+// Two nodes which point at each other, shown from the locals of Main.main
+// using the layouts of the classes the compiler made.
+#[test]
+fn test_object_graph() {
+    let mut debugger = debugger_for_files(&[("Main.jack", MAIN), ("Node.jack", NODE)]);
+    debugger.set_line_breakpoints("Main.jack", &[8]);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Breakpoint));
+
+    let (frame, _) = debugger.stack()[0];
+    let first = debugger.computer.ram.lock()[frame.lcl as usize];
+    let tree = object_tree(&debugger.debug_info, &*debugger.computer.ram.lock(), "Node", first, 8);
+    let lines: Vec<_> = tree
+        .format("first")
+        .lines()
+        .map(|line| line.split(" = ").next().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "first",
+            "  label",
+            "    buffer",
+            "    buffer_length",
+            "    length",
+            "  next",
+            "    label",
+            "      buffer",
+            "      buffer_length",
+            "      length",
+            "    next",
+        ]
+        .map(|line| line.to_string())
+    );
+    let formatted = tree.format("first");
+    assert!(formatted.starts_with(&format!("first = Node @ {}\n", first)), "{}", formatted);
+    assert!(formatted.contains(" \"one\"\n"), "{}", formatted);
+    assert!(formatted.contains(" \"two\"\n"), "{}", formatted);
+    assert!(formatted.ends_with(&format!("    next = Node @ {} (cycle)", first)), "{}", formatted);
+}
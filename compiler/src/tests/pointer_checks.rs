@@ -0,0 +1,50 @@
+use emulator_core::debugger::{RunMode, StopReason};
+
+use crate::utils::testing::test_utils::*;
+
+// The compiler's own output should never trip the pointer checker. Calls in
+// between uses of each pointer give every chance for one not to be restored.
+#[test]
+fn test_compiled_code_restores_pointers() {
+    let mut debugger = debugger_for(
+        "class Main {
+    field Array cells;
+    field int count;
+
+    constructor Main new(int size) {
+        let cells = Memory.malloc(size);
+        let count = size;
+        return this;
+    }
+
+    method int sum(int from) {
+        var int idx, total;
+        let idx = from;
+        let total = 0;
+        while (idx < count) {
+            let cells[idx] = Main.square(idx);
+            let total = total + cells[idx];
+            let idx = idx + 1;
+        }
+        return total;
+    }
+
+    function int square(int x) {
+        return x * x;
+    }
+
+    function void main() {
+        var Main main;
+        var Array results;
+        let results = 8000;
+        let main = Main.new(4);
+        let results[0] = main.sum(0);
+        let results[1] = main.sum(results[0] - 12) + Main.square(results[0]);
+        return;
+    }
+}",
+    );
+    debugger.check_pointers();
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+    assert_eq!(debugger.computer.ram.lock()[8001], 13 + 14 * 14);
+}
@@ -0,0 +1,256 @@
+use emulator_core::{
+    debugger::{Debugger, RunMode, StopReason},
+    memory_layout::{HEAP_BASE, SCREEN, SCREEN_SIZE},
+};
+use proptest::prelude::*;
+
+use crate::{utils::testing::test_utils::*, vm_compiler::codegen::PointerSaving};
+
+// Programs compiled with the call graph analysis deciding which pointers to
+// save have to behave the same as ones which save all of them on every call.
+
+// Statics aren't compared, because the order they're laid out in can change
+// from one compile to the next.
+fn heap_and_screen(debugger: &Debugger) -> Vec<u16> {
+    debugger.computer.ram.lock()[HEAP_BASE..SCREEN + SCREEN_SIZE].to_vec()
+}
+
+// Two generations of the glider, stopping each time the next frame has been
+// worked out.
+fn game_of_life_frames(pointer_saving: PointerSaving) -> Vec<Vec<u16>> {
+    let mut debugger = debugger_saving_pointers(&GAME_OF_LIFE, pointer_saving);
+    debugger.check_pointers();
+    debugger.set_line_breakpoints("Main.jack", &[line_of(&GAME_OF_LIFE, "Main.jack", "do copy_next_buffer();")]);
+    (0..2)
+        .map(|_| {
+            assert_eq!(debugger.run(RunMode::Continue, 100_000_000), Some(StopReason::Breakpoint));
+            heap_and_screen(&debugger)
+        })
+        .collect()
+}
+
+#[test]
+fn test_game_of_life() {
+    assert_eq!(game_of_life_frames(PointerSaving::Analysed), game_of_life_frames(PointerSaving::All));
+}
+
+#[derive(Clone, Debug)]
+enum Expression {
+    Constant(u8),
+    Argument(usize),
+    Local(usize),
+    // The object's field in methods, and a constant in functions.
+    Field,
+    Cell(usize),
+    Binary(Box<Expression>, &'static str, Box<Expression>),
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    SetLocal(usize, Expression),
+    SetField(Expression),
+    SetCell(usize, Expression),
+    // Methods are called on one of two objects, so that THIS changes.
+    Call {
+        callee: usize,
+        object: bool,
+        result: Option<usize>,
+        args: Vec<Expression>,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Subroutine {
+    method: bool,
+    arg_count: usize,
+    local_count: usize,
+    statements: Vec<Statement>,
+}
+
+const CELL_COUNT: usize = 8;
+
+fn expression() -> impl Strategy<Value = Expression> {
+    let leaf = prop_oneof![
+        any::<u8>().prop_map(Expression::Constant),
+        any::<usize>().prop_map(Expression::Argument),
+        any::<usize>().prop_map(Expression::Local),
+        Just(Expression::Field),
+        (0..CELL_COUNT).prop_map(Expression::Cell),
+    ];
+    leaf.prop_recursive(2, 8, 2, |inner| {
+        (inner.clone(), prop_oneof![Just("+"), Just("-"), Just("&"), Just("|")], inner)
+            .prop_map(|(left, operator, right)| Expression::Binary(Box::new(left), operator, Box::new(right)))
+    })
+}
+
+fn statement() -> impl Strategy<Value = Statement> {
+    prop_oneof![
+        (any::<usize>(), expression()).prop_map(|(local, value)| Statement::SetLocal(local, value)),
+        expression().prop_map(Statement::SetField),
+        (0..CELL_COUNT, expression()).prop_map(|(cell, value)| Statement::SetCell(cell, value)),
+        (
+            any::<usize>(),
+            any::<bool>(),
+            proptest::option::of(any::<usize>()),
+            proptest::collection::vec(expression(), 2)
+        )
+            .prop_map(|(callee, object, result, args)| Statement::Call {
+                callee,
+                object,
+                result,
+                args
+            }),
+    ]
+}
+
+fn subroutine() -> impl Strategy<Value = Subroutine> {
+    (any::<bool>(), 0..3_usize, 0..3_usize, proptest::collection::vec(statement(), 0..5)).prop_map(|(method, arg_count, local_count, statements)| {
+        Subroutine {
+            method,
+            arg_count,
+            local_count,
+            statements,
+        }
+    })
+}
+
+fn render_expression(expression: &Expression, subroutine: &Subroutine) -> String {
+    match expression {
+        Expression::Constant(value) => value.to_string(),
+        Expression::Argument(idx) if subroutine.arg_count > 0 => format!("a{}", idx % subroutine.arg_count),
+        Expression::Local(idx) if subroutine.local_count > 0 => format!("l{}", idx % subroutine.local_count),
+        Expression::Argument(_) | Expression::Local(_) => "depth".to_string(),
+        Expression::Field if subroutine.method => "value".to_string(),
+        Expression::Field => "7".to_string(),
+        Expression::Cell(idx) => format!("cells[{}]", idx),
+        Expression::Binary(left, operator, right) => format!(
+            "({} {} {})",
+            render_expression(left, subroutine),
+            operator,
+            render_expression(right, subroutine)
+        ),
+    }
+}
+
+fn render_call(callee_idx: usize, object: bool, args: &[String], subroutines: &[Subroutine]) -> String {
+    let callee = &subroutines[callee_idx];
+    let target = match (callee.method, object) {
+        (false, _) => "Main",
+        (true, false) => "first",
+        (true, true) => "second",
+    };
+    let args: Vec<_> = args.iter().take(callee.arg_count).map(|arg| format!(", {}", arg)).collect();
+    format!("{}.s{}(depth - 1{})", target, callee_idx, args.concat())
+}
+
+fn render_statement(statement: &Statement, subroutine: &Subroutine, subroutines: &[Subroutine]) -> String {
+    match statement {
+        Statement::SetLocal(idx, value) if subroutine.local_count > 0 => {
+            format!("let l{} = {};", idx % subroutine.local_count, render_expression(value, subroutine))
+        }
+        Statement::SetField(value) if subroutine.method => format!("let value = {};", render_expression(value, subroutine)),
+        Statement::SetLocal(_, value) | Statement::SetField(value) => {
+            format!("let cells[0] = {};", render_expression(value, subroutine))
+        }
+        Statement::SetCell(idx, value) => format!("let cells[{}] = {};", idx, render_expression(value, subroutine)),
+        Statement::Call {
+            callee,
+            object,
+            result,
+            args,
+        } => {
+            let args: Vec<_> = args.iter().map(|arg| render_expression(arg, subroutine)).collect();
+            let call = render_call(callee % subroutines.len(), *object, &args, subroutines);
+            let statement = match result {
+                Some(idx) if subroutine.local_count > 0 => format!("let l{} = {};", idx % subroutine.local_count, call),
+                _ => format!("do {};", call),
+            };
+            format!("if (depth > 0) {{\n            {}\n        }}", statement)
+        }
+    }
+}
+
+fn render_subroutine(idx: usize, subroutine: &Subroutine, subroutines: &[Subroutine]) -> String {
+    let kind = if subroutine.method { "method" } else { "function" };
+    let params: Vec<_> = (0..subroutine.arg_count).map(|arg_idx| format!(", int a{}", arg_idx)).collect();
+    let locals: Vec<_> = (0..subroutine.local_count).map(|local_idx| format!("l{}", local_idx)).collect();
+    let mut lines = Vec::new();
+    if !locals.is_empty() {
+        lines.push(format!("var int {};", locals.join(", ")));
+    }
+    // Locals aren't zeroed, and the stack underneath them won't be the same
+    // in the two programs.
+    lines.extend(locals.iter().map(|local| format!("let {} = 0;", local)));
+    lines.extend(
+        subroutine
+            .statements
+            .iter()
+            .map(|statement| render_statement(statement, subroutine, subroutines)),
+    );
+    let mut result: Vec<_> = ["depth".to_string()]
+        .into_iter()
+        .chain((0..subroutine.arg_count).map(|arg_idx| format!("a{}", arg_idx)))
+        .chain(locals)
+        .collect();
+    if subroutine.method {
+        result.push("value".to_string());
+    }
+    lines.push(format!("return {};", result.join(" + ")));
+    format!(
+        "    {} int s{}(int depth{}) {{\n        {}\n    }}\n",
+        kind,
+        idx,
+        params.concat(),
+        lines.join("\n        ")
+    )
+}
+
+fn render_program(subroutines: &[Subroutine]) -> String {
+    let rendered: Vec<_> = subroutines
+        .iter()
+        .enumerate()
+        .map(|(idx, subroutine)| render_subroutine(idx, subroutine, subroutines))
+        .collect();
+    format!(
+        "class Main {{
+    static Main first, second;
+    static Array cells;
+    field int value;
+
+    constructor Main new(int initial_value) {{
+        let value = initial_value;
+        return this;
+    }}
+
+{}
+    function void main() {{
+        var Array results;
+        let results = 8000;
+        let cells = 8100;
+        let first = Main.new(1);
+        let second = Main.new(2);
+        let results[0] = {};
+        return;
+    }}
+}}",
+        rendered.join("\n"),
+        render_call(0, false, &["1".to_string(), "2".to_string()], subroutines).replace("depth - 1", "3")
+    )
+}
+
+fn results(source: &str, pointer_saving: PointerSaving) -> Vec<u16> {
+    let mut debugger = debugger_saving_pointers(&[("Main.jack", source)], pointer_saving);
+    debugger.check_pointers();
+    assert_eq!(debugger.run(RunMode::Continue, 100_000_000), Some(StopReason::Exited), "{}", source);
+    heap_and_screen(&debugger)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn test_random_call_graphs(subroutines in proptest::collection::vec(subroutine(), 1..6)) {
+        let source = render_program(&subroutines);
+        prop_assert_eq!(results(&source, PointerSaving::Analysed), results(&source, PointerSaving::All), "{}", source);
+    }
+}
@@ -0,0 +1,106 @@
+use emulator_core::{
+    debugger::{Debugger, RunMode, StopReason},
+    profiler::{Profile, SubroutineProfile},
+};
+
+use crate::utils::testing::test_utils::*;
+
+fn profiled_run() -> Debugger {
+    let mut debugger = debugger_for(
+        "class Main {
+    function int square(int x) {
+        return x * x;
+    }
+
+    function int factorial(int n) {
+        if (n < 2) {
+            return 1;
+        }
+        return n * Main.factorial(n - 1);
+    }
+
+    function void main() {
+        var int i, total;
+        let i = 0;
+        while (i < 10) {
+            let total = total + Main.square(i);
+            let i = i + 1;
+        }
+        let total = Main.factorial(5);
+        return;
+    }
+}",
+    );
+    debugger.profile();
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+    debugger
+}
+
+fn subroutine<'a>(profile: &'a Profile, name: &str) -> &'a SubroutineProfile {
+    profile.subroutines.iter().find(|subroutine| subroutine.name == name).unwrap()
+}
+
+#[test]
+fn test_subroutine_profile() {
+    let debugger = profiled_run();
+    let profile = debugger.profiler().unwrap().profile(&debugger.debug_info);
+
+    let square = subroutine(&profile, "Main.square");
+    assert_eq!(square.calls, 10);
+    assert!(square.inclusive_ticks > square.self_ticks);
+    let multiply = subroutine(&profile, "Math.multiply");
+    assert_eq!(multiply.self_ticks, multiply.inclusive_ticks);
+    assert!(multiply.calls >= 14);
+
+    // Recursive calls are only counted once towards inclusive time.
+    let factorial = subroutine(&profile, "Main.factorial");
+    assert_eq!(factorial.calls, 5);
+    let main = subroutine(&profile, "Main.main");
+    assert!(factorial.inclusive_ticks < main.inclusive_ticks);
+    assert!(main.inclusive_ticks > square.inclusive_ticks + factorial.inclusive_ticks);
+
+    let sys_init = subroutine(&profile, "Sys.init");
+    assert_eq!(profile.subroutines[0], *sys_init);
+    assert!(sys_init.inclusive_ticks < profile.total_ticks);
+    let self_ticks: u64 = profile.subroutines.iter().map(|subroutine| subroutine.self_ticks).sum();
+    assert!(self_ticks <= sys_init.inclusive_ticks);
+}
+
+#[test]
+fn test_line_profile() {
+    let debugger = profiled_run();
+    let profile = debugger.profiler().unwrap().profile(&debugger.debug_info);
+    let line_ticks = |line| {
+        profile
+            .lines
+            .iter()
+            .find(|profile| profile.filename == "Main.jack" && profile.line == line)
+            .map_or(0, |profile| profile.ticks)
+    };
+    // The loop body runs ten times, and the statement after it once.
+    assert!(line_ticks(17) > 10 * line_ticks(20));
+    assert!(line_ticks(20) > 0);
+    assert_eq!(line_ticks(14), 0);
+    let report = profile.format(5);
+    assert!(report.starts_with(&format!("{} instructions\n", profile.total_ticks)), "{}", report);
+    assert_eq!(report.lines().count(), 15);
+}
+
+#[test]
+fn test_folded_stacks() {
+    let debugger = profiled_run();
+    let folded = debugger.profiler().unwrap().folded_stacks(&debugger.debug_info);
+    let ticks = |stack: &str| {
+        folded
+            .lines()
+            .find_map(|line| line.strip_prefix(stack)?.strip_prefix(' ')?.parse::<u64>().ok())
+            .unwrap_or_else(|| panic!("no {} in\n{}", stack, folded))
+    };
+    assert!(ticks("Sys.init;Main.main;Main.square;Math.multiply") > 0);
+    assert!(ticks("Sys.init;Main.main;Main.factorial;Main.factorial;Main.factorial") > 0);
+    assert!(ticks("(boot)") > 0);
+
+    let total: u64 = folded.lines().map(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap()).sum();
+    let profile = debugger.profiler().unwrap().profile(&debugger.debug_info);
+    assert_eq!(total, profile.total_ticks);
+}
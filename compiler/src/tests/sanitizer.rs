@@ -0,0 +1,112 @@
+use emulator_core::debugger::{RunMode, StopReason};
+
+use crate::utils::testing::test_utils::*;
+
+// The first thing the sanitizer finds in a program with a Main.main.
+fn memory_error(main: &str) -> String {
+    let mut debugger = debugger_for(&format!("class Main {{\n    function void main() {{\n{}\n    }}\n}}", main));
+    debugger.sanitize_memory();
+    match debugger.run(RunMode::Continue, 10_000_000) {
+        Some(StopReason::MemoryError(error)) => error,
+        stop_reason => panic!("expected a memory error, got {:?}", stop_reason),
+    }
+}
+
+#[test]
+fn test_uninitialised_local() {
+    let error = memory_error(
+        "        var int x, y;
+        let y = x + 1;
+        return;",
+    );
+    assert!(error.starts_with("read of local x before it was set at pc "), "{}", error);
+    assert!(error.ends_with(", Main.jack:4 in Main.main"), "{}", error);
+}
+
+#[test]
+fn test_uninitialised_heap() {
+    let error = memory_error(
+        "        var Array a;
+        var int x;
+        let a = Memory.malloc(3);
+        let a[0] = 1;
+        let x = a[0] + a[1];
+        return;",
+    );
+    assert!(error.starts_with("read of RAM["), "{}", error);
+    assert!(error.contains("in the 3 word block at "), "{}", error);
+    assert!(error.ends_with(", Main.jack:7 in Main.main"), "{}", error);
+}
+
+#[test]
+fn test_calloc_is_initialised() {
+    let mut debugger = debugger_for(
+        "class Main {
+    function void main() {
+        var Array a;
+        var int x;
+        let a = Memory.calloc(3);
+        let x = a[0] + a[2];
+        do Memory.deAlloc(a);
+        return;
+    }
+}",
+    );
+    debugger.sanitize_memory();
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Exited));
+}
+
+#[test]
+fn test_null_that() {
+    let error = memory_error(
+        "        var Array a;
+        let a = null;
+        let a[2] = 1;
+        return;",
+    );
+    assert!(error.starts_with("access to RAM[2] through null THAT at pc "), "{}", error);
+    assert!(error.ends_with(", Main.jack:5 in Main.main"), "{}", error);
+}
+
+#[test]
+fn test_out_of_block() {
+    let error = memory_error(
+        "        var Array a;
+        let a = Memory.calloc(3);
+        let a[3] = 1;
+        return;",
+    );
+    assert!(error.starts_with("access outside of the heap's blocks: RAM["), "{}", error);
+    assert!(error.contains("] is past the end of the 3 word block at "), "{}", error);
+    assert!(error.ends_with(", Main.jack:5 in Main.main"), "{}", error);
+}
+
+#[test]
+fn test_use_after_free() {
+    let error = memory_error(
+        "        var Array a;
+        var int x;
+        let a = Memory.calloc(3);
+        do Memory.deAlloc(a);
+        let x = a[1];
+        return;",
+    );
+    assert!(error.starts_with("access to RAM["), "{}", error);
+    assert!(
+        error.contains("allocated by Main.main at Main.jack:5, which was freed by Main.main at Main.jack:6 at pc "),
+        "{}",
+        error
+    );
+    assert!(error.ends_with(", Main.jack:7 in Main.main"), "{}", error);
+}
+
+// The standard library and a real program shouldn't set it off.
+#[test]
+fn test_game_of_life() {
+    let mut debugger = debugger_for_files(&GAME_OF_LIFE);
+    debugger.sanitize_memory();
+    debugger.set_line_breakpoints("Main.jack", &[line_of(&GAME_OF_LIFE, "Main.jack", "do copy_next_buffer();")]);
+    for _ in 0..2 {
+        assert_eq!(debugger.run(RunMode::Continue, 100_000_000), Some(StopReason::Breakpoint));
+    }
+}
@@ -0,0 +1,53 @@
+use emulator_core::debugger::{RunMode, StepKind, StopReason};
+
+use crate::utils::testing::test_utils::*;
+
+#[test]
+fn test_source_level_debugging() {
+    let mut debugger = debugger_for(
+        "class Main {
+    function int double(int x) {
+        var int y;
+        let y = x + x;
+        return y;
+    }
+
+    function void main() {
+        var int a;
+        let a = Main.double(3);
+        let a = a + 1;
+        return;
+    }
+}",
+    );
+    let max_ticks = 10_000_000;
+
+    assert_eq!(
+        debugger.set_line_breakpoints("Main.jack", &[7, 10]),
+        [None, debugger.debug_info.line_pcs("Main.jack", 10).first().copied()]
+    );
+    assert_eq!(debugger.run(RunMode::Continue, max_ticks), Some(StopReason::Breakpoint));
+    assert_eq!(current_line(&debugger), ("Main.jack".to_string(), 10));
+    assert_eq!(current_function(&debugger), "Main.main");
+    let depth = debugger.call_tracker.depth();
+
+    let step_in = debugger.start_step(StepKind::In);
+    assert_eq!(debugger.run(step_in, max_ticks), Some(StopReason::Step));
+    assert_eq!(current_line(&debugger), ("Main.jack".to_string(), 4));
+    assert_eq!(current_function(&debugger), "Main.double");
+    assert_eq!(debugger.call_tracker.depth(), depth + 1);
+    let (frame, _) = debugger.stack()[0];
+    assert_eq!(debugger.computer.ram.lock()[frame.arg as usize], 3);
+
+    let step_out = debugger.start_step(StepKind::Out);
+    assert_eq!(debugger.run(step_out, max_ticks), Some(StopReason::Step));
+    assert_eq!(current_line(&debugger), ("Main.jack".to_string(), 11));
+    let (frame, _) = debugger.stack()[0];
+    assert_eq!(debugger.computer.ram.lock()[frame.lcl as usize], 6);
+
+    let step_over = debugger.start_step(StepKind::Over);
+    assert_eq!(debugger.run(step_over, max_ticks), Some(StopReason::Step));
+    assert_eq!(current_line(&debugger), ("Main.jack".to_string(), 12));
+
+    assert_eq!(debugger.run(RunMode::Continue, max_ticks), Some(StopReason::Exited));
+}
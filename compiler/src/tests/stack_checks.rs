@@ -0,0 +1,44 @@
+use emulator_core::{
+    debugger::{RunMode, StopReason},
+    memory_layout::{HEAP_BASE, SCREEN},
+};
+
+use crate::utils::testing::test_utils::*;
+
+const PROGRAM: &str = "class Main {
+    function int recurse(int n) {
+        return Main.recurse(n + 1) + 1;
+    }
+
+    function void main() {
+        do Main.recurse(0);
+        return;
+    }
+}";
+
+// Runaway recursion stops at the push which would have gone into the heap,
+// with the whole stack still there to unwind.
+#[test]
+fn test_stack_overflow() {
+    let mut debugger = debugger_for(PROGRAM);
+    debugger.computer.set_stack_checking(true);
+    debugger.set_line_breakpoints("Main.jack", &[7]);
+    assert_eq!(debugger.run(RunMode::Continue, 10_000_000), Some(StopReason::Breakpoint));
+    let heap = debugger.computer.ram.lock()[HEAP_BASE..SCREEN].to_vec();
+
+    let Some(StopReason::Fault(fault)) = debugger.run(RunMode::Continue, 10_000_000) else {
+        panic!("expected the stack to overflow");
+    };
+    assert!(fault.starts_with("stack overflowed into the heap"), "{}", fault);
+    assert_eq!(debugger.computer.ram.lock()[HEAP_BASE..SCREEN], heap);
+
+    let backtrace = debugger.backtrace();
+    assert_eq!(backtrace.error, None);
+    let names: Vec<_> = backtrace
+        .frames
+        .iter()
+        .map(|(frame, _)| debugger.debug_info.subroutines[frame.subroutine_idx].name.as_str())
+        .collect();
+    assert_eq!(names[0], "Main.recurse");
+    assert_eq!(&names[names.len() - 2..], &["Main.main", "Sys.init"]);
+}
@@ -1,15 +1,40 @@
 #[cfg(test)]
 pub mod test_utils {
     use crate::compile_to_machine_code;
+    use crate::debug_compile;
+    use crate::jack_compiler::codegen::Arithmetic;
     use crate::utils::source_modules::SourceModule;
+    use crate::vm_compiler::codegen::{PointerSaving, RamInit};
     use emulator_core::computer::Computer;
+    use emulator_core::debug_info::DebugInfo;
+    use emulator_core::debugger::Debugger;
+    use emulator_core::heap::{inspect_heap, MIN_ORDER};
     use std::collections::HashMap;
     use std::path::PathBuf;
 
     pub const INITIAL_STACK_POINTER_ADDRESS: u16 = 261;
 
+    // A real program, for tests that the standard library and ordinary code
+    // don't set anything off.
+    pub const GAME_OF_LIFE: [(&str, &str); 4] = [
+        ("Main.jack", include_str!("../../../programs/jack/game_of_life/Main.jack")),
+        ("Drawing.jack", include_str!("../../../programs/jack/game_of_life/Drawing.jack")),
+        ("Helpers.jack", include_str!("../../../programs/jack/game_of_life/Helpers.jack")),
+        ("Neighbours.jack", include_str!("../../../programs/jack/game_of_life/Neighbours.jack")),
+    ];
+
+    // The line of `text` in one of `files`, so that breakpoints follow the
+    // source when it's edited.
+    pub fn line_of(files: &[(&str, &str)], filename: &str, text: &str) -> usize {
+        let (_, source) = files.iter().find(|(name, _)| *name == filename).unwrap();
+        let lines: Vec<_> = (1..).zip(source.lines()).filter(|(_, line)| line.contains(text)).collect();
+        assert_eq!(lines.len(), 1, "expected {:?} on one line of {}", text, filename);
+        lines[0].0
+    }
+
     pub fn computer_from_jack_code(jack_code: HashMap<PathBuf, SourceModule>) -> Computer {
-        Computer::new(compile_to_machine_code(jack_code).try_into().unwrap())
+        let (rom, ram_image) = compile_to_machine_code(jack_code);
+        Computer::with_ram_image(rom.try_into().unwrap(), &ram_image)
     }
 
     pub fn stack_pointer(computer: &Computer) -> u16 {
@@ -49,27 +74,66 @@ pub mod test_utils {
         count
     }
 
+    // The free lists by block size, after checking the allocator's invariants.
     pub fn heap_avail_list(computer: &Computer) -> HashMap<usize, Vec<u16>> {
-        let mut result = HashMap::new();
-
-        let ram = computer.ram.lock();
-        let avail_list = &ram[2050..2050 + 13];
-        for (idx, &list_head) in avail_list.iter().enumerate() {
-            let mut current = list_head;
-            let mut free_blocks = Vec::new();
-            while current != 0 {
-                free_blocks.push(current);
-                current = ram[current as usize + 2];
-            }
-            result.insert(2_usize.pow(idx as u32 + 2), free_blocks);
-        }
-        result
+        let heap = inspect_heap(&*computer.ram.lock());
+        assert_eq!(heap.problems, Vec::<String>::new());
+        (MIN_ORDER..)
+            .zip(heap.free_lists)
+            .map(|(order, free_blocks)| (1 << order, free_blocks))
+            .collect()
     }
 
     pub fn program_completed(computer: &Computer) -> bool {
         computer.cpu.pc == 2
     }
 
+    pub fn debugger_for_files(files: &[(&str, &str)]) -> Debugger {
+        debugger_saving_pointers(files, PointerSaving::Analysed)
+    }
+
+    pub fn debugger_saving_pointers(files: &[(&str, &str)], pointer_saving: PointerSaving) -> Debugger {
+        compile_for_debugger(files, pointer_saving, Arithmetic::Wrapping)
+    }
+
+    pub fn debugger_checking_arithmetic(source: &str) -> Debugger {
+        compile_for_debugger(&[("Main.jack", source)], PointerSaving::Analysed, Arithmetic::Checked)
+    }
+
+    pub fn source_modules(files: &[(&str, &str)]) -> HashMap<PathBuf, SourceModule> {
+        files
+            .iter()
+            .map(|(filename, source)| {
+                (
+                    PathBuf::from(filename),
+                    SourceModule {
+                        filename: filename.into(),
+                        source: source.to_string(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn compile_for_debugger(files: &[(&str, &str)], pointer_saving: PointerSaving, arithmetic: Arithmetic) -> Debugger {
+        let user_code = source_modules(files);
+        let json = serde_json::to_string(&debug_compile(user_code, RamInit::Image, pointer_saving, arithmetic)).unwrap();
+        Debugger::new(DebugInfo::from_json(&json).unwrap())
+    }
+
+    pub fn debugger_for(source: &str) -> Debugger {
+        debugger_for_files(&[("Main.jack", source)])
+    }
+
+    pub fn current_line(debugger: &Debugger) -> (String, usize) {
+        let location = debugger.debug_info.location(debugger.computer.cpu.pc).unwrap();
+        (location.filename.clone(), location.line)
+    }
+
+    pub fn current_function(debugger: &Debugger) -> &str {
+        &debugger.debug_info.subroutines[debugger.stack()[0].0.subroutine_idx].name
+    }
+
     #[test]
     fn test_count_nonoverlapping_sequences() {
         let haystack = [1, 2, 3, 4, 1, 2, 3];
@@ -5,15 +5,25 @@ use std::{
 
 use crate::jack_compiler::codegen::{CompiledSubroutine, SourcemappedCommand};
 
-use super::parser::{Command, FunctionCommandVariant, MemoryCommandVariant, MemorySegmentVariant, OffsetSegmentVariant, PointerSegmentVariant};
+use super::{
+    codegen::{PointerSaving, SAVED_CALLER_POINTER_ORDER},
+    parser::{Command, FunctionCommandVariant, MemoryCommandVariant, MemorySegmentVariant, OffsetSegmentVariant, PointerSegmentVariant},
+};
 
 #[derive(Clone, Debug, Default)]
 pub struct SubroutineInfo {
     pub calls: HashSet<String>,
     pub callers: HashSet<String>,
     pub reachable_subroutines: HashSet<String>,
-    pub pointers_used_directly: HashSet<PointerSegmentVariant>,
-    pub pointers_used: HashSet<PointerSegmentVariant>,
+    // The pointers which the subroutine's own commands read, and the ones
+    // they set with `pop pointer`.
+    pub pointers_read: HashSet<PointerSegmentVariant>,
+    pub pointers_written: HashSet<PointerSegmentVariant>,
+    // ARG and LCL, if the subroutine reads them. Calling it has to set them.
+    pub pointers_set_by_call: HashSet<PointerSegmentVariant>,
+    // The pointers which might be different once a call to the subroutine has
+    // returned.
+    pub pointers_clobbered: HashSet<PointerSegmentVariant>,
     pub pointers_to_restore: HashSet<PointerSegmentVariant>,
 }
 
@@ -34,25 +44,36 @@ fn include_in_call_graph(command: &Command, subroutine_name: &str, call_graph: &
     }
 }
 
-fn record_directly_used_pointers(command: &Command, subroutine_name: &str, call_graph: &mut CallGraph) {
+// Pushing to or popping from LCL, ARG, THIS or THAT reads the pointer to find
+// the address. `push pointer` reads THIS or THAT, and `pop pointer` writes it.
+// Calls and returns are dealt with separately, because what they do to the
+// pointers depends on the callee.
+fn record_pointer_accesses(command: &Command, subroutine_name: &str, call_graph: &mut CallGraph) {
     let subroutine_info = call_graph.entry(subroutine_name.to_owned()).or_default();
-    if let Command::Memory(MemoryCommandVariant::Pop(memory_segment, offset) | MemoryCommandVariant::Push(memory_segment, offset)) = command {
-        match memory_segment {
-            MemorySegmentVariant::OffsetSegment(OffsetSegmentVariant::Pointer) => {
-                let pointer = if *offset == 0 {
-                    PointerSegmentVariant::This
-                } else if *offset == 1 {
-                    PointerSegmentVariant::That
-                } else {
-                    panic!("expected offset for pointer to be either 0 or 1")
-                };
-                subroutine_info.pointers_used_directly.insert(pointer);
-            }
-            MemorySegmentVariant::PointerSegment(segment) => {
-                subroutine_info.pointers_used_directly.insert(segment.clone());
+    let (memory_segment, offset, popping) = match command {
+        Command::Memory(MemoryCommandVariant::Push(memory_segment, offset)) => (memory_segment, offset, false),
+        Command::Memory(MemoryCommandVariant::Pop(memory_segment, offset)) => (memory_segment, offset, true),
+        _ => return,
+    };
+    match memory_segment {
+        MemorySegmentVariant::OffsetSegment(OffsetSegmentVariant::Pointer) => {
+            let pointer = if *offset == 0 {
+                PointerSegmentVariant::This
+            } else if *offset == 1 {
+                PointerSegmentVariant::That
+            } else {
+                panic!("expected offset for pointer to be either 0 or 1")
+            };
+            if popping {
+                subroutine_info.pointers_written.insert(pointer);
+            } else {
+                subroutine_info.pointers_read.insert(pointer);
             }
-            _ => {}
         }
+        MemorySegmentVariant::PointerSegment(segment) => {
+            subroutine_info.pointers_read.insert(segment.clone());
+        }
+        _ => {}
     }
 }
 
@@ -67,24 +88,59 @@ fn depth_first_search(caller_name: String, call_graph: &CallGraph, discovered: &
     }
 }
 
-fn analyse_pointer_usage(call_graph: &mut CallGraph, subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>) {
-    for subroutine in subroutines.values().flatten() {
-        let subroutine_info = call_graph
-            .get(&subroutine.name)
-            .unwrap_or_else(|| panic!("expected to find subroutine info for {}", subroutine.name));
+// A call to a subroutine can change the pointers which the call sets up for
+// it, the ones it writes itself, and whatever its own calls leave clobbered.
+// Of those, it restores the ones that any of its callers read, so the rest are
+// clobbered for its callers in turn. Calls can be recursive, so this goes
+// round until nothing changes - the sets only ever grow, so it gets there.
+fn analyse_pointer_usage(call_graph: &mut CallGraph) {
+    for subroutine_info in call_graph.values_mut() {
+        subroutine_info.pointers_set_by_call = [PointerSegmentVariant::Argument, PointerSegmentVariant::Local]
+            .into_iter()
+            .filter(|pointer| subroutine_info.pointers_read.contains(pointer))
+            .collect();
+    }
 
-        let reachable_subroutines = subroutines_reachable_from(&subroutine.name, call_graph);
+    let names: Vec<_> = call_graph.keys().cloned().collect();
+    for name in &names {
+        let reachable_subroutines = subroutines_reachable_from(name, call_graph);
+        call_graph.get_mut(name).unwrap().reachable_subroutines = reachable_subroutines;
+    }
 
-        let pointers_used = pointers_used_directly_by_subroutines(&reachable_subroutines, call_graph);
-        let pointers_used_directly_by_callers = pointers_used_directly_by_subroutines(&subroutine_info.callers, call_graph);
-        let pointers_to_restore = pointers_used.intersection(&pointers_used_directly_by_callers).cloned().collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for name in &names {
+            let subroutine_info = &call_graph[name];
+            let pointers_changed: HashSet<_> = subroutine_info
+                .pointers_set_by_call
+                .iter()
+                .chain(&subroutine_info.pointers_written)
+                .chain(subroutine_info.calls.iter().flat_map(|callee| &call_graph[callee].pointers_clobbered))
+                .cloned()
+                .collect();
+            let pointers_read_by_callers = pointers_read_by_subroutines(&subroutine_info.callers, call_graph);
+            let (pointers_to_restore, pointers_clobbered): (HashSet<_>, HashSet<_>) = pointers_changed
+                .into_iter()
+                .partition(|pointer| pointers_read_by_callers.contains(pointer));
 
-        let subroutine_info = call_graph
-            .get_mut(&subroutine.name)
-            .unwrap_or_else(|| panic!("expected to find subroutine info for {}", subroutine.name));
-        subroutine_info.reachable_subroutines = reachable_subroutines;
-        subroutine_info.pointers_used = pointers_used;
-        subroutine_info.pointers_to_restore = pointers_to_restore;
+            let subroutine_info = call_graph.get_mut(name).unwrap();
+            if pointers_clobbered != subroutine_info.pointers_clobbered || pointers_to_restore != subroutine_info.pointers_to_restore {
+                subroutine_info.pointers_clobbered = pointers_clobbered;
+                subroutine_info.pointers_to_restore = pointers_to_restore;
+                changed = true;
+            }
+        }
+    }
+}
+
+// The convention from the book, where calls always set ARG and LCL, and every
+// subroutine restores all four pointers.
+fn save_all_pointers(call_graph: &mut CallGraph) {
+    for subroutine_info in call_graph.values_mut() {
+        subroutine_info.pointers_set_by_call = [PointerSegmentVariant::Argument, PointerSegmentVariant::Local].into_iter().collect();
+        subroutine_info.pointers_to_restore = SAVED_CALLER_POINTER_ORDER.into_iter().collect();
+        subroutine_info.pointers_clobbered = HashSet::new();
     }
 }
 
@@ -94,34 +150,38 @@ fn subroutines_reachable_from(subroutine_name: &str, call_graph: &CallGraph) ->
     discovered
 }
 
-fn get_call_graph(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>) -> CallGraph {
+fn get_call_graph(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>, pointer_saving: PointerSaving) -> CallGraph {
     let mut call_graph = HashMap::new();
     for file_subroutines in subroutines.values() {
         for subroutine in file_subroutines {
+            call_graph.entry(subroutine.name.clone()).or_default();
             for SourcemappedCommand { command, .. } in &subroutine.commands {
                 include_in_call_graph(command, &subroutine.name, &mut call_graph);
-                record_directly_used_pointers(command, &subroutine.name, &mut call_graph);
+                record_pointer_accesses(command, &subroutine.name, &mut call_graph);
             }
         }
     }
-    analyse_pointer_usage(&mut call_graph, subroutines);
+    analyse_pointer_usage(&mut call_graph);
+    if pointer_saving == PointerSaving::All {
+        save_all_pointers(&mut call_graph);
+    }
     call_graph
 }
 
-fn pointers_used_directly_by_subroutines(subroutines: &HashSet<String>, call_graph: &CallGraph) -> HashSet<PointerSegmentVariant> {
+fn pointers_read_by_subroutines(subroutines: &HashSet<String>, call_graph: &CallGraph) -> HashSet<PointerSegmentVariant> {
     subroutines
         .iter()
-        .flat_map(|reachable_subroutine| {
-            let reachable_subroutine_info = call_graph
-                .get(reachable_subroutine)
-                .unwrap_or_else(|| panic!("expected to find subroutine info for {}", reachable_subroutine));
-            reachable_subroutine_info.pointers_used_directly.clone()
+        .flat_map(|subroutine| {
+            let subroutine_info = call_graph
+                .get(subroutine)
+                .unwrap_or_else(|| panic!("expected to find subroutine info for {}", subroutine));
+            subroutine_info.pointers_read.clone()
         })
         .collect()
 }
 
-pub fn analyse_call_graph(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>) -> CallGraphAnalysis {
-    let call_graph = get_call_graph(subroutines);
+pub fn analyse_call_graph(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>, pointer_saving: PointerSaving) -> CallGraphAnalysis {
+    let call_graph = get_call_graph(subroutines, pointer_saving);
 
     let live_subroutines = &call_graph
         .get("Sys.init")
@@ -146,3 +206,142 @@ pub fn analyse_call_graph(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>
         subroutine_info_by_name,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        jack_compiler::{codegen::Arithmetic, compile_jack},
+        utils::source_modules::SourceModule,
+    };
+
+    fn analyse(source: &str, pointer_saving: PointerSaving) -> HashMap<String, SubroutineInfo> {
+        let user_code = HashMap::from([(
+            PathBuf::from("Main.jack"),
+            SourceModule {
+                filename: "Main.jack".into(),
+                source: source.to_owned(),
+            },
+        )]);
+        analyse_call_graph(&compile_jack(user_code, Arithmetic::Wrapping).subroutines, pointer_saving).subroutine_info_by_name
+    }
+
+    fn pointers(pointers: &[PointerSegmentVariant]) -> HashSet<PointerSegmentVariant> {
+        pointers.iter().cloned().collect()
+    }
+
+    use PointerSegmentVariant::*;
+
+    // The case from game_of_life - Main.g doesn't read ARG itself, but calling
+    // Main.h sets ARG, so Main.g has to restore it for Main.f.
+    const PROGRAM: &str = "class Main {
+    function int f(int x) {
+        do Main.g();
+        return x;
+    }
+
+    function void g() {
+        do Main.h(1);
+        return;
+    }
+
+    function int h(int y) {
+        return y;
+    }
+
+    function void main() {
+        do Main.f(1);
+        return;
+    }
+}";
+
+    #[test]
+    fn test_calls_write_arg() {
+        let info = analyse(PROGRAM, PointerSaving::Analysed);
+        assert_eq!(info["Main.g"].pointers_read, pointers(&[]));
+        assert_eq!(info["Main.g"].pointers_set_by_call, pointers(&[]));
+        assert_eq!(info["Main.g"].pointers_to_restore, pointers(&[Argument]));
+        assert_eq!(info["Main.g"].pointers_clobbered, pointers(&[]));
+    }
+
+    #[test]
+    fn test_unread_pointers_are_not_restored() {
+        let info = analyse(PROGRAM, PointerSaving::Analysed);
+        assert_eq!(info["Main.h"].pointers_set_by_call, pointers(&[Argument]));
+        assert_eq!(info["Main.h"].pointers_to_restore, pointers(&[]));
+        assert_eq!(info["Main.h"].pointers_clobbered, pointers(&[Argument]));
+        assert_eq!(info["Main.f"].pointers_to_restore, pointers(&[]));
+    }
+
+    #[test]
+    fn test_pointers_written_by_pop_pointer() {
+        let info = analyse(
+            "class Main {
+    function void set(Array a) {
+        let a[0] = 1;
+        return;
+    }
+
+    function int get(Array a) {
+        do Main.set(a);
+        return a[0];
+    }
+
+    function void main() {
+        do Main.get(8000);
+        return;
+    }
+}",
+            PointerSaving::Analysed,
+        );
+        assert_eq!(info["Main.set"].pointers_read, pointers(&[Argument, That]));
+        assert_eq!(info["Main.set"].pointers_written, pointers(&[That]));
+        assert_eq!(info["Main.set"].pointers_to_restore, pointers(&[Argument, That]));
+        assert_eq!(info["Main.get"].pointers_to_restore, pointers(&[]));
+        assert_eq!(info["Main.get"].pointers_clobbered, pointers(&[Argument, That]));
+    }
+
+    // Whatever Main.down clobbers has to make its way back round to Main.up,
+    // however many times they go round the cycle.
+    #[test]
+    fn test_recursive_calls() {
+        let info = analyse(
+            "class Main {
+    function int up(int n) {
+        var int result;
+        let result = Main.down(n);
+        return result;
+    }
+
+    function int down(int n) {
+        if (n = 0) {
+            return Main.clobber(8000);
+        }
+        return Main.up(n - 1);
+    }
+
+    function int clobber(Array a) {
+        return a[0];
+    }
+
+    function void main() {
+        do Main.up(3);
+        return;
+    }
+}",
+            PointerSaving::Analysed,
+        );
+        assert_eq!(info["Main.down"].pointers_to_restore, pointers(&[Argument, Local]));
+        assert_eq!(info["Main.down"].pointers_clobbered, pointers(&[That]));
+        assert_eq!(info["Main.up"].pointers_clobbered, pointers(&[Local, That]));
+    }
+
+    #[test]
+    fn test_save_all_pointers() {
+        let info = analyse(PROGRAM, PointerSaving::All);
+        for subroutine_info in info.values() {
+            assert_eq!(subroutine_info.pointers_set_by_call, pointers(&[Argument, Local]));
+            assert_eq!(subroutine_info.pointers_to_restore, pointers(&[Local, Argument, This, That]));
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+};
+
+use serde::Serialize;
+
+use crate::jack_compiler::codegen::CompiledSubroutine;
+
+use super::{
+    call_graph_analyser::{analyse_call_graph, CallGraphAnalysis, SubroutineInfo},
+    codegen::{PointerSaving, SAVED_CALLER_POINTER_ORDER},
+    parser::PointerSegmentVariant,
+};
+
+// What the call graph analysis found out about a subroutine, for looking at
+// outside of the compiler.
+#[derive(Debug, Serialize)]
+pub struct ExportedSubroutine {
+    pub name: String,
+    // Whether Sys.init can reach it. Subroutines which aren't live are left out
+    // of the program.
+    pub live: bool,
+    pub vm_command_count: usize,
+    pub calls: Vec<String>,
+    pub callers: Vec<String>,
+    pub pointers_read: Vec<String>,
+    pub pointers_written: Vec<String>,
+    pub pointers_set_by_call: Vec<String>,
+    pub pointers_saved: Vec<String>,
+    pub pointers_clobbered: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CallGraphExport {
+    pub subroutines: Vec<ExportedSubroutine>,
+    // Subroutines which are called, but not defined anywhere.
+    pub undefined: Vec<String>,
+}
+
+fn pointer_names(pointers: &HashSet<PointerSegmentVariant>) -> Vec<String> {
+    SAVED_CALLER_POINTER_ORDER
+        .iter()
+        .filter(|pointer| pointers.contains(pointer))
+        .map(|pointer| pointer.to_string())
+        .collect()
+}
+
+fn sorted(names: &HashSet<String>) -> Vec<String> {
+    let mut names: Vec<_> = names.iter().cloned().collect();
+    names.sort();
+    names
+}
+
+pub fn export_call_graph(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>) -> CallGraphExport {
+    let CallGraphAnalysis {
+        live_subroutines,
+        subroutine_info_by_name,
+    } = analyse_call_graph(subroutines, PointerSaving::Analysed);
+
+    let vm_command_counts: BTreeMap<_, _> = subroutines
+        .values()
+        .flatten()
+        .map(|subroutine| (subroutine.name.clone(), subroutine.commands.len()))
+        .collect();
+
+    let exported: Vec<_> = vm_command_counts
+        .iter()
+        .map(|(name, &vm_command_count)| {
+            let SubroutineInfo {
+                calls,
+                callers,
+                pointers_read,
+                pointers_written,
+                pointers_set_by_call,
+                pointers_to_restore,
+                pointers_clobbered,
+                ..
+            } = &subroutine_info_by_name[name];
+            ExportedSubroutine {
+                name: name.clone(),
+                live: live_subroutines.contains(name),
+                vm_command_count,
+                calls: sorted(calls),
+                callers: sorted(callers),
+                pointers_read: pointer_names(pointers_read),
+                pointers_written: pointer_names(pointers_written),
+                pointers_set_by_call: pointer_names(pointers_set_by_call),
+                pointers_saved: pointer_names(pointers_to_restore),
+                pointers_clobbered: pointer_names(pointers_clobbered),
+            }
+        })
+        .collect();
+
+    let undefined = exported
+        .iter()
+        .flat_map(|subroutine| &subroutine.calls)
+        .filter(|callee| !vm_command_counts.contains_key(*callee))
+        .cloned()
+        .collect::<HashSet<_>>();
+
+    CallGraphExport {
+        subroutines: exported,
+        undefined: sorted(&undefined),
+    }
+}
+
+fn class_name(subroutine_name: &str) -> &str {
+    subroutine_name.split('.').next().unwrap_or(subroutine_name)
+}
+
+impl CallGraphExport {
+    // Graphviz, with a cluster for each class. Subroutines which aren't live
+    // are greyed out, along with their calls.
+    pub fn to_dot(&self) -> String {
+        let mut classes: BTreeMap<&str, Vec<&ExportedSubroutine>> = BTreeMap::new();
+        for subroutine in &self.subroutines {
+            classes.entry(class_name(&subroutine.name)).or_default().push(subroutine);
+        }
+
+        let mut lines = vec!["digraph call_graph {".to_string(), "  node [shape=box];".to_string()];
+        for (class_name, subroutines) in &classes {
+            lines.push(format!("  subgraph \"cluster_{}\" {{", class_name));
+            lines.push(format!("    label=\"{}\";", class_name));
+            for subroutine in subroutines {
+                let mut label = vec![subroutine.name.clone()];
+                if !subroutine.pointers_saved.is_empty() {
+                    label.push(format!("saves {}", subroutine.pointers_saved.join(" ")));
+                }
+                if !subroutine.pointers_clobbered.is_empty() {
+                    label.push(format!("clobbers {}", subroutine.pointers_clobbered.join(" ")));
+                }
+                let style = if subroutine.live {
+                    ""
+                } else {
+                    ", style=dashed, color=gray, fontcolor=gray"
+                };
+                lines.push(format!("    \"{}\" [label=\"{}\"{}];", subroutine.name, label.join("\\n"), style));
+            }
+            lines.push("  }".to_string());
+        }
+        for name in &self.undefined {
+            lines.push(format!("  \"{}\" [label=\"{}\\nundefined\", color=red, fontcolor=red];", name, name));
+        }
+        for subroutine in &self.subroutines {
+            let style = if subroutine.live { "" } else { " [color=gray]" };
+            for callee in &subroutine.calls {
+                lines.push(format!("  \"{}\" -> \"{}\"{};", subroutine.name, callee, style));
+            }
+        }
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        jack_compiler::{codegen::Arithmetic, compile_jack},
+        utils::source_modules::SourceModule,
+    };
+
+    fn export(source: &str) -> CallGraphExport {
+        let user_code = HashMap::from([(
+            PathBuf::from("Main.jack"),
+            SourceModule {
+                filename: "Main.jack".into(),
+                source: source.to_owned(),
+            },
+        )]);
+        export_call_graph(&compile_jack(user_code, Arithmetic::Wrapping).subroutines)
+    }
+
+    const PROGRAM: &str = "class Main {
+    function int f(int x) {
+        do Main.g();
+        return x;
+    }
+
+    function void g() {
+        do Main.h(1);
+        return;
+    }
+
+    function int h(int y) {
+        return y;
+    }
+
+    function void unused() {
+        do Main.missing();
+        return;
+    }
+
+    function void main() {
+        do Main.f(1);
+        return;
+    }
+}";
+
+    #[test]
+    fn test_export() {
+        let export = export(PROGRAM);
+        let subroutine = |name: &str| export.subroutines.iter().find(|subroutine| subroutine.name == name).unwrap();
+        assert!(subroutine("Main.g").live);
+        assert_eq!(subroutine("Main.g").calls, vec!["Main.h"]);
+        assert_eq!(subroutine("Main.g").callers, vec!["Main.f"]);
+        assert_eq!(subroutine("Main.g").pointers_saved, vec!["ARG"]);
+        assert_eq!(subroutine("Main.h").pointers_clobbered, vec!["ARG"]);
+        assert!(!subroutine("Main.unused").live);
+        assert_eq!(export.undefined, vec!["Main.missing"]);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = export(PROGRAM).to_dot();
+        assert!(dot.starts_with("digraph call_graph {\n"));
+        assert!(dot.contains("  subgraph \"cluster_Main\" {\n    label=\"Main\";\n"));
+        assert!(dot.contains("    \"Main.g\" [label=\"Main.g\\nsaves ARG\"];\n"));
+        assert!(dot.contains("    \"Main.h\" [label=\"Main.h\\nclobbers ARG\"];\n"));
+        assert!(dot.contains("    \"Main.unused\" [label=\"Main.unused\", style=dashed, color=gray, fontcolor=gray];\n"));
+        assert!(dot.contains("  \"Main.missing\" [label=\"Main.missing\\nundefined\", color=red, fontcolor=red];\n"));
+        assert!(dot.contains("  \"Main.f\" -> \"Main.g\";\n"));
+        assert!(dot.contains("  \"Main.unused\" -> \"Main.missing\" [color=gray];\n"));
+    }
+}
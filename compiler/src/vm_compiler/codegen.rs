@@ -4,12 +4,13 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use emulator_core::{memory_layout::STACK_BASE, ram_image::RamImage};
 use serde::Serialize;
 use ts_rs::TS;
 
 use crate::{
     assembler::parser::{ASMInstruction, AValue},
-    fonts::glyphs_asm,
+    fonts::{glyphs_asm, glyphs_ram_image},
     jack_compiler::codegen::{CompiledSubroutine, SourcemappedCommand},
 };
 
@@ -29,7 +30,7 @@ use super::{
     sourcemap::SourceMap,
 };
 
-const SAVED_CALLER_POINTER_ORDER: [PointerSegmentVariant; 4] = [
+pub(super) const SAVED_CALLER_POINTER_ORDER: [PointerSegmentVariant; 4] = [
     PointerSegmentVariant::Local,
     PointerSegmentVariant::Argument,
     PointerSegmentVariant::This,
@@ -63,11 +64,24 @@ fn holding_pattern() -> Vec<ASMInstruction> {
     ]
 }
 
+// Once the glyphs have been written, nothing else should write to the
+// read-only regions of memory, so from here on doing so is a fault.
+fn arm_write_protection() -> Vec<ASMInstruction> {
+    vec![
+        ASMInstruction::A(AValue::Symbolic("WRITE_PROTECT".to_string())),
+        ASMInstruction::C {
+            expr: "1".to_string(),
+            dest: Some("M".to_string()),
+            jump: None,
+        },
+    ]
+}
+
 fn init_call_stack() -> Vec<ASMInstruction> {
     vec![
         // For each stack frame, ARG points to the base of the frame. This is the
         // first stack frame, so here ARG points to the base of the entire stack.
-        ASMInstruction::A(AValue::Numeric("256".to_string())),
+        ASMInstruction::A(AValue::Numeric(STACK_BASE.to_string())),
         ASMInstruction::C {
             expr: "A".to_string(),
             dest: Some("D".to_string()),
@@ -80,7 +94,7 @@ fn init_call_stack() -> Vec<ASMInstruction> {
             jump: None,
         },
         // Initialize the stack pointer.
-        ASMInstruction::A(AValue::Numeric((256 + 1).to_string())),
+        ASMInstruction::A(AValue::Numeric((STACK_BASE + 1).to_string())),
         ASMInstruction::C {
             expr: "A".to_string(),
             dest: Some("D".to_string()),
@@ -93,7 +107,7 @@ fn init_call_stack() -> Vec<ASMInstruction> {
             jump: None,
         },
         // LCL starts off pointing to the same address as the stack pointer.
-        ASMInstruction::A(AValue::Numeric((256 + 1).to_string())),
+        ASMInstruction::A(AValue::Numeric((STACK_BASE + 1).to_string())),
         ASMInstruction::C {
             expr: "A".to_string(),
             dest: Some("D".to_string()),
@@ -113,7 +127,7 @@ fn init_call_stack() -> Vec<ASMInstruction> {
             dest: Some("D".to_string()),
             jump: None,
         },
-        ASMInstruction::A(AValue::Numeric("256".to_string())),
+        ASMInstruction::A(AValue::Numeric(STACK_BASE.to_string())),
         ASMInstruction::C {
             expr: "D".to_string(),
             dest: Some("M".to_string()),
@@ -457,12 +471,17 @@ struct CodeGenerator {
     current_function: Option<String>,
 }
 
+// The assembly symbol for a static, which the assembler gives an address.
+pub fn static_symbol(filename: &Path, index: usize) -> String {
+    format!("{}.{}", filename.to_str().unwrap(), index)
+}
+
 impl CodeGenerator {
     fn pop_into_static_memory_segment(&self, index: u16, filename: &Path) -> Vec<ASMInstruction> {
         vec![
             pop_into_d_register(),
             vec![
-                ASMInstruction::A(AValue::Symbolic(format!("{}.{}", filename.to_str().unwrap(), index))),
+                ASMInstruction::A(AValue::Symbolic(static_symbol(filename, index as usize))),
                 ASMInstruction::C {
                     expr: "D".to_string(),
                     dest: Some("M".to_string()),
@@ -478,7 +497,7 @@ impl CodeGenerator {
     fn push_from_static(&self, index: u16, filename: &Path) -> Vec<ASMInstruction> {
         vec![
             vec![
-                ASMInstruction::A(AValue::Symbolic(format!("{}.{}", filename.to_str().unwrap(), index))),
+                ASMInstruction::A(AValue::Symbolic(static_symbol(filename, index as usize))),
                 ASMInstruction::C {
                     expr: "M".to_string(),
                     dest: Some("D".to_string()),
@@ -721,7 +740,7 @@ impl CodeGenerator {
         .flatten()
         .collect();
 
-        if subroutine_info.pointers_used.contains(&PointerSegmentVariant::Argument) {
+        if subroutine_info.pointers_set_by_call.contains(&PointerSegmentVariant::Argument) {
             // Set arg pointer - at this point, all the arguments have been pushed to the stack,
             // plus the return address, plus the saved caller pointers.
             // So to find the correct position for ARG, we can count back from the stack pointer.
@@ -749,7 +768,7 @@ impl CodeGenerator {
             ])
         }
 
-        if subroutine_info.pointers_used.contains(&PointerSegmentVariant::Local) {
+        if subroutine_info.pointers_set_by_call.contains(&PointerSegmentVariant::Local) {
             // set lcl pointer
             instructions.extend(vec![
                 ASMInstruction::A(AValue::Symbolic("SP".to_string())),
@@ -992,6 +1011,31 @@ impl CodeGenerator {
     }
 }
 
+// How data that has to be in RAM before the program starts, like the glyphs,
+// gets there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RamInit {
+    // Loaded straight into RAM from the RAM image when the computer starts.
+    #[default]
+    Image,
+    // Written into RAM one word at a time by the boot code. This costs ROM and
+    // startup time, but doesn't need any way of loading a RAM image, which
+    // isn't always available on the FPGA.
+    Instructions,
+}
+
+// Which of the caller's pointers get saved and restored around calls.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PointerSaving {
+    // Only the ones that the call graph analysis says a caller might rely on
+    // after the call has changed them.
+    #[default]
+    Analysed,
+    // All of them, on every call. This is slower and bigger, but doesn't
+    // depend on the analysis being right.
+    All,
+}
+
 #[derive(Default, Serialize, TS)]
 #[ts(export)]
 #[ts(export_to = "../web/bindings/")]
@@ -999,22 +1043,46 @@ pub struct VMCompilerResult {
     pub sourcemap: SourceMap,
     #[ts(type = "Array<string>")]
     pub instructions: Vec<ASMInstruction>,
+    #[ts(type = "{ segments: Array<{ address: number, words: Array<number> }> }")]
+    pub ram_image: RamImage,
+    // The caller's pointers which each subroutine saves, in the order they're
+    // pushed after the return address. This is what a debugger needs to find
+    // its way back up the stack.
+    pub saved_pointers: HashMap<String, Vec<String>>,
 }
 
-pub fn generate_asm(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>) -> VMCompilerResult {
+pub fn generate_asm(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>, ram_init: RamInit, pointer_saving: PointerSaving) -> VMCompilerResult {
     let CallGraphAnalysis {
         live_subroutines,
         subroutine_info_by_name,
-    } = analyse_call_graph(subroutines);
+    } = analyse_call_graph(subroutines, pointer_saving);
 
     let mut sourcemap = SourceMap::new();
     let mut code_generator = CodeGenerator::default();
     let mut instructions: Vec<_> = holding_pattern();
 
+    let saved_pointers = live_subroutines
+        .iter()
+        .filter_map(|name| {
+            let subroutine_info = subroutine_info_by_name.get(name)?;
+            let pointers = SAVED_CALLER_POINTER_ORDER
+                .iter()
+                .filter(|pointer| subroutine_info.pointers_to_restore.contains(pointer))
+                .map(|pointer| pointer.to_string())
+                .collect();
+            Some((name.clone(), pointers))
+        })
+        .collect();
+
+    let mut ram_image = RamImage::default();
     if live_subroutines.contains("Output.getGlyph") {
-        instructions.extend(glyphs_asm());
+        match ram_init {
+            RamInit::Image => ram_image = glyphs_ram_image(),
+            RamInit::Instructions => instructions.extend(glyphs_asm()),
+        }
     }
 
+    instructions.extend(arm_write_protection());
     instructions.extend(init_call_stack());
 
     for (filename, file_subroutines) in subroutines {
@@ -1024,6 +1092,7 @@ pub fn generate_asm(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>) ->
             commands,
             locals_count,
             arg_count,
+            ..
         } in file_subroutines.iter()
         {
             for SourcemappedCommand { command, .. } in commands {
@@ -1037,5 +1106,10 @@ pub fn generate_asm(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>) ->
             }
         }
     }
-    VMCompilerResult { sourcemap, instructions }
+    VMCompilerResult {
+        sourcemap,
+        instructions,
+        ram_image,
+        saved_pointers,
+    }
 }
@@ -1,4 +1,6 @@
 mod call_graph_analyser;
+pub mod call_graph_export;
 pub mod codegen;
 pub mod parser;
 mod sourcemap;
+pub mod stack_depth;
@@ -0,0 +1,445 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    iter,
+    path::PathBuf,
+};
+
+use emulator_core::memory_layout::{HEAP_BASE, STACK_BASE};
+
+use crate::jack_compiler::codegen::{CompiledSubroutine, SourcemappedCommand};
+
+use super::{
+    call_graph_analyser::{analyse_call_graph, CallGraphAnalysis},
+    codegen::PointerSaving,
+    parser::{ArithmeticCommandVariant, Command, FlowCommandVariant, FunctionCommandVariant, MemoryCommandVariant},
+};
+
+// The stack runs from its base up to the heap, and nothing stops it carrying
+// on into the heap.
+pub const STACK_SIZE: usize = HEAP_BASE - STACK_BASE;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubroutineStackDepth {
+    // The return address, saved pointers, locals and the most that the working
+    // stack holds, not counting any calls.
+    pub frame_size: usize,
+    // The most stack that a call to the subroutine can take, from its return
+    // address up, going through each subroutine at most once on the way down.
+    pub max_depth: usize,
+    // The calls which get to max_depth, starting with the subroutine itself.
+    pub deepest_path: Vec<String>,
+    // Whether the subroutine can end up in a recursive call, in which case
+    // max_depth is only what it takes for one time round.
+    pub recursive: bool,
+}
+
+#[derive(Debug)]
+pub struct StackDepthReport {
+    pub subroutines: BTreeMap<String, SubroutineStackDepth>,
+    // Subroutines which can call themselves, with the others they can do it
+    // through.
+    pub cycles: Vec<Vec<String>>,
+    // The file each subroutine is in.
+    pub filenames: HashMap<String, PathBuf>,
+}
+
+// How a vm command changes the height of the working stack.
+fn stack_effect(command: &Command) -> i32 {
+    match command {
+        Command::Memory(MemoryCommandVariant::Push(..)) => 1,
+        Command::Memory(MemoryCommandVariant::Pop(..)) => -1,
+        Command::Arithmetic(ArithmeticCommandVariant::Binary(_)) => -1,
+        Command::Arithmetic(ArithmeticCommandVariant::Unary(_)) => 0,
+        Command::Flow(FlowCommandVariant::IfGoTo(_)) => -1,
+        Command::Flow(_) => 0,
+        // The return value takes the place of the arguments.
+        Command::Function(FunctionCommandVariant::Call(_, arg_count)) => 1 - *arg_count as i32,
+        Command::Function(FunctionCommandVariant::ReturnFrom) => -1,
+        Command::Function(FunctionCommandVariant::Define(..)) => 0,
+    }
+}
+
+struct Frame {
+    frame_size: usize,
+    // The words below the callee's return address at each call - the locals,
+    // and the working stack including the callee's arguments.
+    calls: Vec<(String, usize)>,
+}
+
+// The jack compiler keeps statements balanced, so working through the
+// commands in order gives the height of the working stack at each one.
+fn frame(subroutine: &CompiledSubroutine, saved_pointer_count: usize) -> Frame {
+    let base = 1 + saved_pointer_count + subroutine.locals_count;
+    let mut height = 0;
+    let mut max_height = 0;
+    let mut calls = Vec::new();
+    for SourcemappedCommand { command, .. } in &subroutine.commands {
+        if let Command::Function(FunctionCommandVariant::Call(callee, _)) = command {
+            calls.push((callee.clone(), base + height.max(0) as usize));
+        }
+        height += stack_effect(command);
+        max_height = max_height.max(height);
+    }
+    Frame {
+        frame_size: base + max_height as usize,
+        calls,
+    }
+}
+
+struct Analysis<'a> {
+    frames: HashMap<String, Frame>,
+    cyclic: &'a HashSet<String>,
+    // Subroutines which can't reach a cycle need the same amount of stack
+    // whichever way they're reached, so they only need working out once.
+    memo: HashMap<String, SubroutineStackDepth>,
+}
+
+impl Analysis<'_> {
+    fn depth(&mut self, name: &str, path: &mut Vec<String>) -> SubroutineStackDepth {
+        if let Some(depth) = self.memo.get(name) {
+            return depth.clone();
+        }
+        let Some(frame) = self.frames.get(name) else {
+            // Calls to subroutines which don't exist won't get through the
+            // assembler anyway.
+            return SubroutineStackDepth {
+                frame_size: 0,
+                max_depth: 0,
+                deepest_path: vec![name.to_string()],
+                recursive: false,
+            };
+        };
+        let frame_size = frame.frame_size;
+        let calls = frame.calls.clone();
+
+        path.push(name.to_string());
+        let mut result = SubroutineStackDepth {
+            frame_size,
+            max_depth: frame_size,
+            deepest_path: vec![name.to_string()],
+            recursive: self.cyclic.contains(name),
+        };
+        for (callee, below) in calls {
+            if path.contains(&callee) {
+                continue;
+            }
+            let callee_depth = self.depth(&callee, path);
+            result.recursive |= callee_depth.recursive;
+            if below + callee_depth.max_depth > result.max_depth {
+                result.max_depth = below + callee_depth.max_depth;
+                result.deepest_path = iter::once(name.to_string()).chain(callee_depth.deepest_path).collect();
+            }
+        }
+        path.pop();
+
+        if !self.cyclic.contains(name) {
+            self.memo.insert(name.to_string(), result.clone());
+        }
+        result
+    }
+}
+
+// Tarjan's algorithm, keeping the strongly connected components which have a
+// cycle in them.
+fn find_cycles(calls: &BTreeMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    struct State<'a> {
+        calls: &'a BTreeMap<String, Vec<String>>,
+        index: HashMap<&'a str, usize>,
+        low_link: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: HashSet<&'a str>,
+        cycles: Vec<Vec<String>>,
+    }
+
+    fn visit<'a>(state: &mut State<'a>, name: &'a str) {
+        let idx = state.index.len();
+        state.index.insert(name, idx);
+        state.low_link.insert(name, idx);
+        state.stack.push(name);
+        state.on_stack.insert(name);
+        let calls = state.calls;
+        for callee in calls.get(name).into_iter().flatten() {
+            if !calls.contains_key(callee) {
+                continue;
+            }
+            if !state.index.contains_key(callee.as_str()) {
+                visit(state, callee);
+                let low_link = state.low_link[name].min(state.low_link[callee.as_str()]);
+                state.low_link.insert(name, low_link);
+            } else if state.on_stack.contains(callee.as_str()) {
+                let low_link = state.low_link[name].min(state.index[callee.as_str()]);
+                state.low_link.insert(name, low_link);
+            }
+        }
+        if state.low_link[name] == state.index[name] {
+            let mut component = Vec::new();
+            while let Some(member) = state.stack.pop() {
+                state.on_stack.remove(member);
+                component.push(member.to_string());
+                if member == name {
+                    break;
+                }
+            }
+            let calls_itself = calls[name].iter().any(|callee| callee == name);
+            if component.len() > 1 || calls_itself {
+                component.sort();
+                state.cycles.push(component);
+            }
+        }
+    }
+
+    let mut state = State {
+        calls,
+        index: HashMap::new(),
+        low_link: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        cycles: Vec::new(),
+    };
+    for name in calls.keys() {
+        if !state.index.contains_key(name.as_str()) {
+            visit(&mut state, name);
+        }
+    }
+    state.cycles.sort();
+    state.cycles
+}
+
+pub fn analyse_stack_depth(subroutines: &HashMap<PathBuf, Vec<CompiledSubroutine>>, pointer_saving: PointerSaving) -> StackDepthReport {
+    let CallGraphAnalysis {
+        live_subroutines,
+        subroutine_info_by_name,
+    } = analyse_call_graph(subroutines, pointer_saving);
+
+    let live: Vec<_> = subroutines
+        .values()
+        .flatten()
+        .filter(|subroutine| live_subroutines.contains(&subroutine.name))
+        .collect();
+    let frames: HashMap<_, _> = live
+        .iter()
+        .map(|subroutine| {
+            let saved_pointer_count = subroutine_info_by_name[&subroutine.name].pointers_to_restore.len();
+            (subroutine.name.clone(), frame(subroutine, saved_pointer_count))
+        })
+        .collect();
+    let calls: BTreeMap<_, _> = frames
+        .iter()
+        .map(|(name, frame)| (name.clone(), frame.calls.iter().map(|(callee, _)| callee.clone()).collect()))
+        .collect();
+    let cycles = find_cycles(&calls);
+    let cyclic: HashSet<_> = cycles.iter().flatten().cloned().collect();
+
+    let mut analysis = Analysis {
+        frames,
+        cyclic: &cyclic,
+        memo: HashMap::new(),
+    };
+    let depths = calls.keys().map(|name| (name.clone(), analysis.depth(name, &mut Vec::new()))).collect();
+    let filenames = subroutines
+        .iter()
+        .flat_map(|(filename, subroutines)| subroutines.iter().map(move |subroutine| (subroutine.name.clone(), filename.clone())))
+        .collect();
+
+    StackDepthReport {
+        subroutines: depths,
+        cycles,
+        filenames,
+    }
+}
+
+impl StackDepthReport {
+    pub fn entry_point(&self) -> Option<&SubroutineStackDepth> {
+        self.subroutines.get("Sys.init")
+    }
+
+    // Only for recursion which goes through the program's own files, as
+    // there's nothing to be done about recursion in the std_lib.
+    pub fn warnings(&self, user_filenames: &HashSet<PathBuf>) -> Vec<String> {
+        self.cycles
+            .iter()
+            .filter(|cycle| {
+                cycle
+                    .iter()
+                    .any(|name| self.filenames.get(name).map_or(false, |filename| user_filenames.contains(filename)))
+            })
+            .map(|cycle| {
+                format!(
+                    "the stack depth can't be worked out for recursion through {}, so it only counts one time round",
+                    cycle.join(", ")
+                )
+            })
+            .collect()
+    }
+
+    // Even one time round any recursion, the stack can run into the heap.
+    pub fn error(&self) -> Option<String> {
+        let entry_point = self.entry_point()?;
+        (entry_point.max_depth > STACK_SIZE).then(|| {
+            format!(
+                "the program can need {} words of stack, but there are only {} before the heap (via {})",
+                entry_point.max_depth,
+                STACK_SIZE,
+                entry_point.deepest_path.join(" -> ")
+            )
+        })
+    }
+
+    // The deepest subroutines first.
+    pub fn format(&self) -> String {
+        let mut subroutines: Vec<_> = self.subroutines.iter().collect();
+        subroutines.sort_by_key(|(name, depth)| (std::cmp::Reverse(depth.max_depth), name.to_string()));
+        let mut lines = Vec::new();
+        if let Some(entry_point) = self.entry_point() {
+            lines.push(format!(
+                "stack: {} of {} words{} via {}",
+                entry_point.max_depth,
+                STACK_SIZE,
+                if entry_point.recursive { " (per level of recursion)" } else { "" },
+                entry_point.deepest_path.join(" -> ")
+            ));
+        }
+        lines.push(format!("{:>9} {:>6}  subroutine", "max depth", "frame"));
+        for (name, depth) in subroutines {
+            lines.push(format!(
+                "{:>9} {:>6}  {}{}",
+                depth.max_depth,
+                depth.frame_size,
+                name,
+                if depth.recursive { " (recursive)" } else { "" }
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        jack_compiler::{codegen::Arithmetic, compile_jack},
+        utils::source_modules::SourceModule,
+    };
+
+    fn analyse(source: &str) -> StackDepthReport {
+        let user_code = HashMap::from([(
+            PathBuf::from("Main.jack"),
+            SourceModule {
+                filename: "Main.jack".into(),
+                source: source.to_owned(),
+            },
+        )]);
+        analyse_stack_depth(&compile_jack(user_code, Arithmetic::Wrapping).subroutines, PointerSaving::Analysed)
+    }
+
+    #[test]
+    fn test_frames_and_calls() {
+        let report = analyse(
+            "class Main {
+    function int add(int x, int y) {
+        var int result;
+        let result = x + y;
+        return result;
+    }
+
+    function int f(int x) {
+        return 1 + Main.add(x, 2);
+    }
+
+    function void main() {
+        do Main.f(1);
+        return;
+    }
+}",
+        );
+        // The return address, ARG saved for Main.f, the local, and x and y on
+        // the working stack.
+        let add = &report.subroutines["Main.add"];
+        assert_eq!(add.frame_size, 1 + 1 + 1 + 2);
+        assert_eq!(add.max_depth, add.frame_size);
+        assert!(!add.recursive);
+        // Main.f has its return address, and 1, x and 2 on the working stack
+        // when it calls Main.add.
+        let f = &report.subroutines["Main.f"];
+        assert_eq!(f.max_depth, 1 + 3 + add.max_depth);
+        assert_eq!(f.deepest_path, vec!["Main.f", "Main.add"]);
+        assert!(report.cycles.iter().all(|cycle| !cycle.contains(&"Main.f".to_string())));
+    }
+
+    #[test]
+    fn test_recursion() {
+        let report = analyse(
+            "class Main {
+    function int up(int n) {
+        return Main.down(n);
+    }
+
+    function int down(int n) {
+        if (n = 0) {
+            return 0;
+        }
+        return Main.up(n - 1);
+    }
+
+    function int countdown(int n) {
+        if (n = 0) {
+            return 0;
+        }
+        return Main.countdown(n - 1);
+    }
+
+    function void main() {
+        do Main.up(3);
+        do Main.countdown(3);
+        return;
+    }
+}",
+        );
+        assert!(report.cycles.contains(&vec!["Main.down".to_string(), "Main.up".to_string()]));
+        assert!(report.cycles.contains(&vec!["Main.countdown".to_string()]));
+        assert!(report.subroutines["Main.main"].recursive);
+        assert_eq!(report.subroutines["Main.up"].deepest_path, vec!["Main.up", "Main.down"]);
+        let warnings = report.warnings(&HashSet::from([PathBuf::from("Main.jack")]));
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("recursion through"));
+    }
+
+    #[test]
+    fn test_std_lib_recursion() {
+        let report = analyse(
+            "class Main {
+    function void main() {
+        do Main.half(10);
+        return;
+    }
+
+    function int half(int x) {
+        return x / 2;
+    }
+}",
+        );
+        assert!(report.cycles.iter().any(|cycle| cycle.contains(&"Math.divide".to_string())));
+        assert!(report.warnings(&HashSet::from([PathBuf::from("Main.jack")])).is_empty());
+        // Unless the program has its own Math.jack.
+        assert!(!report.warnings(&HashSet::from([PathBuf::from("Math.jack")])).is_empty());
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let locals: Vec<_> = (0..STACK_SIZE).map(|idx| format!("l{}", idx)).collect();
+        let report = analyse(&format!(
+            "class Main {{
+    function void main() {{
+        var int {};
+        return;
+    }}
+}}",
+            locals.join(", ")
+        ));
+        assert!(report.entry_point().unwrap().max_depth > STACK_SIZE);
+        assert!(report
+            .error()
+            .unwrap()
+            .contains(&format!("words of stack, but there are only {}", STACK_SIZE)));
+    }
+}
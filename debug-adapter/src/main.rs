@@ -0,0 +1,28 @@
+// A debug adapter protocol server, so that jack programs can be debugged from
+// editors like VS Code. The editor starts it and talks to it over stdin and
+// stdout.
+mod protocol;
+mod session;
+
+use std::{
+    io::{self, BufReader},
+    sync::mpsc,
+    thread,
+};
+
+use session::Session;
+
+fn main() {
+    // Requests are read on their own thread so that the session can check for
+    // new ones (like pause) in between running batches of instructions.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = BufReader::new(io::stdin());
+        while let Some(message) = protocol::read_message(&mut stdin).unwrap_or_else(|err| panic!("failed to read request: {}", err)) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    Session::new(io::stdout()).run(receiver);
+}
@@ -0,0 +1,702 @@
+// Handles requests from the editor, and runs the program in between them.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, TryRecvError},
+};
+
+use emulator_core::{
+    breakpoints::Breakpoint,
+    computer::Computer,
+    debug_info::{format_value, DebugInfo, Variable},
+    debugger::{Debugger, RunMode, StepKind, StopReason},
+    generate_rom,
+    image_format::{self, ImageFormat},
+    memory_layout::{ARG, LCL, SP, THAT, THIS},
+};
+use serde_json::{json, Value};
+
+use crate::protocol::write_message;
+
+// How many instructions to run before checking for new requests.
+const TICKS_PER_BATCH: usize = 100_000;
+
+// Each stack frame has three scopes, which are numbered consecutively to give
+// their variablesReference. Zero means no variables, so numbering starts at 1.
+const LOCALS_SCOPE: i64 = 0;
+const ARGUMENTS_SCOPE: i64 = 1;
+const STATICS_SCOPE: i64 = 2;
+const REGISTERS_SCOPE: i64 = 3;
+const SCOPES_PER_FRAME: i64 = 4;
+
+pub struct Session<W: Write> {
+    out: W,
+    seq: i64,
+    debugger: Option<Debugger>,
+    source_dir: Option<PathBuf>,
+    stop_on_entry: bool,
+    running: Option<RunMode>,
+}
+
+fn launch_debugger(args: &Value) -> Result<Debugger, String> {
+    let path_arg = |name: &str| args.get(name).and_then(Value::as_str).map(Path::new);
+    let program = path_arg("program").ok_or("launch requires a program, the debug output of the compiler")?;
+    let json = std::fs::read_to_string(program).map_err(|err| format!("failed to read {}: {}", program.display(), err))?;
+    let mut debug_info = DebugInfo::from_json(&json)?;
+    let format = match args.get("format").and_then(Value::as_str) {
+        Some(format) => Some(format.parse::<ImageFormat>()?),
+        None => None,
+    };
+    if let Some(path) = path_arg("ramImage") {
+        debug_info.ram_image = image_format::read(path, format)?;
+    }
+    // The rom is normally taken from the debug output, but can be given
+    // separately to check that it's what the debug output describes.
+    let rom = match path_arg("rom") {
+        Some(path) => generate_rom::from_image(&image_format::read(path, format)?),
+        None => return Ok(Debugger::new(debug_info)),
+    };
+    if rom[..debug_info.rom.len()] != debug_info.rom[..] {
+        return Err("the rom doesn't match the debug output".to_string());
+    }
+    let computer = Computer::with_ram_image(rom, &debug_info.ram_image);
+    Ok(Debugger::with_computer(debug_info, computer))
+}
+
+impl<W: Write> Session<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            seq: 0,
+            debugger: None,
+            source_dir: None,
+            stop_on_entry: false,
+            running: None,
+        }
+    }
+
+    // Runs until the editor disconnects.
+    pub fn run(mut self, requests: Receiver<Value>) {
+        loop {
+            let request = if self.running.is_some() {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return,
+                }
+            };
+            match request {
+                Some(request) => {
+                    if !self.handle(&request) {
+                        return;
+                    }
+                }
+                None => self.run_batch(),
+            }
+        }
+    }
+
+    fn send(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.out, &message).unwrap_or_else(|err| panic!("failed to write message: {}", err));
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) {
+        self.send(json!({"type": "event", "event": event, "body": body}));
+    }
+
+    fn send_response(&mut self, request: &Value, result: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+    }
+
+    fn send_stopped(&mut self, reason: &str, description: Option<String>) {
+        self.send_event(
+            "stopped",
+            json!({"reason": reason, "description": description, "threadId": 1, "allThreadsStopped": true}),
+        );
+    }
+
+    fn run_batch(&mut self) {
+        let (Some(mode), Some(debugger)) = (self.running, self.debugger.as_mut()) else {
+            return;
+        };
+        let stop_reason = debugger.run(mode, TICKS_PER_BATCH);
+        for log in debugger.take_logs() {
+            self.send_event("output", json!({"category": "console", "output": format!("{}\n", log)}));
+        }
+        let Some(stop_reason) = stop_reason else {
+            return;
+        };
+        self.running = None;
+        match stop_reason {
+            StopReason::Breakpoint => self.send_stopped("breakpoint", None),
+            StopReason::Step => self.send_stopped("step", None),
+            StopReason::Watchpoint { address, old, new } => {
+                self.send_stopped("data breakpoint", Some(format!("RAM[{}] changed from {} to {}", address, old, new)))
+            }
+            StopReason::Fault(fault) => {
+                // Faults often come from a trampled stack, so the stack as
+                // it is in RAM goes to the console, alongside the one from
+                // the call tracker.
+                let debugger = self.debugger();
+                let backtrace = debugger.backtrace().format(&debugger.debug_info, &debugger.computer);
+                self.send_event(
+                    "output",
+                    json!({"category": "stderr", "output": format!("computer faulted: {}\n{}\n", fault, backtrace)}),
+                );
+                self.send_stopped("exception", Some(fault));
+            }
+            StopReason::PointerNotRestored(violation) | StopReason::MemoryError(violation) | StopReason::ArithmeticError(violation) => {
+                self.send_stopped("exception", Some(violation))
+            }
+            StopReason::ConditionError(error) => self.send_stopped("breakpoint", Some(error)),
+            StopReason::Exited => {
+                if let Some(allocations) = self.debugger().allocations() {
+                    let report = allocations.leak_report();
+                    self.send_event("output", json!({"category": "console", "output": format!("{}\n", report)}));
+                }
+                self.send_event("exited", json!({"exitCode": 0}));
+                self.send_event("terminated", json!({}));
+            }
+        }
+    }
+
+    // Returns false once the editor has disconnected.
+    fn handle(&mut self, request: &Value) -> bool {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsHitConditionalBreakpoints": true,
+                "supportsLogPoints": true,
+            })),
+            "launch" => self.launch(args),
+            "disconnect" => {
+                self.send_response(request, Ok(json!({})));
+                return false;
+            }
+            _ => match self.debugger.is_some() {
+                true => self.handle_launched(command, args),
+                false => Err(format!("{} before launch", command)),
+            },
+        };
+        self.send_response(request, result);
+        if command == "launch" && self.debugger.is_some() {
+            // Breakpoints can only be set once the program has been loaded.
+            self.send_event("initialized", json!({}));
+        }
+        // Stopped events have to come after the response to the request that
+        // caused them.
+        match command {
+            "configurationDone" if self.stop_on_entry => self.send_stopped("entry", None),
+            "configurationDone" => self.running = Some(RunMode::Continue),
+            "pause" if self.debugger.is_some() => self.send_stopped("pause", None),
+            _ => {}
+        }
+        true
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let mut debugger = launch_debugger(args)?;
+        if args.get("checkPointers").and_then(Value::as_bool).unwrap_or(false) {
+            debugger.check_pointers();
+        }
+        if args.get("trackAllocations").and_then(Value::as_bool).unwrap_or(false) {
+            debugger.track_allocations();
+        }
+        if args.get("sanitizeMemory").and_then(Value::as_bool).unwrap_or(false) {
+            debugger.sanitize_memory();
+        }
+        debugger
+            .computer
+            .set_stack_checking(args.get("checkStack").and_then(Value::as_bool).unwrap_or(false));
+        self.debugger = Some(debugger);
+        self.source_dir = args.get("sourceDir").and_then(Value::as_str).map(PathBuf::from);
+        self.stop_on_entry = args.get("stopOnEntry").and_then(Value::as_bool).unwrap_or(false);
+        Ok(json!({}))
+    }
+
+    fn handle_launched(&mut self, command: &str, args: &Value) -> Result<Value, String> {
+        let step = |session: &mut Self, step_kind| {
+            session.running = Some(session.debugger().start_step(step_kind));
+            Ok(json!({}))
+        };
+        match command {
+            "configurationDone" => Ok(json!({})),
+            "threads" => Ok(json!({"threads": [{"id": 1, "name": "main"}]})),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(self.scopes(args)),
+            "variables" => Ok(self.variables(args)),
+            "source" => self.source(args),
+            "continue" => {
+                self.running = Some(RunMode::Continue);
+                Ok(json!({"allThreadsContinued": true}))
+            }
+            "next" => step(self, StepKind::Over),
+            "stepIn" => step(self, StepKind::In),
+            "stepOut" => step(self, StepKind::Out),
+            "pause" => {
+                self.running = None;
+                Ok(json!({}))
+            }
+            _ => Err(format!("unsupported request {}", command)),
+        }
+    }
+
+    fn debugger(&self) -> &Debugger {
+        self.debugger.as_ref().unwrap()
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        // Files are identified by name alone, as that's all the debug output
+        // knows about.
+        let filename = args["source"]["path"]
+            .as_str()
+            .and_then(|path| Path::new(path).file_name())
+            .or_else(|| args["source"]["name"].as_str().map(std::ffi::OsStr::new))
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // Breakpoints with conditions that don't parse are left out, and
+        // reported as unverified with the reason.
+        let mut results = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let Some(line) = breakpoint["line"].as_u64().map(|line| line as usize) else {
+                continue;
+            };
+            let text = |name: &str| breakpoint[name].as_str();
+            match Breakpoint::parse(text("condition"), text("hitCondition"), text("logMessage")) {
+                Ok(breakpoint) => {
+                    results.push((line, Ok(breakpoints.len())));
+                    breakpoints.push((line, breakpoint));
+                }
+                Err(err) => results.push((line, Err(err))),
+            }
+        }
+        let pcs = self.debugger.as_mut().unwrap().set_conditional_line_breakpoints(&filename, breakpoints);
+        let breakpoints: Vec<_> = results
+            .into_iter()
+            .map(|(line, result)| match result {
+                Ok(idx) => json!({"verified": pcs[idx].is_some(), "line": line}),
+                Err(message) => json!({"verified": false, "line": line, "message": message}),
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn source_json(&self, filename: &str) -> Value {
+        let path = self.source_dir.as_ref().map(|dir| dir.join(filename)).filter(|path| path.exists());
+        match path {
+            Some(path) => json!({"name": filename, "path": path}),
+            None => {
+                let idx = self.debugger().debug_info.filenames().iter().position(|name| name == filename);
+                json!({"name": filename, "sourceReference": idx.map_or(0, |idx| idx + 1)})
+            }
+        }
+    }
+
+    fn stack_trace(&self) -> Value {
+        let debugger = self.debugger();
+        let frames: Vec<_> = debugger
+            .stack()
+            .iter()
+            .enumerate()
+            .map(|(idx, (frame, pc))| {
+                let subroutine = &debugger.debug_info.subroutines[frame.subroutine_idx];
+                let mut frame_json = json!({"id": idx, "name": subroutine.name, "line": 0, "column": 0});
+                if let Some(location) = debugger.debug_info.location(*pc) {
+                    frame_json["source"] = self.source_json(&location.filename);
+                    frame_json["line"] = json!(location.line);
+                    frame_json["column"] = json!(location.column);
+                }
+                frame_json
+            })
+            .collect();
+        json!({"stackFrames": frames, "totalFrames": frames.len()})
+    }
+
+    fn scopes(&self, args: &Value) -> Value {
+        let frame_id = args["frameId"].as_i64().unwrap_or_default();
+        let scope = |name: &str, kind| json!({"name": name, "variablesReference": frame_id * SCOPES_PER_FRAME + kind + 1, "expensive": false});
+        let mut scopes = vec![
+            scope("Locals", LOCALS_SCOPE),
+            scope("Arguments", ARGUMENTS_SCOPE),
+            scope("Statics", STATICS_SCOPE),
+        ];
+        // Registers are only known for the innermost frame.
+        if frame_id == 0 {
+            scopes.push(scope("Registers", REGISTERS_SCOPE));
+        }
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&self, args: &Value) -> Value {
+        let debugger = self.debugger();
+        let reference = args["variablesReference"].as_i64().unwrap_or_default() - 1;
+        let (frame_id, kind) = (reference / SCOPES_PER_FRAME, reference % SCOPES_PER_FRAME);
+        let stack = debugger.stack();
+        let Some((frame, _)) = stack.get(frame_id as usize) else {
+            return json!({"variables": []});
+        };
+        let subroutine = &debugger.debug_info.subroutines[frame.subroutine_idx];
+        let ram = debugger.computer.ram.lock();
+        let variable = |name: &str, type_name: &str, value: u16| json!({"name": name, "type": type_name, "value": format_value(type_name, value), "variablesReference": 0});
+        let segment = |variables: &[Variable], base: u16| {
+            variables
+                .iter()
+                .map(|var| variable(&var.name, &var.type_name, ram[(base as usize + var.index) % ram.len()]))
+                .collect::<Vec<_>>()
+        };
+        let variables = match kind {
+            LOCALS_SCOPE => segment(&subroutine.locals, frame.lcl),
+            ARGUMENTS_SCOPE => segment(&subroutine.arguments, frame.arg),
+            STATICS_SCOPE => debugger
+                .debug_info
+                .class_layout(subroutine.class_name())
+                .map(|class_layout| {
+                    class_layout
+                        .statics
+                        .iter()
+                        .filter_map(|var| Some(variable(&var.name, &var.type_name, ram[var.address? as usize])))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            _ => {
+                let cpu = &debugger.computer.cpu;
+                let mut registers = vec![
+                    variable("A", "int", cpu.reg_a),
+                    variable("D", "int", cpu.reg_d),
+                    variable("PC", "int", cpu.pc),
+                ];
+                for (name, address) in [("SP", SP), ("LCL", LCL), ("ARG", ARG), ("THIS", THIS), ("THAT", THAT)] {
+                    registers.push(variable(name, "int", ram[address]));
+                }
+                registers
+            }
+        };
+        json!({ "variables": variables })
+    }
+
+    fn source(&self, args: &Value) -> Result<Value, String> {
+        let reference = args["sourceReference"].as_u64().unwrap_or_default() as usize;
+        let debug_info = &self.debugger().debug_info;
+        let source = reference
+            .checked_sub(1)
+            .and_then(|idx| debug_info.filenames().get(idx))
+            .and_then(|filename| debug_info.source(filename))
+            .ok_or("unknown source")?;
+        Ok(json!({"content": source, "mimeType": "text/x-jack"}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, iter};
+
+    use emulator_core::testing::TestProgram;
+
+    use super::*;
+    use crate::protocol::read_message;
+
+    const JUMP: u16 = 0b1110101010000111; // 0;JMP
+    const SET_M: u16 = 0b1110111010001000; // M=-1
+
+    // The same program as the debugger's tests. Sys.init is the boot code,
+    // which falls through into Main.main, which calls Main.helper and sets
+    // the word at 1000 before returning.
+    fn debugger() -> Debugger {
+        let mut debug_info = TestProgram::new()
+            .file("Sys.jack", "class Sys {\n  function void init() {\n    do Main.main();\n  }\n}\n")
+            .subroutine("Sys.init", 0, &[], &[(2, "function Sys.init 0"), (3, "call Main.main 0")])
+            .file(
+                "Main.jack",
+                "class Main {
+  function void main() {
+    var int x;
+    let x = Main.helper();
+    let x = x + 1;
+    return;
+  }
+
+  function int helper() {
+    return 1;
+  }
+}
+",
+            )
+            .subroutine(
+                "Main.main",
+                0,
+                &["x"],
+                &[
+                    (2, "function Main.main 1"),
+                    (4, "call Main.helper 0"),
+                    (4, "pop local 0"),
+                    (5, "push local 0"),
+                    (5, "push constant 1"),
+                    (5, "add"),
+                    (5, "pop local 0"),
+                    (6, "push constant 0"),
+                    (6, "return"),
+                ],
+            )
+            .subroutine(
+                "Main.helper",
+                0,
+                &[],
+                &[(9, "function Main.helper 0"), (10, "push constant 1"), (10, "return")],
+            )
+            .build();
+        let rom = &mut debug_info.rom;
+        rom[2] = 11;
+        rom[3] = JUMP;
+        rom[12] = 4;
+        rom[13] = JUMP;
+        rom[7] = 1000;
+        rom[8] = SET_M;
+        rom[9] = 0;
+        rom[10] = JUMP;
+        Debugger::new(debug_info)
+    }
+
+    // A session which has been launched with the program above, as launching
+    // needs the compiler's output on disk.
+    fn launched_session() -> Session<Vec<u8>> {
+        let mut session = Session::new(Vec::new());
+        session.debugger = Some(debugger());
+        session
+    }
+
+    // The messages written since this was last called.
+    fn messages(session: &mut Session<Vec<u8>>) -> Vec<Value> {
+        let mut reader = io::Cursor::new(std::mem::take(&mut session.out));
+        iter::from_fn(|| read_message(&mut reader).unwrap()).collect()
+    }
+
+    fn request(session: &mut Session<Vec<u8>>, command: &str, arguments: Value) -> Vec<Value> {
+        assert!(session.handle(&json!({"seq": 1, "type": "request", "command": command, "arguments": arguments})));
+        messages(session)
+    }
+
+    // The body of the response to a request, after checking that it succeeded.
+    fn response(session: &mut Session<Vec<u8>>, command: &str, arguments: Value) -> Value {
+        let messages = request(session, command, arguments);
+        assert_eq!(messages[0]["type"], "response");
+        assert_eq!(messages[0]["command"], command);
+        assert_eq!(messages[0]["success"], true, "{}", messages[0]);
+        messages[0]["body"].clone()
+    }
+
+    // Runs until the program stops, returning the events sent on the way.
+    fn run_until_stopped(session: &mut Session<Vec<u8>>) -> Vec<Value> {
+        while session.running.is_some() {
+            session.run_batch();
+        }
+        messages(session)
+    }
+
+    fn stopped(reason: &str, description: Option<&str>) -> Value {
+        json!({
+            "type": "event",
+            "event": "stopped",
+            "body": {"reason": reason, "description": description, "threadId": 1, "allThreadsStopped": true},
+        })
+    }
+
+    // Events without their sequence numbers, which depend on everything sent
+    // before them.
+    fn without_seq(mut messages: Vec<Value>) -> Vec<Value> {
+        for message in &mut messages {
+            message.as_object_mut().unwrap().remove("seq");
+        }
+        messages
+    }
+
+    #[test]
+    fn test_before_launch() {
+        let mut session = Session::new(Vec::new());
+        let body = response(&mut session, "initialize", json!({}));
+        assert_eq!(body["supportsConditionalBreakpoints"], true);
+        assert_eq!(body["supportsLogPoints"], true);
+
+        let messages = request(&mut session, "threads", json!({}));
+        assert_eq!(messages[0]["success"], false);
+        assert_eq!(messages[0]["message"], "threads before launch");
+
+        let messages = request(&mut session, "launch", json!({}));
+        assert_eq!(messages.len(), 1, "no initialized event after a failed launch");
+        assert_eq!(messages[0]["message"], "launch requires a program, the debug output of the compiler");
+        assert!(!session.handle(&json!({"seq": 2, "type": "request", "command": "disconnect"})));
+    }
+
+    #[test]
+    fn test_set_breakpoints() {
+        let mut session = launched_session();
+        let body = response(
+            &mut session,
+            "setBreakpoints",
+            json!({
+                "source": {"path": "/somewhere/Main.jack"},
+                "breakpoints": [{"line": 10}, {"line": 8}, {"line": 5, "hitCondition": "% 0"}],
+            }),
+        );
+        assert_eq!(
+            body["breakpoints"],
+            json!([
+                {"verified": true, "line": 10},
+                {"verified": false, "line": 8},
+                {"verified": false, "line": 5, "message": "hits can't be a multiple of 0"},
+            ])
+        );
+        assert_eq!(session.debugger().breakpoints(), &[12].into());
+    }
+
+    #[test]
+    fn test_stack_scopes_and_variables() {
+        let mut session = launched_session();
+        response(
+            &mut session,
+            "setBreakpoints",
+            json!({"source": {"name": "Main.jack"}, "breakpoints": [{"line": 10}]}),
+        );
+        response(&mut session, "configurationDone", json!({}));
+        assert_eq!(without_seq(run_until_stopped(&mut session)), [stopped("breakpoint", None)]);
+
+        let body = response(&mut session, "stackTrace", json!({"threadId": 1}));
+        let frames: Vec<_> = body["stackFrames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| (frame["name"].clone(), frame["line"].clone()))
+            .collect();
+        assert_eq!(frames, [(json!("Main.helper"), json!(10)), (json!("Main.main"), json!(4))]);
+        // Without a source dir, sources come from the debug output.
+        assert_eq!(body["stackFrames"][0]["source"], json!({"name": "Main.jack", "sourceReference": 1}));
+        let body = response(&mut session, "source", json!({"sourceReference": 1}));
+        assert!(body["content"].as_str().unwrap().starts_with("class Main {"));
+
+        // Each frame has its own block of variablesReferences, and only the
+        // innermost one has registers.
+        let references = |body: Value| -> Vec<(Value, Value)> {
+            body["scopes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|scope| (scope["name"].clone(), scope["variablesReference"].clone()))
+                .collect()
+        };
+        assert_eq!(
+            references(response(&mut session, "scopes", json!({"frameId": 0}))),
+            [
+                (json!("Locals"), json!(1)),
+                (json!("Arguments"), json!(2)),
+                (json!("Statics"), json!(3)),
+                (json!("Registers"), json!(4)),
+            ]
+        );
+        assert_eq!(
+            references(response(&mut session, "scopes", json!({"frameId": 1}))),
+            [(json!("Locals"), json!(5)), (json!("Arguments"), json!(6)), (json!("Statics"), json!(7))]
+        );
+
+        let names = |body: Value| -> Vec<Value> { body["variables"].as_array().unwrap().iter().map(|var| var["name"].clone()).collect() };
+        assert!(names(response(&mut session, "variables", json!({"variablesReference": 1}))).is_empty());
+        assert_eq!(names(response(&mut session, "variables", json!({"variablesReference": 5}))), [json!("x")]);
+        let registers = response(&mut session, "variables", json!({"variablesReference": 4}));
+        assert_eq!(
+            names(registers.clone()),
+            ["A", "D", "PC", "SP", "LCL", "ARG", "THIS", "THAT"].map(|name| json!(name))
+        );
+        assert_eq!(registers["variables"][2]["value"], "12");
+        assert!(names(response(&mut session, "variables", json!({"variablesReference": 9}))).is_empty());
+
+        assert_eq!(response(&mut session, "continue", json!({"threadId": 1}))["allThreadsContinued"], true);
+        assert_eq!(
+            without_seq(run_until_stopped(&mut session)),
+            [
+                json!({"type": "event", "event": "exited", "body": {"exitCode": 0}}),
+                json!({"type": "event", "event": "terminated", "body": {}}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stopped_events() {
+        let mut session = launched_session();
+        session.stop_on_entry = true;
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!(messages[0]["type"], "response");
+        assert_eq!(without_seq(messages[1..].to_vec()), [stopped("entry", None)]);
+
+        response(&mut session, "next", json!({"threadId": 1}));
+        assert_eq!(without_seq(run_until_stopped(&mut session)), [stopped("step", None)]);
+
+        session.debugger.as_mut().unwrap().add_watchpoint(1000);
+        response(&mut session, "continue", json!({"threadId": 1}));
+        assert_eq!(
+            without_seq(run_until_stopped(&mut session)),
+            [stopped("data breakpoint", Some("RAM[1000] changed from 0 to 65535"))]
+        );
+
+        let mut session = launched_session();
+        response(
+            &mut session,
+            "setBreakpoints",
+            json!({"source": {"name": "Main.jack"}, "breakpoints": [{"line": 10, "condition": "x == 1"}]}),
+        );
+        response(&mut session, "configurationDone", json!({}));
+        assert_eq!(
+            without_seq(run_until_stopped(&mut session)),
+            [stopped(
+                "breakpoint",
+                Some("can't check the condition x == 1: there's no variable called x in Main.helper")
+            )]
+        );
+
+        // Log points go to the console, and don't stop.
+        let mut session = launched_session();
+        response(
+            &mut session,
+            "setBreakpoints",
+            json!({"source": {"name": "Main.jack"}, "breakpoints": [{"line": 10, "logMessage": "SP is {SP}"}]}),
+        );
+        response(&mut session, "configurationDone", json!({}));
+        let events = without_seq(run_until_stopped(&mut session));
+        assert_eq!(
+            events[0],
+            json!({"type": "event", "event": "output", "body": {"category": "console", "output": "SP is 0\n"}})
+        );
+        assert_eq!(events[1]["event"], "exited");
+
+        // Main.main sets SP to -1 instead of the word at 1000.
+        let mut session = launched_session();
+        let debugger = session.debugger.as_mut().unwrap();
+        debugger.computer.rom_mut()[7] = SP as u16;
+        debugger.computer.set_stack_checking(true);
+        response(&mut session, "configurationDone", json!({}));
+        let events = without_seq(run_until_stopped(&mut session));
+        assert_eq!(events[0]["body"]["category"], "stderr");
+        assert!(events[0]["body"]["output"].as_str().unwrap().starts_with("computer faulted: "));
+        assert_eq!(events[1]["body"]["reason"], "exception");
+    }
+}
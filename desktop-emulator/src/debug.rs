@@ -0,0 +1,640 @@
+// A debugger which reads commands from the terminal, and prints out the state
+// of the computer whenever it stops. The screen window carries on being drawn
+// while it's waiting for commands, so it's possible to see what the program
+// has drawn so far.
+
+use std::{
+    fs,
+    io::{self, Write},
+    sync::mpsc::{self, TryRecvError},
+    thread,
+};
+
+use emulator_core::{
+    breakpoints::Breakpoint,
+    debug_info::{format_value, Variable},
+    debugger::{Debugger, RunMode, StepKind, StopReason},
+    disassemble::disassemble,
+    heap::inspect_heap,
+    memory_layout::{ARG, HEAP_BASE, LCL, RAM_SIZE, SP, STACK_BASE, THAT, THIS},
+    object_graph::object_tree,
+    run::IO,
+};
+
+use crate::io::DesktopIO;
+
+// How many instructions to run in between drawing the screen and checking for
+// commands.
+const TICKS_PER_REFRESH: usize = 20_000;
+const STACK_WORDS_SHOWN: usize = 24;
+const WORDS_PER_ROW: usize = 8;
+const RAM_ROWS_SHOWN: usize = 4;
+// How far `print` follows objects into other objects.
+const MAX_OBJECT_DEPTH: usize = 6;
+// How many of the latest messages from log points are shown.
+const LOG_LINES_SHOWN: usize = 5;
+// How many subroutines and lines the profile shows.
+pub const PROFILE_ROWS: usize = 15;
+
+const HELP: &str = "\
+step [n]          run n instructions (default 1)
+next              run to the next jack statement, stepping over calls
+into              run to the next jack statement, stepping into calls
+out               run until the current subroutine returns
+backtrace         show the jack call stack, as worked out from RAM
+continue          run until a breakpoint or watchpoint
+break [location] [if <condition>] [hits <count>] [log <message>]
+                  add a breakpoint at a pc, file:line or subroutine, or list them
+clear <location>  remove a breakpoint
+watch <address>   stop when the value at an address changes
+unwatch <address> stop watching an address
+poke <address> <value>  write to RAM
+x <address>       show RAM from an address
+print <variable>  show a variable, following the objects it points to
+print <class> <address>  show the object of a class at an address
+heap [blocks]     check the heap, and list its blocks
+leaks             show what's still allocated, with --track-allocations
+profile [path]    show where the time went, with --profile, and write folded stacks to a path
+coverage [path]   show what has run, with --coverage, and write an lcov file to a path
+quit";
+
+pub struct TerminalDebugger {
+    debugger: Debugger,
+    running: Option<RunMode>,
+    ram_view_address: usize,
+    message: String,
+    // The latest messages from log points.
+    logs: Vec<String>,
+}
+
+fn parse_number(text: &str) -> Result<i32, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => i32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("{} isn't a number", text))
+}
+
+fn parse_address(text: &str) -> Result<usize, String> {
+    let address = match text {
+        "SP" => SP,
+        "LCL" => LCL,
+        "ARG" => ARG,
+        "THIS" => THIS,
+        "THAT" => THAT,
+        _ => parse_number(text)?.try_into().map_err(|_| format!("{} isn't an address", text))?,
+    };
+    if address >= RAM_SIZE {
+        return Err(format!("{} is past the end of RAM", text));
+    }
+    Ok(address)
+}
+
+// The options after a breakpoint's location, like `if i > 3 hits 2`. The log
+// message goes on to the end, so it comes last.
+fn parse_breakpoint(options: &[&str]) -> Result<Breakpoint, String> {
+    let mut parts: Vec<(&str, Vec<&str>)> = Vec::new();
+    for &word in options {
+        match (word, parts.last_mut()) {
+            (_, Some(("log", words))) => words.push(word),
+            ("if" | "hits" | "log", _) => parts.push((word, Vec::new())),
+            (_, Some((_, words))) => words.push(word),
+            (_, None) => return Err(format!("expected if, hits or log, not {}", word)),
+        }
+    }
+    let part = |keyword| {
+        parts
+            .iter()
+            .find(|(part_keyword, _)| *part_keyword == keyword)
+            .map(|(_, words)| words.join(" "))
+    };
+    Breakpoint::parse(part("if").as_deref(), part("hits").as_deref(), part("log").as_deref())
+}
+
+fn format_words(ram: &[u16], start: usize, end: usize, format_word: impl Fn(u16) -> String) -> Vec<String> {
+    (start..end)
+        .step_by(WORDS_PER_ROW)
+        .map(|row_start| {
+            let words: Vec<_> = (row_start..end.min(row_start + WORDS_PER_ROW))
+                .map(|address| format_word(ram[address]))
+                .collect();
+            format!("{:>6}: {}", row_start, words.join(" "))
+        })
+        .collect()
+}
+
+impl TerminalDebugger {
+    pub fn new(debugger: Debugger) -> Self {
+        Self {
+            debugger,
+            running: None,
+            ram_view_address: HEAP_BASE,
+            message: "type help for a list of commands".to_string(),
+            logs: Vec::new(),
+        }
+    }
+
+    pub fn run(mut self, io: &mut DesktopIO) {
+        // Reading commands blocks, so it's done on another thread to keep the
+        // window responsive.
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lines() {
+                if sender.send(line.expect("failed to read command")).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let ram = self.debugger.computer.ram.clone();
+        self.show();
+        loop {
+            io.refresh(&ram);
+            match receiver.try_recv() {
+                Ok(line) => {
+                    if !self.execute(line.trim()) {
+                        return;
+                    }
+                    if self.running.is_none() {
+                        self.show();
+                    }
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return,
+            }
+            if let Some(mode) = self.running {
+                let stop_reason = self.debugger.run(mode, TICKS_PER_REFRESH);
+                self.print_logs();
+                if let Some(stop_reason) = stop_reason {
+                    self.running = None;
+                    self.message = self.describe_stop(&stop_reason);
+                    self.show();
+                }
+            }
+        }
+    }
+
+    // Log points print as the program runs, as the screen is only drawn when
+    // it stops.
+    fn print_logs(&mut self) {
+        for log in self.debugger.take_logs() {
+            println!("{}", log);
+            self.logs.push(log);
+        }
+        let excess = self.logs.len().saturating_sub(LOG_LINES_SHOWN);
+        self.logs.drain(..excess);
+    }
+
+    fn describe_stop(&self, stop_reason: &StopReason) -> String {
+        match stop_reason {
+            StopReason::Breakpoint => format!("stopped at breakpoint at pc {}", self.debugger.computer.cpu.pc),
+            StopReason::Watchpoint { address, old, new } => format!("RAM[{}] changed from {} to {}", address, old, new),
+            StopReason::Step => String::new(),
+            StopReason::Fault(fault) => self.describe_fault(fault),
+            StopReason::PointerNotRestored(violation) => violation.clone(),
+            StopReason::MemoryError(error) | StopReason::ArithmeticError(error) | StopReason::ConditionError(error) => error.clone(),
+            StopReason::Exited => match self.debugger.allocations() {
+                Some(allocations) => format!("program finished\n{}", allocations.leak_report()),
+                None => "program finished".to_string(),
+            },
+        }
+    }
+
+    fn describe_fault(&self, fault: &str) -> String {
+        match self.backtrace() {
+            Ok(backtrace) => format!("computer faulted: {}\n{}", fault, backtrace),
+            Err(_) => format!("computer faulted: {}", fault),
+        }
+    }
+
+    fn backtrace(&self) -> Result<String, String> {
+        if self.debugger.debug_info.filenames().is_empty() {
+            return Err("backtraces need the debug output of the compiler".to_string());
+        }
+        Ok(self.debugger.backtrace().format(&self.debugger.debug_info, &self.debugger.computer))
+    }
+
+    // Returns false to quit.
+    fn execute(&mut self, line: &str) -> bool {
+        if self.running.take().is_some() {
+            self.message = match self.backtrace() {
+                Ok(backtrace) => format!("interrupted\n{}", backtrace),
+                Err(_) => "interrupted".to_string(),
+            };
+            if line.is_empty() {
+                return true;
+            }
+        }
+        let words: Vec<_> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return true;
+        };
+        let result = match command {
+            "q" | "quit" => return false,
+            "h" | "help" => Ok(HELP.to_string()),
+            "s" | "step" => self.step_instructions(args),
+            "n" | "next" => self.step(StepKind::Over),
+            "i" | "into" => self.step(StepKind::In),
+            "o" | "out" => self.step(StepKind::Out),
+            "bt" | "backtrace" => self.backtrace(),
+            "c" | "continue" => {
+                self.running = Some(RunMode::Continue);
+                Ok("running, press enter to interrupt".to_string())
+            }
+            "b" | "break" => self.add_breakpoint(args),
+            "clear" => self.clear_breakpoint(args),
+            "w" | "watch" => self.watch(args, true),
+            "unwatch" => self.watch(args, false),
+            "poke" => self.poke(args),
+            "p" | "print" => self.print(args),
+            "heap" => self.heap(args),
+            "leaks" => self
+                .debugger
+                .allocations()
+                .map(|allocations| allocations.leak_report())
+                .ok_or_else(|| "allocations are only recorded with --track-allocations".to_string()),
+            "profile" => self.profile(args),
+            "coverage" => self.coverage(args),
+            "x" => args
+                .first()
+                .ok_or_else(|| "x needs an address".to_string())
+                .and_then(|address| parse_address(address))
+                .map(|address| {
+                    self.ram_view_address = address;
+                    String::new()
+                }),
+            _ => Err(format!("unknown command {}, type help for a list of commands", command)),
+        };
+        self.message = result.unwrap_or_else(|err| err);
+        true
+    }
+
+    fn step_instructions(&mut self, args: &[&str]) -> Result<String, String> {
+        let count = match args.first() {
+            Some(count) => parse_number(count)?,
+            None => 1,
+        };
+        for _ in 0..count {
+            self.debugger.tick();
+            if let Some(fault) = self.debugger.computer.fault() {
+                return Ok(self.describe_fault(&fault.to_string()));
+            }
+        }
+        Ok(String::new())
+    }
+
+    fn step(&mut self, step_kind: StepKind) -> Result<String, String> {
+        if self.debugger.debug_info.filenames().is_empty() {
+            return Err("stepping through jack needs the debug output of the compiler".to_string());
+        }
+        self.running = Some(self.debugger.start_step(step_kind));
+        Ok(String::new())
+    }
+
+    // The pcs of a location, which is either a pc, file:line or the name of a
+    // subroutine.
+    fn location_pcs(&self, location: &str) -> Result<Vec<u16>, String> {
+        let debug_info = &self.debugger.debug_info;
+        if let Some((filename, line)) = location.split_once(':') {
+            let line = parse_number(line)? as usize;
+            let pcs = debug_info.line_pcs(filename, line);
+            if pcs.is_empty() {
+                return Err(format!("there are no statements on line {} of {}", line, filename));
+            }
+            return Ok(pcs);
+        }
+        if let Some(subroutine) = debug_info.subroutine_by_name(location) {
+            let pc = subroutine
+                .entry_pc
+                .ok_or_else(|| format!("{} is never called, so was left out", location))?;
+            return Ok(vec![pc]);
+        }
+        let pc = parse_number(location)?;
+        u16::try_from(pc).map(|pc| vec![pc]).map_err(|_| format!("{} isn't a pc", location))
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let Some((location, options)) = args.split_first() else {
+            let mut pcs: Vec<_> = self.debugger.breakpoints().iter().copied().collect();
+            pcs.sort();
+            let pcs: Vec<_> = pcs.iter().map(|&pc| self.describe_breakpoint(pc)).collect();
+            return Ok(format!("breakpoints at pcs: {}", pcs.join(", ")));
+        };
+        let breakpoint = parse_breakpoint(options)?;
+        let pcs = self.location_pcs(location)?;
+        for &pc in &pcs {
+            self.debugger.add_conditional_breakpoint(pc, breakpoint.clone());
+        }
+        Ok(format!("added breakpoint at {}", location))
+    }
+
+    fn describe_breakpoint(&self, pc: u16) -> String {
+        let Some(breakpoint) = self.debugger.breakpoint(pc) else {
+            return pc.to_string();
+        };
+        let mut parts = vec![pc.to_string()];
+        if let Some(condition) = &breakpoint.condition {
+            parts.push(format!("if {}", condition.source()));
+        }
+        if breakpoint.hits() > 0 {
+            parts.push(format!("(hit {} times)", breakpoint.hits()));
+        }
+        if breakpoint.log_message.is_some() {
+            parts.push("(logs)".to_string());
+        }
+        parts.join(" ")
+    }
+
+    fn clear_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let location = args.first().ok_or("clear needs a location")?;
+        let mut removed = false;
+        for pc in self.location_pcs(location)? {
+            removed |= self.debugger.remove_breakpoint(pc);
+        }
+        match removed {
+            true => Ok(format!("removed breakpoint at {}", location)),
+            false => Err(format!("there's no breakpoint at {}", location)),
+        }
+    }
+
+    fn watch(&mut self, args: &[&str], add: bool) -> Result<String, String> {
+        let address = parse_address(args.first().ok_or("watch needs an address")?)?;
+        if add {
+            self.debugger.add_watchpoint(address);
+            Ok(format!("watching RAM[{}]", address))
+        } else if self.debugger.remove_watchpoint(address) {
+            Ok(format!("stopped watching RAM[{}]", address))
+        } else {
+            Err(format!("RAM[{}] isn't being watched", address))
+        }
+    }
+
+    fn poke(&mut self, args: &[&str]) -> Result<String, String> {
+        let [address, value] = args else {
+            return Err("poke needs an address and a value".to_string());
+        };
+        let address = parse_address(address)?;
+        let value = parse_number(value)?;
+        if !(i16::MIN as i32..=u16::MAX as i32).contains(&value) {
+            return Err(format!("{} doesn't fit in a word", value));
+        }
+        self.debugger.computer.ram.lock()[address] = value as u16;
+        Ok(format!("RAM[{}] = {}", address, value))
+    }
+
+    // The type and value of a variable in the innermost subroutine. `this`
+    // is THIS, as an object of the subroutine's class.
+    fn variable(&self, name: &str) -> Result<(String, u16), String> {
+        let debug_info = &self.debugger.debug_info;
+        let stack = self.debugger.stack();
+        let (frame, _) = stack.first().ok_or("there's no jack subroutine running")?;
+        let ram = self.debugger.computer.ram.lock();
+        debug_info.variable(frame, &*ram, name).ok_or_else(|| {
+            let subroutine = &debug_info.subroutines[frame.subroutine_idx];
+            format!("there's no variable called {} in {}", name, subroutine.name)
+        })
+    }
+
+    fn print(&self, args: &[&str]) -> Result<String, String> {
+        let (type_name, value) = match args {
+            [name] => self.variable(name)?,
+            [class_name, address] => {
+                if self.debugger.debug_info.class_layout(class_name).is_none() {
+                    return Err(format!("there's no layout for a class called {}", class_name));
+                }
+                (class_name.to_string(), parse_address(address)? as u16)
+            }
+            _ => return Err("print needs a variable, or a class and an address".to_string()),
+        };
+        let ram = self.debugger.computer.ram.lock();
+        let tree = object_tree(&self.debugger.debug_info, &*ram, &type_name, value, MAX_OBJECT_DEPTH);
+        Ok(tree.format(args.last().unwrap()))
+    }
+
+    fn heap(&self, args: &[&str]) -> Result<String, String> {
+        let heap = inspect_heap(&*self.debugger.computer.ram.lock());
+        match args.first() {
+            None => Ok(heap.summary()),
+            Some(&"blocks") => Ok(format!("{}\n{}", heap.format_blocks(), heap.summary())),
+            Some(arg) => Err(format!("heap doesn't take {}", arg)),
+        }
+    }
+
+    fn profile(&self, args: &[&str]) -> Result<String, String> {
+        let profiler = self
+            .debugger
+            .profiler()
+            .ok_or_else(|| "the profile is only recorded with --profile".to_string())?;
+        let report = profiler.profile(&self.debugger.debug_info).format(PROFILE_ROWS);
+        match args.first() {
+            None => Ok(report),
+            Some(path) => {
+                fs::write(path, profiler.folded_stacks(&self.debugger.debug_info)).map_err(|err| format!("failed to write {}: {}", path, err))?;
+                Ok(format!("{}\nwrote folded stacks to {}", report, path))
+            }
+        }
+    }
+
+    fn coverage(&self, args: &[&str]) -> Result<String, String> {
+        let coverage = self
+            .debugger
+            .coverage()
+            .ok_or_else(|| "coverage is only recorded with --coverage".to_string())?;
+        let report = coverage.report(&self.debugger.debug_info);
+        match args.first() {
+            None => Ok(report.summary()),
+            Some(path) => {
+                fs::write(path, report.to_lcov()).map_err(|err| format!("failed to write {}: {}", path, err))?;
+                Ok(format!("{}\nwrote lcov to {}", report.summary(), path))
+            }
+        }
+    }
+
+    fn source_lines(&self, pc: u16) -> Vec<String> {
+        let debug_info = &self.debugger.debug_info;
+        let (Some(location), Some(subroutine)) = (debug_info.location(pc), debug_info.subroutine_at(pc)) else {
+            return vec!["no jack source for this pc".to_string()];
+        };
+        let callers: Vec<_> = self
+            .debugger
+            .stack()
+            .iter()
+            .skip(1)
+            .map(|(frame, _)| debug_info.subroutines[frame.subroutine_idx].name.as_str())
+            .collect();
+        let mut lines = vec![format!(
+            "{}:{} in {} <- {}",
+            location.filename,
+            location.line,
+            subroutine.name,
+            callers.join(" <- ")
+        )];
+        let source = debug_info.source(&location.filename).unwrap_or_default();
+        for (idx, text) in source.lines().enumerate().skip(location.line.saturating_sub(3)).take(5) {
+            let marker = if idx + 1 == location.line { ">" } else { " " };
+            lines.push(format!("{} {:>5} | {}", marker, idx + 1, text));
+        }
+        lines.extend(self.variable_lines());
+        lines
+    }
+
+    // The arguments and locals of the innermost subroutine.
+    fn variable_lines(&self) -> Vec<String> {
+        let stack = self.debugger.stack();
+        let Some((frame, _)) = stack.first() else {
+            return Vec::new();
+        };
+        let subroutine = &self.debugger.debug_info.subroutines[frame.subroutine_idx];
+        let ram = self.debugger.computer.ram.lock();
+        let format_segment = |variables: &[Variable], base: u16| {
+            variables
+                .iter()
+                .map(|var| {
+                    format!(
+                        "{} = {}",
+                        var.name,
+                        format_value(&var.type_name, ram[(base as usize + var.index) % RAM_SIZE])
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        vec![
+            format!("arguments: {}", format_segment(&subroutine.arguments, frame.arg)),
+            format!("locals: {}", format_segment(&subroutine.locals, frame.lcl)),
+        ]
+    }
+
+    fn disassembly_lines(&self, pc: u16) -> Vec<String> {
+        let rom = self.debugger.computer.rom();
+        let start = pc.saturating_sub(4) as usize;
+        (start..(start + 10).min(rom.len()))
+            .map(|address| {
+                let marker = if address == pc as usize { ">" } else { " " };
+                let breakpoint = if self.debugger.breakpoints().contains(&(address as u16)) {
+                    "*"
+                } else {
+                    " "
+                };
+                let vm_command = self
+                    .debugger
+                    .debug_info
+                    .pc_info(address as u16)
+                    .map_or("", |pc_info| pc_info.vm_command.as_str());
+                format!(
+                    "{}{} {:>5}  {:<12} {}",
+                    marker,
+                    breakpoint,
+                    address,
+                    disassemble(rom[address]),
+                    vm_command
+                )
+            })
+            .collect()
+    }
+
+    // The state of the computer, as shown whenever it stops.
+    fn screen(&self) -> String {
+        let computer = &self.debugger.computer;
+        let pc = computer.cpu.pc;
+        // These lock RAM themselves, so they have to come before it's locked
+        // here.
+        let source_lines = self.source_lines(pc);
+        let disassembly_lines = self.disassembly_lines(pc);
+        let ram = computer.ram.lock();
+        let mut lines = vec![format!(
+            "PC {}  A {}  D {}  SP {}  LCL {}  ARG {}  THIS {}  THAT {}",
+            pc, computer.cpu.reg_a, computer.cpu.reg_d as i16, ram[SP], ram[LCL], ram[ARG], ram[THIS], ram[THAT]
+        )];
+        lines.push(String::new());
+        lines.extend(source_lines);
+        lines.push(String::new());
+        lines.extend(disassembly_lines);
+
+        let stack_end = (ram[SP] as usize).clamp(STACK_BASE, HEAP_BASE);
+        let stack_start = stack_end.saturating_sub(STACK_WORDS_SHOWN).max(STACK_BASE);
+        lines.push(String::new());
+        lines.push(format!("stack ({}-{}), top {} words", STACK_BASE, HEAP_BASE - 1, stack_end - stack_start));
+        lines.extend(format_words(&*ram, stack_start, stack_end, |word| format!("{:>6}", word as i16)));
+
+        let ram_end = (self.ram_view_address + RAM_ROWS_SHOWN * WORDS_PER_ROW).min(RAM_SIZE);
+        lines.push(String::new());
+        lines.push("RAM".to_string());
+        lines.extend(format_words(&*ram, self.ram_view_address, ram_end, |word| format!("{:04x}", word)));
+
+        let watches: Vec<_> = self
+            .debugger
+            .watchpoints()
+            .map(|address| format!("RAM[{}] = {}", address, ram[address] as i16))
+            .collect();
+        if !watches.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("watching {}", watches.join(", ")));
+        }
+        if !self.logs.is_empty() {
+            lines.push(String::new());
+            lines.push("log".to_string());
+            lines.extend(self.logs.iter().cloned());
+        }
+        lines.push(String::new());
+        lines.push(self.message.clone());
+        lines.join("\n")
+    }
+
+    fn show(&self) {
+        // Clear the terminal, so the state is always in the same place.
+        print!("\x1b[2J\x1b[H{}\n(debug) ", self.screen());
+        io::stdout().flush().expect("failed to write to terminal");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use emulator_core::testing::TestProgram;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_breakpoint() {
+        let breakpoint = parse_breakpoint(&["if", "i", ">", "3", "hits", "%", "2", "log", "i", "is", "{i}"]).unwrap();
+        assert_eq!(breakpoint.condition.unwrap().source(), "i > 3");
+        assert!(breakpoint.hit_condition.is_some());
+        assert!(breakpoint.log_message.is_some());
+        let breakpoint = parse_breakpoint(&[]).unwrap();
+        assert!(breakpoint.condition.is_none() && breakpoint.log_message.is_none());
+        assert!(parse_breakpoint(&["i", ">", "3"]).is_err());
+        assert!(parse_breakpoint(&["if", "i", ">"]).is_err());
+    }
+
+    #[test]
+    fn test_screen() {
+        let debug_info = TestProgram::new()
+            .file("Sys.jack", "class Sys {\n  function void init() {\n    do Main.main();\n  }\n}\n")
+            .subroutine("Sys.init", 0, &[], &[(2, "function Sys.init 0"), (3, "call Main.main 0")])
+            .file(
+                "Main.jack",
+                "class Main {\n  function void main() {\n    var int x;\n    let x = 3;\n    return;\n  }\n}\n",
+            )
+            .subroutine(
+                "Main.main",
+                0,
+                &["x"],
+                &[
+                    (2, "function Main.main 1"),
+                    (4, "push constant 3"),
+                    (4, "pop local 0"),
+                    (5, "push constant 0"),
+                    (5, "return"),
+                ],
+            )
+            .build();
+        let mut debugger = TerminalDebugger::new(Debugger::new(debug_info));
+        for _ in 0..3 {
+            debugger.debugger.tick();
+        }
+        let screen = debugger.screen();
+        assert!(screen.contains("Main.jack:4 in Main.main <- "), "{}", screen);
+        assert!(screen.contains(">     4 |     let x = 3;"), "{}", screen);
+        assert!(screen.contains("locals: x = "), "{}", screen);
+    }
+}
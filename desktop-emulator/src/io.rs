@@ -1,13 +1,12 @@
 use emulator_core::{
-    computer::{bit, Ram},
+    computer::Ram,
+    display::{self, SCREEN_HEIGHT, SCREEN_WIDTH},
+    memory_layout::{KBD, LEDS},
+    png,
     run::IO,
 };
-use minifb::{Key, Scale, ScaleMode, Window, WindowOptions};
-use std::{array, time::SystemTime};
-
-const WORD_SIZE: usize = 16;
-const SCREEN_WIDTH: usize = 512;
-const SCREEN_HEIGHT: usize = 256;
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
+use std::{array, fs, time::SystemTime};
 
 pub struct DesktopIO {
     screen_window: Window,
@@ -15,6 +14,8 @@ pub struct DesktopIO {
     screen_buffer: [u32; SCREEN_WIDTH * SCREEN_HEIGHT],
     leds: u16,
     last_draw_time: SystemTime,
+    // Off while an input script is playing into the keyboard register.
+    keyboard: bool,
 }
 
 fn get_key_scancode(key: &Key) -> u16 {
@@ -81,7 +82,7 @@ fn get_key_scancode(key: &Key) -> u16 {
 }
 
 fn kbd_output(keys: Vec<Key>) -> u16 {
-    let non_modifier_key = keys.get(0).map(get_key_scancode).unwrap_or(0);
+    let non_modifier_key = keys.first().map(get_key_scancode).unwrap_or(0);
     let shift_is_down = keys.contains(&Key::LeftShift) || keys.contains(&Key::RightShift);
     let ctrl_is_down = keys.contains(&Key::LeftCtrl) || keys.contains(&Key::RightCtrl);
     let alt_is_down = keys.contains(&Key::LeftAlt) || keys.contains(&Key::RightAlt);
@@ -90,6 +91,15 @@ fn kbd_output(keys: Vec<Key>) -> u16 {
     (modifier_flags << 12) | non_modifier_key
 }
 
+fn save_screenshot(ram: &Ram) {
+    let screenshot_path = "screenshot.png";
+    let pixels = display::render_rgb(&*ram.lock());
+    match fs::write(screenshot_path, png::encode_rgb(SCREEN_WIDTH, SCREEN_HEIGHT, &pixels)) {
+        Ok(()) => println!("saved screenshot to {}", screenshot_path),
+        Err(err) => println!("failed to save screenshot: {}", err),
+    }
+}
+
 impl Default for DesktopIO {
     fn default() -> Self {
         Self::new()
@@ -101,18 +111,12 @@ impl IO for DesktopIO {
         let time = SystemTime::now();
         if let Ok(t) = time.duration_since(self.last_draw_time) {
             if t.as_millis() >= 16 {
-                for (pixel_idx, pixel) in self.screen_buffer.iter_mut().enumerate() {
-                    let word_idx = pixel_idx / WORD_SIZE;
-                    let word = ram.lock()[word_idx + 18432];
-                    let bit_position_in_word = 15 - (pixel_idx % 16);
-                    *pixel = if bit(word, bit_position_in_word as u32) == 0 {
-                        0xff000000
-                    } else {
-                        0xffffffff
-                    }
+                let pixels = display::render_rgb(&*ram.lock());
+                for (pixel, [r, g, b]) in self.screen_buffer.iter_mut().zip(&pixels) {
+                    *pixel = 0xff000000 | (*r as u32) << 16 | (*g as u32) << 8 | *b as u32;
                 }
 
-                self.leds = ram.lock()[30425];
+                self.leds = ram.lock()[LEDS];
                 self.last_draw_time = time;
             }
             // Note that if `update_with_buffer` is called on one screen, it must be called on all
@@ -125,7 +129,13 @@ impl IO for DesktopIO {
                 array::from_fn(|i| 15 - i as u32).map(|i| if 2u16.pow(i) & self.leds == 0 { 0xff000000u32 } else { 0xff00ff00u32 });
             self.led_window.update_with_buffer(&led_buffer, 16, 1).unwrap();
 
-            ram.lock()[26624] = kbd_output(self.screen_window.get_keys());
+            if self.screen_window.is_key_pressed(Key::F12, KeyRepeat::No) {
+                save_screenshot(ram);
+            }
+
+            if self.keyboard {
+                ram.lock()[KBD] = kbd_output(self.screen_window.get_keys());
+            }
         }
     }
 }
@@ -174,6 +184,12 @@ impl DesktopIO {
             screen_buffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
             leds: 0,
             last_draw_time: SystemTime::now(),
+            keyboard: true,
         }
     }
+
+    // Leaves the keyboard register alone, for input scripts.
+    pub fn disable_keyboard(&mut self) {
+        self.keyboard = false;
+    }
 }
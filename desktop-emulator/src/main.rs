@@ -1,8 +1,26 @@
+mod debug;
 mod io;
-use std::fs;
+use std::{
+    fs,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    thread,
+};
 
 use clap::{Parser, Subcommand};
-use emulator_core::{computer::Computer, generate_rom, run::run};
+use debug::{TerminalDebugger, PROFILE_ROWS};
+use emulator_core::{
+    computer::Computer,
+    debug_info::DebugInfo,
+    debugger::{Debugger, RunMode, StopReason},
+    display::{self, SCREEN_HEIGHT, SCREEN_WIDTH},
+    gdb_stub::GdbStub,
+    generate_rom,
+    image_format::{self, ImageFormat},
+    input_script::{InputPlayer, InputRecorder, InputScript},
+    png,
+    run::{run, Input, IO},
+};
 use io::DesktopIO;
 
 #[derive(Parser, Debug)]
@@ -12,20 +30,340 @@ struct Args {
     command: Commands,
 }
 
+#[derive(clap::Args, Debug)]
+struct Program {
+    file_path: Option<String>,
+    /// Initial contents of RAM, as output by the compiler
+    #[clap(long)]
+    ram_image: Option<String>,
+    /// Format of the machine code and ram image, one of bin-be, bin-le,
+    /// ihex, memb, memh, coe or annotated. Detected from the files if not
+    /// given
+    #[clap(long)]
+    format: Option<ImageFormat>,
+}
+
+impl Program {
+    fn load(&self) -> Computer {
+        let file_path = self.file_path.as_ref().expect("path is required");
+        let machine_code = image_format::read(Path::new(file_path), self.format).unwrap_or_else(|err| panic!("{}", err));
+        let rom = generate_rom::from_image(&machine_code);
+        let ram_image = self
+            .ram_image
+            .as_ref()
+            .map(|path| image_format::read(Path::new(path), self.format).unwrap_or_else(|err| panic!("{}", err)))
+            .unwrap_or_default();
+        Computer::with_ram_image(rom, &ram_image)
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Run machine code on emulator
-    Run { file_path: Option<String> },
+    Run {
+        #[clap(flatten)]
+        program: Program,
+        /// JSON output of the compiler's debug-compile command, for showing
+        /// a backtrace of the jack code if the computer faults
+        #[clap(long)]
+        debug_info: Option<String>,
+        /// Fault when the stack runs into the heap or below its base
+        #[clap(long)]
+        check_stack: bool,
+        /// Script of key presses to play instead of reading the keyboard
+        #[clap(long, conflicts_with = "record_input")]
+        input: Option<String>,
+        /// Where to record what's typed as a script of key presses, which
+        /// --input plays back
+        #[clap(long)]
+        record_input: Option<String>,
+    },
+    /// Run machine code on emulator, controlled by a debugger speaking the
+    /// gdb remote protocol
+    Gdb {
+        #[clap(flatten)]
+        program: Program,
+        #[clap(long, default_value = "3333")]
+        port: u16,
+    },
+    /// Run machine code on emulator, controlled by commands typed into the
+    /// terminal
+    Debug {
+        #[clap(flatten)]
+        program: Program,
+        /// JSON output of the compiler's debug-compile command, for
+        /// debugging at the level of jack. The machine code and ram image
+        /// are taken from it if no path is given
+        #[clap(long)]
+        debug_info: Option<String>,
+        /// Stop when a subroutine relies on LCL, ARG, THIS or THAT after a
+        /// call which didn't restore it. Needs --debug-info
+        #[clap(long)]
+        check_pointers: bool,
+        /// Fault when the stack runs into the heap or below its base
+        #[clap(long)]
+        check_stack: bool,
+        /// Record the blocks that Memory.malloc hands out, and report the
+        /// ones which haven't been freed when the program finishes. Needs
+        /// --debug-info
+        #[clap(long)]
+        track_allocations: bool,
+        /// Stop at reads of memory which hasn't been set, null pointer
+        /// accesses, and heap accesses outside of allocated blocks. Needs
+        /// --debug-info
+        #[clap(long)]
+        sanitize: bool,
+        /// Count the instructions run in each jack subroutine and on each
+        /// line, for the profile command. Needs --debug-info
+        #[clap(long)]
+        profile: bool,
+        /// Record which jack statements, branches and subroutines run, for
+        /// the coverage command. Needs --debug-info
+        #[clap(long)]
+        coverage: bool,
+        /// Script of key presses to play instead of reading the keyboard
+        #[clap(long)]
+        input: Option<String>,
+    },
+    /// Run machine code without a window, and report which jack subroutines
+    /// and lines it spent its time in
+    Profile {
+        #[clap(flatten)]
+        program: Program,
+        /// JSON output of the compiler's debug-compile command
+        #[clap(long)]
+        debug_info: String,
+        /// Stop after this many instructions, if the program hasn't finished
+        #[clap(long, default_value = "100000000")]
+        ticks: usize,
+        /// Where to write the call stacks as folded stacks, for flamegraph
+        /// tools
+        #[clap(long)]
+        folded: Option<String>,
+    },
+    /// Run machine code without a window, and report which jack statements,
+    /// branches and subroutines it ran
+    Coverage {
+        #[clap(flatten)]
+        program: Program,
+        /// JSON output of the compiler's debug-compile command
+        #[clap(long)]
+        debug_info: String,
+        /// Stop after this many instructions, if the program hasn't finished
+        #[clap(long, default_value = "100000000")]
+        ticks: usize,
+        /// Where to write the coverage as an lcov tracefile
+        #[clap(long)]
+        lcov: Option<String>,
+    },
+    /// Run machine code without a window, playing a script of key presses,
+    /// and save what's on the screen at the end
+    Play {
+        #[clap(flatten)]
+        program: Program,
+        /// JSON output of the compiler's debug-compile command, for jack
+        /// variables in the script's conditions
+        #[clap(long)]
+        debug_info: Option<String>,
+        /// The script of key presses
+        #[clap(long)]
+        input: String,
+        /// Stop after this many instructions, if the program hasn't finished
+        #[clap(long, default_value = "100000000")]
+        ticks: usize,
+        /// Where to save the screen as a PNG
+        #[clap(long)]
+        screenshot: Option<String>,
+    },
+}
+
+fn load_input_script(path: &str) -> InputScript {
+    let text = fs::read_to_string(path).unwrap_or_else(|err| panic!("failed to read {}: {}", path, err));
+    InputScript::parse(&text).unwrap_or_else(|err| panic!("{}: {}", path, err))
+}
+
+// Where the keyboard gets its input from, which the window needs to know.
+fn window_input(io: &mut DesktopIO, script_path: Option<&String>, record_path: Option<&String>) -> Input {
+    if let Some(path) = script_path {
+        io.disable_keyboard();
+        return Input::Script(InputPlayer::new(load_input_script(path)));
+    }
+    match record_path {
+        Some(path) => Input::Record(InputRecorder::new(), PathBuf::from(path)),
+        None => Input::Window,
+    }
+}
+
+fn load_debugger(program: &Program, debug_info_path: Option<&String>) -> Debugger {
+    let Some(path) = debug_info_path else {
+        let computer = program.load();
+        let debug_info = DebugInfo::without_sourcemap(computer.rom().to_vec(), Default::default());
+        return Debugger::with_computer(debug_info, computer);
+    };
+    let json = fs::read_to_string(path).unwrap_or_else(|err| panic!("failed to read {}: {}", path, err));
+    let debug_info = DebugInfo::from_json(&json).unwrap_or_else(|err| panic!("{}", err));
+    match program.file_path {
+        Some(_) => Debugger::with_computer(debug_info, program.load()),
+        None => Debugger::new(debug_info),
+    }
+}
+
+// Runs a program without a window until it finishes or stops, or it's run for
+// `ticks` instructions.
+fn run_headless(debugger: &mut Debugger, ticks: usize) {
+    match debugger.run(RunMode::Continue, ticks) {
+        Some(StopReason::Exited) => println!("program finished"),
+        Some(StopReason::Fault(fault)) => println!("computer faulted: {}", fault),
+        Some(stop_reason) => println!("stopped: {:?}", stop_reason),
+        None => println!("stopped after {} instructions", ticks),
+    }
+}
+
+fn profile(program: &Program, debug_info_path: &String, ticks: usize, folded_path: Option<&String>) {
+    let mut debugger = load_debugger(program, Some(debug_info_path));
+    debugger.profile();
+    run_headless(&mut debugger, ticks);
+    let profiler = debugger.profiler().unwrap();
+    println!("{}", profiler.profile(&debugger.debug_info).format(PROFILE_ROWS));
+    if let Some(path) = folded_path {
+        fs::write(path, profiler.folded_stacks(&debugger.debug_info)).unwrap_or_else(|err| panic!("failed to write {}: {}", path, err));
+    }
+}
+
+fn play(program: &Program, debug_info_path: Option<&String>, script_path: &str, ticks: usize, screenshot_path: Option<&String>) {
+    let mut debugger = load_debugger(program, debug_info_path);
+    debugger.play_input(load_input_script(script_path));
+    run_headless(&mut debugger, ticks);
+    if !debugger.input_player().unwrap().is_finished() {
+        println!("the input script didn't finish playing");
+    }
+    if let Some(path) = screenshot_path {
+        let pixels = display::render_rgb(&*debugger.computer.ram.lock());
+        fs::write(path, png::encode_rgb(SCREEN_WIDTH, SCREEN_HEIGHT, &pixels)).unwrap_or_else(|err| panic!("failed to write {}: {}", path, err));
+    }
+}
+
+fn coverage(program: &Program, debug_info_path: &String, ticks: usize, lcov_path: Option<&String>) {
+    let mut debugger = load_debugger(program, Some(debug_info_path));
+    debugger.record_coverage();
+    run_headless(&mut debugger, ticks);
+    let report = debugger.coverage().unwrap().report(&debugger.debug_info);
+    println!("{}", report.summary());
+    if let Some(path) = lcov_path {
+        fs::write(path, report.to_lcov()).unwrap_or_else(|err| panic!("failed to write {}: {}", path, err));
+    }
+}
+
+// Debuggers can attach one after another. The computer carries on from
+// wherever the last one left it.
+fn serve_gdb(mut computer: Computer, port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|err| panic!("failed to listen on port {}: {}", port, err));
+    println!("waiting for gdb to connect on port {}", port);
+    for stream in listener.incoming() {
+        let stream = stream.expect("failed to accept gdb connection");
+        println!("gdb connected");
+        if let Err(err) = GdbStub::new(stream).serve(&mut computer) {
+            println!("gdb connection failed: {}", err);
+        }
+        println!("gdb disconnected");
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
     match &args.command {
-        Commands::Run { file_path: file_path_maybe } => {
-            let file_path = file_path_maybe.as_ref().expect("path is required");
-            let rom = generate_rom::from_string(fs::read_to_string(file_path).expect("failed to read machine code from file"));
-            run(Computer::new(rom), &mut DesktopIO::new());
+        Commands::Run {
+            program,
+            debug_info: None,
+            check_stack,
+            input,
+            record_input,
+        } => {
+            let mut computer = program.load();
+            computer.set_stack_checking(*check_stack);
+            let mut io = DesktopIO::new();
+            let input = window_input(&mut io, input.as_ref(), record_input.as_ref());
+            run(computer, &mut io, None, input);
+        }
+        Commands::Run {
+            program,
+            debug_info,
+            check_stack,
+            input,
+            record_input,
+        } => {
+            let Debugger {
+                mut computer, debug_info, ..
+            } = load_debugger(program, debug_info.as_ref());
+            computer.set_stack_checking(*check_stack);
+            let mut io = DesktopIO::new();
+            let input = window_input(&mut io, input.as_ref(), record_input.as_ref());
+            run(computer, &mut io, Some(debug_info), input);
+        }
+        Commands::Gdb { program, port } => {
+            let computer = program.load();
+            let ram = computer.ram.clone();
+            let port = *port;
+            thread::spawn(move || serve_gdb(computer, port));
+            let mut io = DesktopIO::new();
+            loop {
+                io.refresh(&ram);
+            }
+        }
+        Commands::Debug {
+            program,
+            debug_info,
+            check_pointers,
+            check_stack,
+            track_allocations,
+            sanitize,
+            profile,
+            coverage,
+            input,
+        } => {
+            let mut debugger = load_debugger(program, debug_info.as_ref());
+            if *check_pointers {
+                debugger.check_pointers();
+            }
+            if *track_allocations {
+                debugger.track_allocations();
+            }
+            if *sanitize {
+                debugger.sanitize_memory();
+            }
+            if *profile {
+                debugger.profile();
+            }
+            if *coverage {
+                debugger.record_coverage();
+            }
+            debugger.computer.set_stack_checking(*check_stack);
+            let mut io = DesktopIO::new();
+            if let Some(path) = input {
+                io.disable_keyboard();
+                debugger.play_input(load_input_script(path));
+            }
+            TerminalDebugger::new(debugger).run(&mut io);
         }
+        Commands::Profile {
+            program,
+            debug_info,
+            ticks,
+            folded,
+        } => profile(program, debug_info, *ticks, folded.as_ref()),
+        Commands::Coverage {
+            program,
+            debug_info,
+            ticks,
+            lcov,
+        } => coverage(program, debug_info, *ticks, lcov.as_ref()),
+        Commands::Play {
+            program,
+            debug_info,
+            input,
+            ticks,
+            screenshot,
+        } => play(program, debug_info.as_ref(), input, *ticks, screenshot.as_ref()),
     }
 }
@@ -0,0 +1,178 @@
+// Keeps track of the blocks which a program gets from Memory.malloc and
+// Memory.calloc, and gives back with Memory.deAlloc, by watching for calls to
+// them with the call tracker. Whatever is still allocated when the program
+// finishes has leaked, which otherwise goes unnoticed - a string literal makes
+// a new String every time it's evaluated, for example, and nothing frees it.
+//
+// Calls from inside Memory are the allocator's own business, like calloc
+// using malloc, or init allocating the free list heads, so they're left out.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    computer::Computer,
+    debug_info::{CallEvent, CallTracker, DebugInfo},
+    memory_layout::SP,
+};
+
+const ALLOCATORS: [&str; 2] = ["Memory.malloc", "Memory.calloc"];
+const DEALLOCATOR: &str = "Memory.deAlloc";
+
+// Allocations are grouped by the innermost call from outside the standard
+// library, so that all the strings made by one line of a program end up
+// together rather than under String.new.
+const STD_LIB_CLASSES: [&str; 8] = ["Keyboard", "Math", "Memory", "Number", "Output", "Screen", "String", "Sys"];
+
+#[derive(Clone, Debug)]
+pub struct Allocation {
+    // The pointer which the allocator returned.
+    pub address: u16,
+    pub size: u16,
+    // Whether it came from calloc, which sets it all to zero.
+    pub zeroed: bool,
+    // How many instructions had run when the allocator was called.
+    pub tick: u64,
+    // Where each subroutine was when the allocator was called, innermost
+    // first, like "Main.main at Main.jack:5".
+    pub stack: Vec<String>,
+    pub site: String,
+}
+
+pub enum AllocationEvent {
+    Allocated(Allocation),
+    // The allocation, and where it was freed from.
+    Freed(Allocation, String),
+}
+
+#[derive(Default)]
+pub struct AllocationTracker {
+    ticks: u64,
+    // The allocation in progress, and the call depth it's made at.
+    pending: Option<(usize, Allocation)>,
+    live: BTreeMap<u16, Allocation>,
+    // Calls to deAlloc with pointers that weren't allocated, or have already
+    // been freed.
+    bad_frees: Vec<String>,
+}
+
+fn call_location(debug_info: &DebugInfo, subroutine_idx: usize, pc: Option<u16>) -> String {
+    let name = &debug_info.subroutines[subroutine_idx].name;
+    match pc.and_then(|pc| debug_info.location(pc)) {
+        Some(location) => format!("{} at {}:{}", name, location.filename, location.line),
+        None => name.clone(),
+    }
+}
+
+impl AllocationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Call after every tick, once the call tracker has been updated. Says
+    // when a block has been allocated or freed.
+    pub fn update(
+        &mut self,
+        debug_info: &DebugInfo,
+        call_tracker: &CallTracker,
+        computer: &Computer,
+        event: Option<&CallEvent>,
+    ) -> Option<AllocationEvent> {
+        self.ticks += 1;
+        match event {
+            Some(CallEvent::Call) => self.enter(debug_info, call_tracker, computer),
+            Some(CallEvent::Return) if self.pending.as_ref().is_some_and(|(depth, _)| call_tracker.depth() < *depth) => {
+                let (_, mut allocation) = self.pending.take().unwrap();
+                let ram = computer.ram.lock();
+                allocation.address = ram[ram[SP] as usize - 1];
+                // Null when the heap has run out.
+                if allocation.address == 0 {
+                    return None;
+                }
+                self.live.insert(allocation.address, allocation.clone());
+                Some(AllocationEvent::Allocated(allocation))
+            }
+            _ => None,
+        }
+    }
+
+    fn enter(&mut self, debug_info: &DebugInfo, call_tracker: &CallTracker, computer: &Computer) -> Option<AllocationEvent> {
+        let frames = call_tracker.frames();
+        let [.., caller, callee] = frames else {
+            return None;
+        };
+        let name = debug_info.subroutines[callee.subroutine_idx].name.as_str();
+        let caller_class = debug_info.subroutines[caller.subroutine_idx].class_name();
+        if caller_class == "Memory" || !(ALLOCATORS.contains(&name) || name == DEALLOCATOR) {
+            return None;
+        }
+
+        // Each frame is at the call into the one above it.
+        let callers = frames[..frames.len() - 1].iter().zip(&frames[1..]).rev();
+        let stack: Vec<_> = callers
+            .clone()
+            .map(|(frame, callee)| call_location(debug_info, frame.subroutine_idx, callee.call_pc))
+            .collect();
+        let site = callers
+            .clone()
+            .position(|(frame, _)| !STD_LIB_CLASSES.contains(&debug_info.subroutines[frame.subroutine_idx].class_name()))
+            .map_or(&stack[0], |idx| &stack[idx])
+            .clone();
+        let arg = computer.ram.lock()[callee.arg as usize];
+
+        if name == DEALLOCATOR {
+            let Some(allocation) = self.live.remove(&arg) else {
+                self.bad_frees.push(format!("{} freed {}, which isn't allocated", stack[0], arg));
+                return None;
+            };
+            return Some(AllocationEvent::Freed(allocation, stack[0].clone()));
+        }
+        let allocation = Allocation {
+            address: 0,
+            size: arg,
+            zeroed: name == "Memory.calloc",
+            tick: self.ticks,
+            stack,
+            site,
+        };
+        self.pending = Some((frames.len(), allocation));
+        None
+    }
+
+    // The blocks which haven't been freed yet, by address.
+    pub fn live(&self) -> impl Iterator<Item = &Allocation> {
+        self.live.values()
+    }
+
+    pub fn bad_frees(&self) -> &[String] {
+        &self.bad_frees
+    }
+
+    // What's still allocated, grouped by where it was allocated, with the
+    // sites which hold on to the most memory first.
+    pub fn leak_report(&self) -> String {
+        let mut sites: BTreeMap<&str, Vec<&Allocation>> = BTreeMap::new();
+        for allocation in self.live() {
+            sites.entry(&allocation.site).or_default().push(allocation);
+        }
+        let words = |allocations: &[&Allocation]| allocations.iter().map(|allocation| allocation.size as usize).sum::<usize>();
+        let mut sites: Vec<_> = sites.into_iter().collect();
+        sites.sort_by_key(|(_, allocations)| std::cmp::Reverse(words(allocations)));
+
+        let all: Vec<_> = self.live().collect();
+        let mut lines = vec![format!("{} blocks still allocated, {} words", all.len(), words(&all))];
+        for (site, allocations) in sites {
+            lines.push(format!("{} blocks, {} words, from {}", allocations.len(), words(&allocations), site));
+            for allocation in &allocations {
+                lines.push(format!(
+                    "  {} words at {}, allocated at tick {}",
+                    allocation.size, allocation.address, allocation.tick
+                ));
+            }
+            // The stacks differ between allocations from one site, but the
+            // first is enough to see how it was reached.
+            lines.extend(allocations[0].stack.iter().map(|location| format!("    {}", location)));
+        }
+        lines.extend(self.bad_frees.iter().cloned());
+        lines.join("\n")
+    }
+}
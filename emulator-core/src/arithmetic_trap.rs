@@ -0,0 +1,55 @@
+// Explains calls to Sys.arithmeticError, which is where the checked arithmetic
+// that the compiler can generate goes on overflow or division by zero. The
+// call comes from one of Math's checked functions, which was called by the
+// expression which went wrong, so that's the one to point at, along with the
+// numbers it was given.
+
+use crate::{
+    computer::Computer,
+    debug_info::{CallTracker, DebugInfo},
+    memory_layout::RAM_SIZE,
+};
+
+const TRAP: &str = "Sys.arithmeticError";
+const OVERFLOW: u16 = 1;
+
+// How each checked function shows its arguments. Only the arguments the
+// function takes are read, since whatever follows them is something else.
+fn describe_operation(name: &str, arg: impl Fn(usize) -> i16) -> Option<String> {
+    let operation = match name {
+        "Math.checkedAdd" => format!("{} + {}", arg(0), arg(1)),
+        "Math.checkedSubtract" => format!("{} - {}", arg(0), arg(1)),
+        "Math.checkedNegate" => format!("-({})", arg(0)),
+        "Math.checkedMultiply" => format!("{} * {}", arg(0), arg(1)),
+        "Math.checkedDivide" => format!("{} / {}", arg(0), arg(1)),
+        _ => return None,
+    };
+    Some(operation)
+}
+
+// Call when the call tracker has seen a call. Describes the arithmetic error
+// if it's a call to the trap.
+pub fn arithmetic_error(debug_info: &DebugInfo, call_tracker: &CallTracker, computer: &Computer) -> Option<String> {
+    let frames = call_tracker.frames();
+    let [.., caller, checked, trap] = frames else {
+        return None;
+    };
+    if debug_info.subroutines[trap.subroutine_idx].name != TRAP {
+        return None;
+    }
+    let ram = computer.ram.lock();
+    let error = match ram[trap.arg as usize % RAM_SIZE] {
+        OVERFLOW => "overflow",
+        _ => "division by zero",
+    };
+    let arg = |idx: usize| ram[(checked.arg as usize + idx) % RAM_SIZE] as i16;
+    let problem = match describe_operation(&debug_info.subroutines[checked.subroutine_idx].name, arg) {
+        Some(operation) => format!("{} in {}", error, operation),
+        None => error.to_string(),
+    };
+    let name = &debug_info.subroutines[caller.subroutine_idx].name;
+    Some(match checked.call_pc.and_then(|pc| debug_info.location(pc)) {
+        Some(location) => format!("{} at {}:{} in {}", problem, location.filename, location.line, name),
+        None => format!("{} in {}", problem, name),
+    })
+}
@@ -0,0 +1,113 @@
+// Works out the jack call stack from what's in RAM. Unlike `CallTracker`, which
+// has to see every call and return, this can be done at any point. Each frame
+// is laid out as
+//
+//     arguments | return address | saved caller pointers | locals | working stack
+//
+// and which of the caller's pointers get saved depends on the subroutine. The
+// debug info says how far into the innermost frame SP is at the current pc,
+// which gives where that frame starts. From there, the return address in each
+// frame leads to the call in the frame below it, until reaching the frame of
+// Sys.init at the bottom of the stack.
+
+use crate::{
+    computer::Computer,
+    debug_info::{format_value, DebugInfo, Frame},
+    memory_layout::{HEAP_BASE, SP, STACK_BASE},
+};
+
+#[derive(Clone, Debug)]
+pub struct Backtrace {
+    // Innermost frame first, along with the pc that each frame is at.
+    pub frames: Vec<(Frame, u16)>,
+    // Why unwinding stopped before reaching Sys.init, if it did. This happens
+    // part way through returning, once the frame has been taken apart, or if
+    // the stack has been trampled on.
+    pub error: Option<String>,
+}
+
+pub fn unwind(debug_info: &DebugInfo, computer: &Computer) -> Backtrace {
+    let ram = computer.ram.lock();
+    let mut frames = Vec::new();
+    let mut pc = computer.cpu.pc;
+    let mut sp = ram[SP] as i32;
+    let error = loop {
+        let Some(pc_info) = debug_info.pc_info(pc) else {
+            break Some(format!("pc {} isn't in any subroutine", pc));
+        };
+        let subroutine = &debug_info.subroutines[pc_info.subroutine_idx];
+        let lcl = sp - pc_info.stack_offset;
+        let return_address_idx = lcl - subroutine.saved_pointers.len() as i32 - 1;
+        let arg = return_address_idx - subroutine.arg_count as i32;
+        if arg < STACK_BASE as i32 || lcl > HEAP_BASE as i32 {
+            break Some(format!("the frame of {} would be outside of the stack", subroutine.name));
+        }
+
+        let return_address = ram[return_address_idx as usize];
+        // The return address is just after the jump at the end of the call.
+        let call_pc = return_address.wrapping_sub(1);
+        let caller = debug_info.pc_info(call_pc);
+        let frame = Frame {
+            subroutine_idx: pc_info.subroutine_idx,
+            call_pc: caller.map(|_| call_pc),
+            lcl: lcl as u16,
+            arg: arg as u16,
+        };
+        frames.push((frame, pc));
+
+        let Some(caller) = caller else {
+            // Sys.init is called by the boot code, with its frame at the very
+            // bottom of the stack.
+            break match arg == STACK_BASE as i32 {
+                true => None,
+                false => Some(format!(
+                    "{} returns to pc {}, which isn't in any subroutine",
+                    subroutine.name, return_address
+                )),
+            };
+        };
+        if caller.vm_command.split_whitespace().nth(1) != Some(subroutine.name.as_str()) {
+            break Some(format!(
+                "{} returns to pc {}, which isn't just after a call to it",
+                subroutine.name, return_address
+            ));
+        }
+        // Just before the jump, the caller has pushed everything up to where
+        // the callee's locals go.
+        pc = call_pc;
+        sp = lcl;
+    };
+    Backtrace { frames, error }
+}
+
+impl Backtrace {
+    // One line per frame, like `#1 Main.add(x = 1, y = 2) at Main.jack:7`.
+    pub fn format(&self, debug_info: &DebugInfo, computer: &Computer) -> String {
+        let ram = computer.ram.lock();
+        let mut lines: Vec<_> = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(idx, (frame, pc))| {
+                let subroutine = &debug_info.subroutines[frame.subroutine_idx];
+                let arguments: Vec<_> = subroutine
+                    .arguments
+                    .iter()
+                    .map(|var| {
+                        let value = ram[frame.arg as usize + var.index];
+                        format!("{} = {}", var.name, format_value(&var.type_name, value))
+                    })
+                    .collect();
+                let location = match debug_info.location(*pc) {
+                    Some(location) => format!("{}:{}", location.filename, location.line),
+                    None => format!("pc {}", pc),
+                };
+                format!("#{} {}({}) at {}", idx, subroutine.name, arguments.join(", "), location)
+            })
+            .collect();
+        if let Some(error) = &self.error {
+            lines.push(format!("(backtrace stops here: {})", error));
+        }
+        lines.join("\n")
+    }
+}
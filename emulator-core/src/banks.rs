@@ -0,0 +1,78 @@
+use crate::memory_layout::{BANK_COUNT, BANK_SELECT, BANK_SIZE, BANK_WINDOW, RAM_SIZE};
+
+// Extra RAM which is only reachable through the bank window. Selecting a bank
+// by writing to the bank select register swaps the contents of the window
+// out into the previously selected bank, and swaps the newly selected bank in.
+// Programs only ever see the window, so to them it looks like the window's
+// memory was instantly replaced.
+#[derive(Clone)]
+pub struct MemoryBanks {
+    banks: Vec<[u16; BANK_SIZE]>,
+    selected: usize,
+}
+
+impl Default for MemoryBanks {
+    fn default() -> Self {
+        Self {
+            banks: vec![[0; BANK_SIZE]; BANK_COUNT],
+            selected: 0,
+        }
+    }
+}
+
+impl MemoryBanks {
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    // The contents of a bank, as they would appear in the window if it was
+    // selected.
+    pub fn contents<'a>(&'a self, ram: &'a [u16; RAM_SIZE], bank: usize) -> &'a [u16] {
+        if bank == self.selected {
+            &ram[BANK_WINDOW..BANK_WINDOW + BANK_SIZE]
+        } else {
+            &self.banks[bank]
+        }
+    }
+
+    // Called after every write to the bank select register. Bank numbers wrap
+    // around, and the register is updated to hold the bank that actually got
+    // selected.
+    pub fn select(&mut self, ram: &mut [u16; RAM_SIZE], bank_select: u16) {
+        let bank = bank_select as usize % BANK_COUNT;
+        ram[BANK_SELECT] = bank as u16;
+        if bank == self.selected {
+            return;
+        }
+        let window = &mut ram[BANK_WINDOW..BANK_WINDOW + BANK_SIZE];
+        self.banks[self.selected].copy_from_slice(window);
+        window.copy_from_slice(&self.banks[bank]);
+        self.selected = bank;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let mut ram = [0; RAM_SIZE];
+        let mut banks = MemoryBanks::default();
+
+        ram[BANK_WINDOW] = 1;
+        banks.select(&mut ram, 3);
+        assert_eq!(ram[BANK_WINDOW], 0);
+        assert_eq!(banks.selected(), 3);
+        assert_eq!(banks.contents(&ram, 0)[0], 1);
+
+        ram[BANK_WINDOW + BANK_SIZE - 1] = 2;
+        banks.select(&mut ram, 0);
+        assert_eq!(ram[BANK_WINDOW], 1);
+        assert_eq!(banks.contents(&ram, 3)[BANK_SIZE - 1], 2);
+
+        banks.select(&mut ram, BANK_COUNT as u16 + 3);
+        assert_eq!(ram[BANK_SELECT], 3);
+        assert_eq!(ram[BANK_WINDOW + BANK_SIZE - 1], 2);
+    }
+}
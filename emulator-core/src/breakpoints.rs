@@ -0,0 +1,636 @@
+// Breakpoints which only stop when a condition holds, or once they've been hit
+// some number of times, and log points, which print a message and carry on
+// rather than stopping.
+//
+// Conditions are written in a small expression language, like `RAM[0] > 1900`
+// or `Main.main:i == 10`. Values are 16 bit words like in jack, so arithmetic
+// wraps around, comparisons are signed and true is -1. The operands are
+//
+//  - numbers, in decimal or in hex like 0x4000
+//  - the registers A, D and PC, and M for the word at A
+//  - the pointers SP, LCL, ARG, THIS and THAT
+//  - RAM[address], where the address can be any expression
+//  - jack variables, which need the debug output of the compiler: a variable
+//    of the subroutine which is running, like `i`, a static of any class,
+//    like `Main.count`, or a local or argument of the innermost call of a
+//    subroutine, like `Main.main:i`
+//
+// and the operators are, loosest first, `||`, `&&`, the comparisons (`==` or
+// `=`, `!=`, `<`, `<=`, `>`, `>=`), `|`, `&`, `+` and `-`, `*` and `/`, and
+// then the unary `-`, `!` and `~`. The names of registers and pointers win
+// over jack variables with the same name.
+
+use std::{collections::HashMap, fmt};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    backtrace::unwind,
+    computer::{tick, Computer},
+    debug_info::{DebugInfo, Frame, Variable},
+    memory_layout::{ARG, LCL, RAM_SIZE, SP, THAT, THIS},
+};
+
+const SYMBOLS: [&str; 22] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "=", "+", "-", "*", "/", "&", "|", "!", "~", "(", ")", "[", "]", ":",
+];
+
+// Binary operators by precedence, loosest first.
+const PRECEDENCE: [&[&str]; 7] = [
+    &["||"],
+    &["&&"],
+    &["==", "=", "!=", "<", "<=", ">", ">="],
+    &["|"],
+    &["&"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(u16),
+    Name(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            let number = &rest[..len];
+            let parsed = match number.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => number.parse(),
+            };
+            let number = parsed.map_err(|_| format!("{} isn't a 16 bit number", number))?;
+            (Token::Number(number), len)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            (Token::Name(rest[..len].to_string()), len)
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or_else(|| format!("unexpected {}", c))?;
+            (Token::Symbol(symbol), symbol.len())
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Register {
+    A,
+    D,
+    PC,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Number(u16),
+    Register(Register),
+    Ram(Box<Expr>),
+    // A local or argument of a subroutine when it's given, and otherwise any
+    // variable the running subroutine can see, or a static like `Main.count`.
+    Variable { subroutine: Option<String>, name: String },
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    idx: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.idx).cloned();
+        self.idx += 1;
+        token
+    }
+
+    fn eat(&mut self, symbols: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.idx) {
+            Some(Token::Symbol(symbol)) if symbols.contains(symbol) => {
+                self.idx += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), String> {
+        self.eat(&[symbol]).map(|_| ()).ok_or_else(|| format!("expected {}", symbol))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut expr = self.binary(level + 1)?;
+        while let Some(operator) = self.eat(operators) {
+            let rhs = self.binary(level + 1)?;
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.eat(&["-", "!", "~"]) {
+            Some(operator) => Ok(Expr::Unary(operator, Box::new(self.unary()?))),
+            None => self.operand(),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expr, String> {
+        let name = match self.next() {
+            Some(Token::Number(number)) => return Ok(Expr::Number(number)),
+            Some(Token::Symbol("(")) => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                return Ok(expr);
+            }
+            Some(Token::Symbol(symbol)) => return Err(format!("unexpected {}", symbol)),
+            Some(Token::Name(name)) => name,
+            None => return Err("the expression ends too soon".to_string()),
+        };
+        let pointer = |address: usize| Expr::Ram(Box::new(Expr::Number(address as u16)));
+        Ok(match name.as_str() {
+            "A" => Expr::Register(Register::A),
+            "D" => Expr::Register(Register::D),
+            "PC" => Expr::Register(Register::PC),
+            "M" => Expr::Ram(Box::new(Expr::Register(Register::A))),
+            "SP" => pointer(SP),
+            "LCL" => pointer(LCL),
+            "ARG" => pointer(ARG),
+            "THIS" => pointer(THIS),
+            "THAT" => pointer(THAT),
+            "RAM" => {
+                self.expect("[")?;
+                let address = self.binary(0)?;
+                self.expect("]")?;
+                Expr::Ram(Box::new(address))
+            }
+            _ if self.eat(&[":"]).is_some() => match self.next() {
+                Some(Token::Name(variable)) if !variable.contains('.') => Expr::Variable {
+                    subroutine: Some(name),
+                    name: variable,
+                },
+                _ => return Err(format!("expected a variable of {} after :", name)),
+            },
+            _ => Expr::Variable { subroutine: None, name },
+        })
+    }
+}
+
+// What an expression can see: the computer, and the jack call stack when
+// there's debug info.
+pub struct Scope<'a> {
+    pub computer: &'a Computer,
+    pub debug_info: Option<&'a DebugInfo>,
+    // Outermost first, like the call tracker's frames.
+    pub frames: &'a [Frame],
+}
+
+fn truth(value: bool) -> u16 {
+    match value {
+        true => 0xffff,
+        false => 0,
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, scope: &Scope, ram: &[u16]) -> Result<u16, String> {
+        Ok(match self {
+            Expr::Number(number) => *number,
+            Expr::Register(Register::A) => scope.computer.cpu.reg_a,
+            Expr::Register(Register::D) => scope.computer.cpu.reg_d,
+            Expr::Register(Register::PC) => scope.computer.cpu.pc,
+            Expr::Ram(address) => ram[address.evaluate(scope, ram)? as usize % RAM_SIZE],
+            Expr::Variable { subroutine, name } => variable(scope, ram, subroutine.as_deref(), name)?,
+            Expr::Unary(operator, operand) => {
+                let value = operand.evaluate(scope, ram)?;
+                match *operator {
+                    "-" => value.wrapping_neg(),
+                    "!" => truth(value == 0),
+                    _ => !value,
+                }
+            }
+            Expr::Binary("&&", lhs, rhs) => truth(lhs.evaluate(scope, ram)? != 0 && rhs.evaluate(scope, ram)? != 0),
+            Expr::Binary("||", lhs, rhs) => truth(lhs.evaluate(scope, ram)? != 0 || rhs.evaluate(scope, ram)? != 0),
+            Expr::Binary(operator, lhs, rhs) => {
+                let (x, y) = (lhs.evaluate(scope, ram)?, rhs.evaluate(scope, ram)?);
+                let (signed_x, signed_y) = (x as i16, y as i16);
+                match *operator {
+                    "==" | "=" => truth(x == y),
+                    "!=" => truth(x != y),
+                    "<" => truth(signed_x < signed_y),
+                    "<=" => truth(signed_x <= signed_y),
+                    ">" => truth(signed_x > signed_y),
+                    ">=" => truth(signed_x >= signed_y),
+                    "|" => x | y,
+                    "&" => x & y,
+                    "+" => x.wrapping_add(y),
+                    "-" => x.wrapping_sub(y),
+                    "*" => x.wrapping_mul(y),
+                    _ => match signed_y {
+                        0 => return Err("division by zero".to_string()),
+                        _ => signed_x.wrapping_div(signed_y) as u16,
+                    },
+                }
+            }
+        })
+    }
+}
+
+fn variable(scope: &Scope, ram: &[u16], subroutine: Option<&str>, name: &str) -> Result<u16, String> {
+    let debug_info = scope
+        .debug_info
+        .ok_or_else(|| format!("{} needs the debug output of the compiler", name))?;
+    if let Some(subroutine_name) = subroutine {
+        let frame = scope
+            .frames
+            .iter()
+            .rev()
+            .find(|frame| debug_info.subroutines[frame.subroutine_idx].name == subroutine_name)
+            .ok_or_else(|| format!("{} isn't running", subroutine_name))?;
+        let subroutine = &debug_info.subroutines[frame.subroutine_idx];
+        let find = |variables: &[Variable], base: u16| {
+            let var = variables.iter().find(|var| var.name == name)?;
+            Some(ram[(base as usize + var.index) % RAM_SIZE])
+        };
+        return find(&subroutine.locals, frame.lcl)
+            .or_else(|| find(&subroutine.arguments, frame.arg))
+            .ok_or_else(|| format!("there's no local or argument called {} in {}", name, subroutine_name));
+    }
+    if let Some((class_name, static_name)) = name.split_once('.') {
+        return debug_info
+            .class_layout(class_name)
+            .into_iter()
+            .flat_map(|class_layout| &class_layout.statics)
+            .find(|var| var.name == static_name)
+            .and_then(|var| Some(ram[var.address? as usize]))
+            .ok_or_else(|| format!("there's no static called {}", name));
+    }
+    let frame = scope.frames.last().ok_or("there's no jack subroutine running")?;
+    debug_info.variable(frame, ram, name).map(|(_, value)| value).ok_or_else(|| {
+        let subroutine = &debug_info.subroutines[frame.subroutine_idx];
+        format!("there's no variable called {} in {}", name, subroutine.name)
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expression {
+    source: String,
+    expr: Expr,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            idx: 0,
+        };
+        let expr = parser.binary(0)?;
+        if let Some(token) = parser.tokens.get(parser.idx) {
+            return Err(format!("unexpected {} in {}", token, source));
+        }
+        Ok(Self {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn evaluate(&self, scope: &Scope) -> Result<u16, String> {
+        let ram = scope.computer.ram.lock();
+        self.expr.evaluate(scope, &*ram)
+    }
+}
+
+// When a breakpoint stops, going by how many times it has been hit. A bare
+// count means once it's been hit that many times, like `>= count`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HitCondition {
+    Equal(u64),
+    Greater(u64),
+    GreaterOrEqual(u64),
+    Less(u64),
+    LessOrEqual(u64),
+    Multiple(u64),
+}
+
+impl HitCondition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let operator = [">=", "<=", "==", ">", "<", "=", "%"]
+            .into_iter()
+            .find(|operator| text.starts_with(operator))
+            .unwrap_or_default();
+        let count = text[operator.len()..]
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} isn't a hit count, like 5, >= 5 or % 5", text))?;
+        Ok(match operator {
+            "==" | "=" => HitCondition::Equal(count),
+            ">" => HitCondition::Greater(count),
+            "<" => HitCondition::Less(count),
+            "<=" => HitCondition::LessOrEqual(count),
+            "%" if count == 0 => return Err("hits can't be a multiple of 0".to_string()),
+            "%" => HitCondition::Multiple(count),
+            _ => HitCondition::GreaterOrEqual(count),
+        })
+    }
+
+    fn holds(&self, hits: u64) -> bool {
+        match *self {
+            HitCondition::Equal(count) => hits == count,
+            HitCondition::Greater(count) => hits > count,
+            HitCondition::GreaterOrEqual(count) => hits >= count,
+            HitCondition::Less(count) => hits < count,
+            HitCondition::LessOrEqual(count) => hits <= count,
+            HitCondition::Multiple(count) => hits % count == 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum LogPart {
+    Text(String),
+    Expression(Expression),
+}
+
+// A message with expressions in braces, like `i is {i}`, which are filled in
+// with their values when it's logged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogMessage {
+    parts: Vec<LogPart>,
+}
+
+impl LogMessage {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("there's no }} to match the {{ in {}", text))?;
+            parts.push(LogPart::Text(rest[..start].to_string()));
+            parts.push(LogPart::Expression(Expression::parse(&rest[start + 1..start + end])?));
+            rest = &rest[start + end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("there's no {{ to match the }} in {}", text));
+        }
+        parts.push(LogPart::Text(rest.to_string()));
+        parts.retain(|part| *part != LogPart::Text(String::new()));
+        Ok(Self { parts })
+    }
+
+    // Values are shown as signed numbers, and expressions which can't be
+    // worked out show why instead.
+    pub fn format(&self, scope: &Scope) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                LogPart::Text(text) => text.clone(),
+                LogPart::Expression(expression) => match expression.evaluate(scope) {
+                    Ok(value) => (value as i16).to_string(),
+                    Err(err) => format!("<{}>", err),
+                },
+            })
+            .collect()
+    }
+}
+
+fn non_empty(text: Option<&str>) -> Option<&str> {
+    text.filter(|text| !text.trim().is_empty())
+}
+
+// What to do on reaching a breakpoint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Hit {
+    Stop,
+    Log(String),
+    Continue,
+}
+
+// A breakpoint with no condition, hit condition or log message always stops.
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoint {
+    pub condition: Option<Expression>,
+    pub hit_condition: Option<HitCondition>,
+    // Makes it a log point, which never stops.
+    pub log_message: Option<LogMessage>,
+    // How many times the condition has held here.
+    hits: u64,
+}
+
+impl Breakpoint {
+    // Each part is optional, as they come from editors and the command line.
+    pub fn parse(condition: Option<&str>, hit_condition: Option<&str>, log_message: Option<&str>) -> Result<Self, String> {
+        Ok(Self {
+            condition: non_empty(condition).map(Expression::parse).transpose()?,
+            hit_condition: non_empty(hit_condition).map(HitCondition::parse).transpose()?,
+            log_message: non_empty(log_message).map(LogMessage::parse).transpose()?,
+            hits: 0,
+        })
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    // Call when the pc reaches the breakpoint. Fails when the condition can't
+    // be worked out, like when it uses a variable which isn't there.
+    pub fn hit(&mut self, scope: &Scope) -> Result<Hit, String> {
+        if let Some(condition) = &self.condition {
+            let value = condition
+                .evaluate(scope)
+                .map_err(|err| format!("can't check the condition {}: {}", condition.source(), err))?;
+            if value == 0 {
+                return Ok(Hit::Continue);
+            }
+        }
+        self.hits += 1;
+        if !self.hit_condition.map_or(true, |hit_condition| hit_condition.holds(self.hits)) {
+            return Ok(Hit::Continue);
+        }
+        Ok(match &self.log_message {
+            Some(log_message) => Hit::Log(log_message.format(scope)),
+            None => Hit::Stop,
+        })
+    }
+}
+
+// Conditional breakpoints for the browser, which runs the computer on its own
+// rather than through a `Debugger`. Jack variables are found by unwinding the
+// stack from RAM, so they work without watching the program from the start.
+#[wasm_bindgen]
+pub struct ConditionalBreakpoints {
+    debug_info: Option<DebugInfo>,
+    breakpoints: HashMap<u16, Breakpoint>,
+    logs: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl ConditionalBreakpoints {
+    // The debug output of the compiler is only needed for jack variables.
+    #[wasm_bindgen(constructor)]
+    pub fn new(debug_json: Option<String>) -> Result<ConditionalBreakpoints, JsValue> {
+        let debug_info = debug_json
+            .map(|json| DebugInfo::from_json(&json))
+            .transpose()
+            .map_err(|err| JsValue::from_str(&err))?;
+        Ok(Self {
+            debug_info,
+            breakpoints: HashMap::new(),
+            logs: Vec::new(),
+        })
+    }
+
+    // Replaces any breakpoint at the pc. Empty strings are the same as none.
+    pub fn set(&mut self, pc: u16, condition: Option<String>, hit_condition: Option<String>, log_message: Option<String>) -> Result<(), JsValue> {
+        let breakpoint =
+            Breakpoint::parse(condition.as_deref(), hit_condition.as_deref(), log_message.as_deref()).map_err(|err| JsValue::from_str(&err))?;
+        self.breakpoints.insert(pc, breakpoint);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, pc: u16) -> bool {
+        self.breakpoints.remove(&pc).is_some()
+    }
+
+    // Like `tick_to_some_breakpoint`, but gives up after `max_ticks` so that
+    // the page can carry on drawing. Returns whether a breakpoint stopped,
+    // and fails when the computer faults or a condition can't be worked out.
+    pub fn tick_to_breakpoint(&mut self, computer: &mut Computer, max_ticks: usize) -> Result<bool, JsValue> {
+        for _ in 0..max_ticks {
+            tick(computer);
+            if let Some(fault) = computer.fault() {
+                return Err(JsValue::from_str(&fault.to_string()));
+            }
+            let Some(breakpoint) = self.breakpoints.get_mut(&computer.cpu.pc) else {
+                continue;
+            };
+            let frames: Vec<_> = match &self.debug_info {
+                Some(debug_info) => unwind(debug_info, computer).frames.into_iter().rev().map(|(frame, _)| frame).collect(),
+                None => Vec::new(),
+            };
+            let scope = Scope {
+                computer,
+                debug_info: self.debug_info.as_ref(),
+                frames: &frames,
+            };
+            match breakpoint.hit(&scope).map_err(|err| JsValue::from_str(&err))? {
+                Hit::Stop => return Ok(true),
+                Hit::Log(message) => self.logs.push(message),
+                Hit::Continue => {}
+            }
+        }
+        Ok(false)
+    }
+
+    // The messages from log points since this was last called.
+    pub fn take_logs(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.logs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str, computer: &Computer) -> Result<i16, String> {
+        let scope = Scope {
+            computer,
+            debug_info: None,
+            frames: &[],
+        };
+        Expression::parse(source)?.evaluate(&scope).map(|value| value as i16)
+    }
+
+    #[test]
+    fn test_expressions() {
+        let mut computer = Computer::new([0; 32768]);
+        computer.cpu.reg_a = 1000;
+        computer.cpu.reg_d = -5_i16 as u16;
+        {
+            let mut ram = computer.ram.lock();
+            ram[SP] = 1950;
+            ram[1000] = 7;
+        }
+        assert_eq!(evaluate("RAM[0] > 1900", &computer), Ok(-1));
+        assert_eq!(evaluate("SP == 1950 && M = 7", &computer), Ok(-1));
+        assert_eq!(evaluate("D < 0 || RAM[A + 1] != 0", &computer), Ok(-1));
+        assert_eq!(evaluate("!(D < 0)", &computer), Ok(0));
+        assert_eq!(evaluate("1 + 2 * 3 - -4", &computer), Ok(11));
+        assert_eq!(evaluate("(1 + 2) * 3 / 2", &computer), Ok(4));
+        assert_eq!(evaluate("32767 + 1", &computer), Ok(-32768));
+        assert_eq!(evaluate("0xff & ~0xf | 1", &computer), Ok(0xf1));
+        assert_eq!(evaluate("1 / (D + 5)", &computer), Err("division by zero".to_string()));
+        assert_eq!(
+            evaluate("Main.main:i == 1", &computer),
+            Err("i needs the debug output of the compiler".to_string())
+        );
+        assert!(Expression::parse("RAM[0").is_err());
+        assert!(Expression::parse("1 +").is_err());
+        assert!(Expression::parse("1 2").is_err());
+        assert!(Expression::parse("Main.main:").is_err());
+        assert!(Expression::parse("65536").is_err());
+    }
+
+    #[test]
+    fn test_hit_conditions() {
+        let hits = |text: &str| {
+            let hit_condition = HitCondition::parse(text).unwrap();
+            (1..=6).filter(|&hits| hit_condition.holds(hits)).collect::<Vec<_>>()
+        };
+        assert_eq!(hits("3"), [3, 4, 5, 6]);
+        assert_eq!(hits("== 3"), [3]);
+        assert_eq!(hits(">3"), [4, 5, 6]);
+        assert_eq!(hits("<= 2"), [1, 2]);
+        assert_eq!(hits("% 2"), [2, 4, 6]);
+        assert!(HitCondition::parse("% 0").is_err());
+        assert!(HitCondition::parse("often").is_err());
+    }
+
+    #[test]
+    fn test_breakpoint_hits() {
+        let computer = Computer::new([0; 32768]);
+        let scope = Scope {
+            computer: &computer,
+            debug_info: None,
+            frames: &[],
+        };
+        let mut breakpoint = Breakpoint::parse(None, Some("% 2"), Some("SP is {SP}, {RAM[100] / 0}")).unwrap();
+        assert_eq!(breakpoint.hit(&scope), Ok(Hit::Continue));
+        assert_eq!(breakpoint.hit(&scope), Ok(Hit::Log("SP is 0, <division by zero>".to_string())));
+        assert_eq!(breakpoint.hits(), 2);
+
+        let mut breakpoint = Breakpoint::parse(Some("PC == 1"), Some(""), None).unwrap();
+        assert_eq!(breakpoint.hit(&scope), Ok(Hit::Continue));
+        assert_eq!(breakpoint.hits(), 0);
+        assert_eq!(Breakpoint::default().hit(&scope), Ok(Hit::Stop));
+        assert!(Breakpoint::parse(None, None, Some("{x")).is_err());
+    }
+}
@@ -1,9 +1,19 @@
 use std::{
     num::Wrapping,
+    ops::Range,
     sync::{Arc, Mutex, MutexGuard},
 };
 
-use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::prelude::{wasm_bindgen, JsValue};
+
+use crate::{
+    banks::MemoryBanks,
+    display::{self, DEFAULT_PALETTE, SCREEN_HEIGHT, SCREEN_WIDTH},
+    fault::Fault,
+    memory_layout::{BANK_SELECT, DEFAULT_PROTECTED_REGIONS, HEAP_BASE, PALETTE, PALETTE_SIZE, RAM_SIZE, SP, STACK_BASE, WRITE_PROTECT},
+    png,
+    ram_image::RamImage,
+};
 
 pub fn bit(instruction: u16, idx: u32) -> u16 {
     (instruction & (2u16).pow(idx)) >> idx
@@ -109,7 +119,7 @@ pub fn get_ram_word(ram: &Ram, addr: usize, display_base: WordDisplayBase) -> St
 pub struct Ram(Arc<Mutex<[u16; 32768]>>);
 
 impl Ram {
-    pub fn lock(&self) -> MutexGuard<[u16; 32768]> {
+    pub fn lock(&self) -> MutexGuard<'_, [u16; 32768]> {
         self.0.lock().unwrap()
     }
 }
@@ -119,22 +129,81 @@ pub struct Computer {
     rom: [u16; 32768],
     pub ram: Ram,
     pub cpu: Cpu,
+    banks: MemoryBanks,
+    protected_regions: Vec<Range<usize>>,
+    write_protection_armed: bool,
+    check_stack: bool,
+    fault: Option<Fault>,
+}
+
+// The A register is 16 bits wide but there are only 15 address lines, so the
+// most significant bit is ignored, for reads and writes alike.
+fn address(reg_a: u16) -> usize {
+    reg_a as usize % RAM_SIZE
 }
 
+// SP is only set to somewhere outside of the stack region by the boot code,
+// before anything is pushed, or by something going wrong. The stack is full
+// when SP reaches the heap, and it's the push which moves SP past that which
+// faults. Writes to the start of the heap are left alone, since the heap uses
+// it too.
+fn stack_fault(ram: &[u16], pc: u16, address: usize, value: u16) -> Option<Fault> {
+    if address != SP {
+        return None;
+    }
+    if value as usize > HEAP_BASE {
+        return Some(Fault::StackOverflow { pc, sp: value });
+    }
+    if (value as usize) < STACK_BASE && ram[SP] as usize >= STACK_BASE {
+        return Some(Fault::StackUnderflow { pc, sp: value });
+    }
+    None
+}
+
+fn write_memory(computer: &mut Computer, pc: u16, address: usize, value: u16) {
+    if computer.write_protection_armed && computer.protected_regions.iter().any(|region| region.contains(&address)) {
+        computer.fault = Some(Fault::WriteProtected { pc, address: address as u16 });
+        return;
+    }
+
+    let mut ram = computer.ram.lock();
+    if computer.check_stack {
+        if let Some(fault) = stack_fault(&*ram, pc, address, value) {
+            computer.fault = Some(fault);
+            return;
+        }
+    }
+    ram[address] = value;
+    if address == BANK_SELECT {
+        computer.banks.select(&mut ram, value);
+    } else if address == WRITE_PROTECT && value != 0 {
+        computer.write_protection_armed = true;
+    }
+}
+
+// Does nothing once the computer has faulted.
 #[wasm_bindgen]
 pub fn tick(computer: &mut Computer) {
+    if computer.fault.is_some() {
+        return;
+    }
+
     // FETCH
-    let prev_reg_a = computer.cpu.reg_a;
-    let instruction = computer.rom[computer.cpu.pc as usize];
-    let addr = computer.cpu.reg_a as usize % computer.ram.lock().len();
+    let instruction = computer.rom[computer.cpu.pc as usize % computer.rom.len()];
+    let addr = address(computer.cpu.reg_a);
     let in_m = computer.ram.lock()[addr];
     computer.cpu.instruction = instruction;
     computer.cpu.in_m = in_m;
 
     // EXECUTE
+    let prev_pc = computer.cpu.pc;
     computer.cpu.execute();
     if computer.cpu.memory_load {
-        computer.ram.lock()[prev_reg_a as usize] = computer.cpu.out_m;
+        write_memory(computer, prev_pc, addr, computer.cpu.out_m);
+        if computer.fault.is_some() {
+            // Leave the pc pointing at the faulting instruction.
+            computer.cpu.pc = prev_pc;
+        }
     }
 }
 
@@ -144,11 +213,34 @@ pub fn tick_until(computer: &mut Computer, predicate: &dyn Fn(&Computer) -> bool
         if predicate(computer) {
             return;
         }
+        if let Some(fault) = &computer.fault {
+            panic!("computer faulted: {}", fault);
+        }
         tick(computer);
     }
     panic!("predicate was not true within {} ticks", max_ticks);
 }
 
+// For loading a ram image one segment at a time from javascript.
+#[wasm_bindgen]
+pub fn load_ram_segment(computer: &mut Computer, address: usize, words: &[u16]) -> Result<(), JsValue> {
+    let range = ram_segment(address, words.len()).map_err(|err| JsValue::from_str(&err))?;
+    computer.ram.lock()[range].copy_from_slice(words);
+    Ok(())
+}
+
+fn ram_segment(address: usize, len: usize) -> Result<Range<usize>, String> {
+    match address.checked_add(len) {
+        Some(end) if end <= RAM_SIZE => Ok(address..end),
+        _ => Err(format!("a segment of {} words at {} doesn't fit in RAM", len, address)),
+    }
+}
+
+#[wasm_bindgen]
+pub fn get_fault(computer: &Computer) -> Option<String> {
+    computer.fault.as_ref().map(|fault| fault.to_string())
+}
+
 #[wasm_bindgen]
 pub fn tick_to_breakpoint(computer: &mut Computer, breakpoint: u16) {
     tick_until(computer, &|comp| comp.cpu.pc == breakpoint)
@@ -157,14 +249,18 @@ pub fn tick_to_breakpoint(computer: &mut Computer, breakpoint: u16) {
 #[wasm_bindgen]
 pub fn tick_to_some_breakpoint(computer: &mut Computer, breakpoints: &[u16]) {
     tick(computer);
-    tick_until(computer, &|comp| breakpoints.iter().any(|breakpoint| comp.cpu.pc == *breakpoint))
+    tick_until(computer, &|comp| breakpoints.contains(&comp.cpu.pc))
 }
 
 impl Computer {
     pub fn new(rom: [u16; 32768]) -> Self {
+        let mut ram = [0; 32768];
+        // The palette registers are the only memory with a non-zero value
+        // at power on.
+        ram[PALETTE..PALETTE + PALETTE_SIZE].copy_from_slice(&DEFAULT_PALETTE);
         Self {
             rom,
-            ram: Ram(Arc::new(Mutex::new([0; 32768]))),
+            ram: Ram(Arc::new(Mutex::new(ram))),
             cpu: Cpu {
                 reg_a: 0,
                 reg_d: 0,
@@ -174,22 +270,62 @@ impl Computer {
                 instruction: 0,
                 in_m: 0,
             },
+            banks: MemoryBanks::default(),
+            protected_regions: DEFAULT_PROTECTED_REGIONS.to_vec(),
+            write_protection_armed: false,
+            check_stack: false,
+            fault: None,
         }
     }
 
+    pub fn with_ram_image(rom: [u16; 32768], ram_image: &RamImage) -> Self {
+        let computer = Self::new(rom);
+        ram_image.load(&mut computer.ram.lock());
+        computer
+    }
+
+    // Protected regions only take effect once a program arms write protection
+    // by writing to the write protect register, so that it has a chance to
+    // initialize them first.
+    pub fn set_protected_regions(&mut self, regions: Vec<Range<usize>>) {
+        self.protected_regions = regions;
+    }
+
+    pub fn protected_regions(&self) -> &[Range<usize>] {
+        &self.protected_regions
+    }
+
+    // With stack checking on, the computer faults when the stack runs into
+    // the heap or below its base, rather than carrying on with the heap
+    // trampled. Only for programs from the compiler, which keep SP in the
+    // stack region.
+    pub fn set_stack_checking(&mut self, check_stack: bool) {
+        self.check_stack = check_stack;
+    }
+
+    pub fn rom(&self) -> &[u16; 32768] {
+        &self.rom
+    }
+
+    pub fn rom_mut(&mut self) -> &mut [u16; 32768] {
+        &mut self.rom
+    }
+
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    pub fn banks(&self) -> &MemoryBanks {
+        &self.banks
+    }
+
     pub fn screen_snapshot(&self) -> Vec<u8> {
-        let height = 256;
-        let width = 512;
-        let mut bytes: Vec<_> = format!("P4\n{} {}\n", width, height).bytes().collect();
-        let screen = 18432;
-        let screen_memory = &self.ram.lock()[screen..=26623];
-        for word in screen_memory {
-            // pbm format is (usually) big-endian
-            let [msb, lsb] = word.to_be_bytes();
-            bytes.push(!msb);
-            bytes.push(!lsb);
-        }
-        bytes
+        display::screen_pbm(&*self.ram.lock())
+    }
+
+    pub fn screen_png(&self) -> Vec<u8> {
+        let pixels = display::render_rgb(&*self.ram.lock());
+        png::encode_rgb(SCREEN_WIDTH, SCREEN_HEIGHT, &pixels)
     }
 }
 
@@ -197,6 +333,19 @@ impl Computer {
 #[allow(overflowing_literals, unused_imports)]
 mod tests {
     use super::*;
+    use crate::memory_layout::GLYPHS;
+
+    #[test]
+    fn test_ram_segment() {
+        let mut computer = computer_with_program(&[]);
+        load_ram_segment(&mut computer, RAM_SIZE - 2, &[1, 2]).unwrap();
+        assert_eq!(computer.ram.lock()[RAM_SIZE - 2..], [1, 2]);
+        assert_eq!(
+            ram_segment(RAM_SIZE - 1, 2),
+            Err(format!("a segment of 2 words at {} doesn't fit in RAM", RAM_SIZE - 1))
+        );
+        assert!(ram_segment(usize::MAX, 1).is_err());
+    }
 
     #[test]
     fn test_get_bit() {
@@ -215,4 +364,124 @@ mod tests {
         assert_eq!(comp_bits(0b1110101010010111), 0b0101010);
         assert_eq!(comp_bits(0b1111010101111010), 0b1010101);
     }
+
+    fn computer_with_program(program: &[u16]) -> Computer {
+        let mut rom = [0; 32768];
+        rom[..program.len()].copy_from_slice(program);
+        Computer::new(rom)
+    }
+
+    const M_EQ_1: u16 = 0xefc8;
+    const A_EQ_NEG_1: u16 = 0xeea0;
+
+    #[test]
+    fn test_write_protection() {
+        let mut computer = computer_with_program(&[GLYPHS as u16, M_EQ_1, WRITE_PROTECT as u16, M_EQ_1, GLYPHS as u16 + 1, M_EQ_1]);
+        for _ in 0..5 {
+            tick(&mut computer);
+        }
+        assert_eq!(computer.ram.lock()[GLYPHS], 1, "writes are allowed before protection is armed");
+        assert_eq!(computer.fault(), None);
+
+        tick(&mut computer);
+        assert_eq!(
+            computer.fault(),
+            Some(&Fault::WriteProtected {
+                pc: 5,
+                address: GLYPHS as u16 + 1
+            })
+        );
+        assert_eq!(computer.ram.lock()[GLYPHS + 1], 0);
+        assert_eq!(computer.cpu.pc, 5);
+
+        tick(&mut computer);
+        assert_eq!(computer.cpu.pc, 5, "a faulted computer doesn't execute instructions");
+    }
+
+    const A_EQ_M: u16 = 0xfc20;
+    const M_EQ_M_PLUS_1: u16 = 0xfdc8;
+    const M_EQ_M_MINUS_1: u16 = 0xfc88;
+
+    #[test]
+    fn test_stack_overflow() {
+        // Push 1 with the stack full.
+        let mut computer = computer_with_program(&[SP as u16, A_EQ_M, M_EQ_1, SP as u16, M_EQ_M_PLUS_1]);
+        computer.set_stack_checking(true);
+        computer.ram.lock()[SP] = HEAP_BASE as u16;
+        for _ in 0..5 {
+            tick(&mut computer);
+        }
+        assert_eq!(
+            computer.fault(),
+            Some(&Fault::StackOverflow {
+                pc: 4,
+                sp: HEAP_BASE as u16 + 1
+            })
+        );
+
+        // Moving SP past the top of the stack, as making space for locals does.
+        let mut computer = computer_with_program(&[SP as u16, M_EQ_M_PLUS_1]);
+        computer.set_stack_checking(true);
+        computer.ram.lock()[SP] = HEAP_BASE as u16;
+        tick(&mut computer);
+        tick(&mut computer);
+        assert_eq!(
+            computer.fault(),
+            Some(&Fault::StackOverflow {
+                pc: 1,
+                sp: HEAP_BASE as u16 + 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_stack_full() {
+        // Push 1 so that the stack reaches the heap, then write to the start
+        // of the heap, as the allocator does.
+        let mut computer = computer_with_program(&[SP as u16, A_EQ_M, M_EQ_1, SP as u16, M_EQ_M_PLUS_1, HEAP_BASE as u16, M_EQ_1]);
+        computer.set_stack_checking(true);
+        computer.ram.lock()[SP] = HEAP_BASE as u16 - 1;
+        for _ in 0..7 {
+            tick(&mut computer);
+        }
+        assert_eq!(computer.fault(), None);
+        let ram = computer.ram.lock();
+        assert_eq!(ram[SP], HEAP_BASE as u16);
+        assert_eq!(ram[HEAP_BASE - 1..HEAP_BASE + 1], [1, 1]);
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        let mut computer = computer_with_program(&[SP as u16, M_EQ_M_MINUS_1]);
+        computer.set_stack_checking(true);
+        computer.ram.lock()[SP] = STACK_BASE as u16;
+        tick(&mut computer);
+        tick(&mut computer);
+        assert_eq!(
+            computer.fault(),
+            Some(&Fault::StackUnderflow {
+                pc: 1,
+                sp: STACK_BASE as u16 - 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_stack_checking_off() {
+        let mut computer = computer_with_program(&[SP as u16, A_EQ_M, M_EQ_1]);
+        computer.ram.lock()[SP] = HEAP_BASE as u16;
+        for _ in 0..3 {
+            tick(&mut computer);
+        }
+        assert_eq!(computer.fault(), None);
+        assert_eq!(computer.ram.lock()[HEAP_BASE], 1);
+    }
+
+    #[test]
+    fn test_address_msb_ignored() {
+        let mut computer = computer_with_program(&[A_EQ_NEG_1, M_EQ_1]);
+        tick(&mut computer);
+        tick(&mut computer);
+        assert_eq!(computer.ram.lock()[RAM_SIZE - 1], 1);
+    }
 }
@@ -0,0 +1,340 @@
+// Records which jack statements, branches and subroutines a program runs, for
+// coverage reports. Statements are counted at their first pc. Branches are
+// the if-goto commands which the compiler makes for if and while statements,
+// and each one either jumps or falls through:
+//
+//  - an if jumps to its if arm, and falls through to its else arm, which is
+//    empty when there's no else
+//  - a while falls through into its body, and jumps out of the loop
+//
+// Subroutines which the compiler left out as dead code aren't in the ROM, so
+// they aren't in the report either.
+//
+// A report is keyed by file and line rather than pc, so that reports from
+// different programs, like all the tests in a suite, can be merged.
+
+use std::collections::BTreeMap;
+
+use crate::debug_info::{CallEvent, CallTracker, DebugInfo};
+
+const ROM_SIZE: usize = 32768;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchKind {
+    If,
+    While,
+}
+
+fn branch_kind(vm_command: &str) -> Option<BranchKind> {
+    let label = vm_command.strip_prefix("if-goto ")?;
+    if label.starts_with("if_statements_") {
+        Some(BranchKind::If)
+    } else if label.starts_with("end_while_") {
+        Some(BranchKind::While)
+    } else {
+        None
+    }
+}
+
+pub struct Coverage {
+    // How many times the instruction at each pc has run.
+    pc_ticks: Vec<u64>,
+    // How many times each conditional jump has jumped and fallen through.
+    jumps: BTreeMap<u16, [u64; 2]>,
+    // The pcs with a conditional jump for a branch.
+    is_jump: Vec<bool>,
+    calls: BTreeMap<usize, u64>,
+}
+
+impl Coverage {
+    pub fn new(debug_info: &DebugInfo) -> Self {
+        let mut is_jump = vec![false; ROM_SIZE];
+        let mut jumps = BTreeMap::new();
+        // An if-goto is a pop into D and then a jump, so the jump is its last
+        // instruction.
+        for pc in 0..debug_info.rom.len() as u16 {
+            let Some(pc_info) = debug_info.pc_info(pc) else {
+                continue;
+            };
+            let is_last = debug_info.pc_info(pc + 1).map_or(true, |next| {
+                (&next.filename, next.vm_command_idx) != (&pc_info.filename, pc_info.vm_command_idx)
+            });
+            if is_last && branch_kind(&pc_info.vm_command).is_some() {
+                is_jump[pc as usize] = true;
+                jumps.insert(pc, [0, 0]);
+            }
+        }
+        Self {
+            pc_ticks: vec![0; ROM_SIZE],
+            jumps,
+            is_jump,
+            calls: BTreeMap::new(),
+        }
+    }
+
+    // Call after every tick, with the pc from before the tick and what the
+    // call tracker made of it.
+    pub fn update(&mut self, call_tracker: &CallTracker, prev_pc: u16, pc: u16, event: Option<&CallEvent>) {
+        let prev_pc_idx = prev_pc as usize % ROM_SIZE;
+        self.pc_ticks[prev_pc_idx] += 1;
+        if self.is_jump[prev_pc_idx] {
+            let jumped = pc != prev_pc.wrapping_add(1);
+            self.jumps.get_mut(&prev_pc).unwrap()[jumped as usize] += 1;
+        }
+        if let (Some(CallEvent::Call), Some(frame)) = (event, call_tracker.frames().last()) {
+            *self.calls.entry(frame.subroutine_idx).or_default() += 1;
+        }
+    }
+
+    pub fn report(&self, debug_info: &DebugInfo) -> CoverageReport {
+        let mut report = CoverageReport::default();
+        for (pc, location) in debug_info.statements() {
+            let hits = self.pc_ticks[pc as usize];
+            let line_hits = report.file(&location.filename).lines.entry(location.line).or_default();
+            *line_hits = (*line_hits).max(hits);
+        }
+        for (subroutine_idx, subroutine) in debug_info.subroutines.iter().enumerate() {
+            let Some(entry_pc) = subroutine.entry_pc else {
+                continue;
+            };
+            let line = debug_info.location(entry_pc).map_or(0, |location| location.line);
+            let calls = self.calls.get(&subroutine_idx).copied().unwrap_or_default();
+            report.file(&subroutine.filename).functions.insert(subroutine.name.clone(), (line, calls));
+        }
+        for (&pc, &[fell_through, jumped]) in &self.jumps {
+            let (Some(pc_info), Some(location)) = (debug_info.pc_info(pc), debug_info.location(pc)) else {
+                continue;
+            };
+            let kind = branch_kind(&pc_info.vm_command).unwrap();
+            let arms = match kind {
+                BranchKind::If => [jumped, fell_through],
+                BranchKind::While => [fell_through, jumped],
+            };
+            let branches = &mut report.file(&location.filename).branches;
+            // Branches on the same line are numbered in the order they're laid
+            // out in.
+            let block = branches.range((location.line, 0)..(location.line + 1, 0)).count();
+            branches.insert((location.line, block), BranchCoverage { kind, arms });
+        }
+        report
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BranchCoverage {
+    pub kind: BranchKind,
+    // How many times each arm was taken. The if arm then the else arm for an
+    // if, and the body then leaving the loop for a while.
+    pub arms: [u64; 2],
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileCoverage {
+    // How many times the most run statement on each line ran.
+    pub lines: BTreeMap<usize, u64>,
+    // Each subroutine, with the line it starts on and how many times it was
+    // called.
+    pub functions: BTreeMap<String, (usize, u64)>,
+    // By line, and then by which branch it is on that line.
+    pub branches: BTreeMap<(usize, usize), BranchCoverage>,
+}
+
+impl FileCoverage {
+    fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|&&hits| hits > 0).count()
+    }
+
+    fn arms_hit(&self) -> usize {
+        self.branches.values().flat_map(|branch| branch.arms).filter(|&hits| hits > 0).count()
+    }
+
+    fn functions_hit(&self) -> usize {
+        self.functions.values().filter(|(_, calls)| *calls > 0).count()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+fn percentage(hit: usize, total: usize) -> String {
+    match total {
+        0 => "-".to_string(),
+        _ => format!("{:.1}%", hit as f64 * 100.0 / total as f64),
+    }
+}
+
+impl CoverageReport {
+    fn file(&mut self, filename: &str) -> &mut FileCoverage {
+        self.files.entry(filename.to_string()).or_default()
+    }
+
+    // Adds up the counts from another report, like one for another test.
+    pub fn merge(&mut self, other: &CoverageReport) {
+        for (filename, other) in &other.files {
+            let file = self.file(filename);
+            for (&line, &hits) in &other.lines {
+                *file.lines.entry(line).or_default() += hits;
+            }
+            for (name, &(line, calls)) in &other.functions {
+                file.functions.entry(name.clone()).or_insert((line, 0)).1 += calls;
+            }
+            for (&key, branch) in &other.branches {
+                let merged = file.branches.entry(key).or_insert(BranchCoverage {
+                    kind: branch.kind,
+                    arms: [0, 0],
+                });
+                merged.arms[0] += branch.arms[0];
+                merged.arms[1] += branch.arms[1];
+            }
+        }
+    }
+
+    // In the lcov tracefile format, which genhtml and most coverage tools
+    // read.
+    pub fn to_lcov(&self) -> String {
+        let mut lines = Vec::new();
+        for (filename, file) in &self.files {
+            lines.push("TN:".to_string());
+            lines.push(format!("SF:{}", filename));
+            for (name, (line, _)) in &file.functions {
+                lines.push(format!("FN:{},{}", line, name));
+            }
+            for (name, (_, calls)) in &file.functions {
+                lines.push(format!("FNDA:{},{}", calls, name));
+            }
+            lines.push(format!("FNF:{}", file.functions.len()));
+            lines.push(format!("FNH:{}", file.functions_hit()));
+            for (&(line, block), branch) in &file.branches {
+                let reached = branch.arms.iter().any(|&hits| hits > 0);
+                for (arm, hits) in branch.arms.iter().enumerate() {
+                    let taken = if reached { hits.to_string() } else { "-".to_string() };
+                    lines.push(format!("BRDA:{},{},{},{}", line, block, arm, taken));
+                }
+            }
+            lines.push(format!("BRF:{}", file.branches.len() * 2));
+            lines.push(format!("BRH:{}", file.arms_hit()));
+            for (line, hits) in &file.lines {
+                lines.push(format!("DA:{},{}", line, hits));
+            }
+            lines.push(format!("LF:{}", file.lines.len()));
+            lines.push(format!("LH:{}", file.lines_hit()));
+            lines.push("end_of_record".to_string());
+        }
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    // A table of how much of each file ran, and the subroutines which were
+    // never called.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!("{:<20} {:>17} {:>17} {:>17}", "file", "lines", "branches", "subroutines")];
+        let column = |hit: usize, total: usize| format!("{}/{} {}", hit, total, percentage(hit, total));
+        let mut totals = [0; 6];
+        for (filename, file) in &self.files {
+            let counts = [
+                file.lines_hit(),
+                file.lines.len(),
+                file.arms_hit(),
+                file.branches.len() * 2,
+                file.functions_hit(),
+                file.functions.len(),
+            ];
+            lines.push(format!(
+                "{:<20} {:>17} {:>17} {:>17}",
+                filename,
+                column(counts[0], counts[1]),
+                column(counts[2], counts[3]),
+                column(counts[4], counts[5])
+            ));
+            for (total, count) in totals.iter_mut().zip(counts) {
+                *total += count;
+            }
+        }
+        lines.push(format!(
+            "{:<20} {:>17} {:>17} {:>17}",
+            "total",
+            column(totals[0], totals[1]),
+            column(totals[2], totals[3]),
+            column(totals[4], totals[5])
+        ));
+        let never_called: Vec<_> = self
+            .files
+            .values()
+            .flat_map(|file| &file.functions)
+            .filter(|(_, (_, calls))| *calls == 0)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if !never_called.is_empty() {
+            lines.push(format!("never called: {}", never_called.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(line_hits: u64, arms: [u64; 2]) -> CoverageReport {
+        let file = FileCoverage {
+            lines: BTreeMap::from([(3, line_hits), (4, 0)]),
+            functions: BTreeMap::from([("Main.main".to_string(), (2, 1)), ("Main.unused".to_string(), (7, 0))]),
+            branches: BTreeMap::from([((3, 0), BranchCoverage { kind: BranchKind::If, arms })]),
+        };
+        CoverageReport {
+            files: BTreeMap::from([("Main.jack".to_string(), file)]),
+        }
+    }
+
+    #[test]
+    fn test_lcov() {
+        assert_eq!(
+            report(2, [2, 0]).to_lcov(),
+            "\
+TN:
+SF:Main.jack
+FN:2,Main.main
+FN:7,Main.unused
+FNDA:1,Main.main
+FNDA:0,Main.unused
+FNF:2
+FNH:1
+BRDA:3,0,0,2
+BRDA:3,0,1,0
+BRF:2
+BRH:1
+DA:3,2
+DA:4,0
+LF:2
+LH:1
+end_of_record
+"
+        );
+        assert!(report(0, [0, 0]).to_lcov().contains("BRDA:3,0,0,-\nBRDA:3,0,1,-\n"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut merged = report(2, [2, 0]);
+        merged.merge(&report(1, [0, 1]));
+        assert_eq!(merged, {
+            let mut expected = report(3, [2, 1]);
+            expected
+                .files
+                .get_mut("Main.jack")
+                .unwrap()
+                .functions
+                .insert("Main.main".to_string(), (2, 2));
+            expected
+        });
+        assert_eq!(
+            merged.summary(),
+            "\
+file                             lines          branches       subroutines
+Main.jack                    1/2 50.0%        2/2 100.0%         1/2 50.0%
+total                        1/2 50.0%        2/2 100.0%         1/2 50.0%
+never called: Main.unused"
+        );
+    }
+}
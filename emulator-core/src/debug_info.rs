@@ -0,0 +1,771 @@
+// Source level information about a program, read from the debug output of the
+// compiler (`compiler debug-compile`). The compiler's sourcemaps go from
+// machine code to assembly to VM commands to jack nodes to tokens, and this
+// flattens that chain into a lookup from pc to jack source.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    computer::Computer,
+    disassemble::disassemble,
+    memory_layout::{ARG, LCL, RAM_SIZE, SP, THIS},
+    ram_image::RamImage,
+};
+
+// Just the parts of the compiler's debug output which are needed here.
+mod json {
+    use std::{collections::HashMap, ops::Range};
+
+    use serde::Deserialize;
+
+    use crate::ram_image::RamImage;
+
+    #[derive(Deserialize)]
+    pub struct CompilerResult {
+        pub jack_compiler_result: JackCompilerResult,
+        pub vm_compiler_result: VMCompilerResult,
+        pub assembly_result: AssemblyResult,
+    }
+
+    #[derive(Deserialize)]
+    pub struct JackCompilerResult {
+        pub sourcemaps: HashMap<String, JackCompilerSourceMap>,
+        pub tokens: HashMap<String, Vec<Token>>,
+        pub subroutines: HashMap<String, Vec<CompiledSubroutine>>,
+        #[serde(default)]
+        pub class_layouts: HashMap<String, ClassLayout>,
+    }
+
+    #[derive(Deserialize)]
+    pub enum Type {
+        Int,
+        Char,
+        Boolean,
+        ClassName(String),
+    }
+
+    #[derive(Deserialize)]
+    pub struct Variable {
+        pub name: String,
+        pub var_type: Type,
+        pub index: usize,
+    }
+
+    #[derive(Deserialize)]
+    pub struct StaticVariable {
+        pub name: String,
+        pub var_type: Type,
+        pub index: usize,
+        pub address: Option<u16>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ClassLayout {
+        pub name: String,
+        pub fields: Vec<Variable>,
+        pub statics: Vec<StaticVariable>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct JackCompilerSourceMap {
+        pub parser_sourcemap: JackParserSourceMap,
+        pub codegen_sourcemap: JackCodegenSourceMap,
+    }
+
+    #[derive(Deserialize)]
+    pub struct JackParserSourceMap {
+        pub jack_nodes: Vec<NodeInfo>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct NodeInfo {
+        pub token_range: Range<usize>,
+        pub child_node_idxs: Vec<usize>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct JackCodegenSourceMap {
+        pub vm_command_idx_to_jack_node_idx: HashMap<usize, usize>,
+        pub statement_node_idxs: Vec<usize>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Token {
+        pub source: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct CompiledSubroutine {
+        pub name: String,
+        pub commands: Vec<SourcemappedCommand>,
+        pub locals_count: usize,
+        pub arg_count: usize,
+        #[serde(default)]
+        pub arguments: Vec<Variable>,
+        #[serde(default)]
+        pub locals: Vec<Variable>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SourcemappedCommand {
+        pub command: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct VMCompilerResult {
+        pub sourcemap: SourceMap,
+        #[serde(default)]
+        pub ram_image: RamImage,
+        #[serde(default)]
+        pub saved_pointers: HashMap<String, Vec<String>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SourceMap {
+        pub asm_instruction_idx_to_vm_cmd: HashMap<usize, VMCommandIdentifier>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct VMCommandIdentifier {
+        pub filename: String,
+        pub vm_command_idx: usize,
+    }
+
+    #[derive(Deserialize)]
+    pub struct AssemblyResult {
+        pub instructions: Vec<u16>,
+        pub sourcemap: AssemblySourcemap,
+    }
+
+    #[derive(Deserialize)]
+    pub struct AssemblySourcemap {
+        pub machine_code_to_asm: Vec<usize>,
+    }
+}
+
+// Lines and columns both start at 1.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    pub filename: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+// A variable, and where it is in its segment. Types are as written in jack,
+// like `int` or `Array`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub type_name: String,
+    pub index: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticVariable {
+    pub name: String,
+    pub type_name: String,
+    pub index: usize,
+    // None for statics which are never used, so weren't given an address.
+    pub address: Option<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClassLayout {
+    pub name: String,
+    pub filename: String,
+    pub fields: Vec<Variable>,
+    pub statics: Vec<StaticVariable>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Subroutine {
+    pub name: String,
+    pub filename: String,
+    pub locals_count: usize,
+    pub arg_count: usize,
+    pub arguments: Vec<Variable>,
+    pub locals: Vec<Variable>,
+    // The caller's pointers which are saved in its frame, in between the
+    // return address and the locals, like `LCL` or `THIS`.
+    pub saved_pointers: Vec<String>,
+    // None for subroutines which the compiler left out because they're never
+    // called.
+    pub entry_pc: Option<u16>,
+}
+
+// Identifies a statement by the file it's in and its jack node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StatementId {
+    pub file_idx: usize,
+    pub node_idx: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct PcInfo {
+    pub filename: String,
+    pub subroutine_idx: usize,
+    pub vm_command_idx: usize,
+    pub vm_command: String,
+    pub statement: Option<StatementId>,
+    pub location: SourceLocation,
+    // How far above the start of the subroutine's locals SP is before the
+    // instruction at this pc runs, which is how to find the frame from SP.
+    pub stack_offset: i32,
+}
+
+#[derive(Clone, Debug)]
+pub struct DebugInfo {
+    pub rom: Vec<u16>,
+    pub ram_image: RamImage,
+    pub subroutines: Vec<Subroutine>,
+    pcs: Vec<Option<PcInfo>>,
+    filenames: Vec<String>,
+    sources: HashMap<String, String>,
+    statement_locations: HashMap<StatementId, SourceLocation>,
+    // The first pc of each statement. This is where execution stops when
+    // stepping, and where breakpoints go.
+    statement_start_pcs: HashMap<StatementId, u16>,
+    subroutine_idx_by_entry_pc: HashMap<u16, usize>,
+    class_layouts: HashMap<String, ClassLayout>,
+}
+
+impl Subroutine {
+    pub fn class_name(&self) -> &str {
+        self.name.split_once('.').map_or(&self.name, |(class_name, _)| class_name)
+    }
+}
+
+fn type_name(var_type: &json::Type) -> String {
+    match var_type {
+        json::Type::Int => "int".to_string(),
+        json::Type::Char => "char".to_string(),
+        json::Type::Boolean => "boolean".to_string(),
+        json::Type::ClassName(class_name) => class_name.clone(),
+    }
+}
+
+fn variable(variable: &json::Variable) -> Variable {
+    Variable {
+        name: variable.name.clone(),
+        type_name: type_name(&variable.var_type),
+        index: variable.index,
+    }
+}
+
+// Shows a value the way it would be written in jack, going by its type.
+pub fn format_value(type_name: &str, value: u16) -> String {
+    match type_name {
+        "int" => (value as i16).to_string(),
+        "boolean" => match value {
+            0 => "false".to_string(),
+            0xffff => "true".to_string(),
+            _ => format!("{} (not a boolean)", value as i16),
+        },
+        "char" => match char::from_u32(value as u32).filter(|c| c.is_ascii_graphic() || *c == ' ') {
+            Some(c) => format!("{} '{}'", value, c),
+            None => value.to_string(),
+        },
+        _ if value == 0 => "null".to_string(),
+        _ => format!("{} @ {}", type_name, value),
+    }
+}
+
+// How a vm command changes the depth of the stack. Return counts as popping
+// the return value, so that any code after it in the same subroutine carries
+// on from the depth it was at before the return statement.
+fn stack_effect(vm_command: &str) -> i32 {
+    let words: Vec<_> = vm_command.split_whitespace().collect();
+    match words.as_slice() {
+        ["push", ..] => 1,
+        ["pop", ..] | ["if-goto", ..] | ["return"] => -1,
+        ["add" | "sub" | "and" | "or" | "eq" | "gt" | "lt"] => -1,
+        ["function", _, locals_count] => locals_count.parse().unwrap_or_default(),
+        ["call", _, arg_count] => 1 - arg_count.parse::<i32>().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+// Follows the changes that instructions make to SP part way through a vm
+// command. Compiled code only ever changes SP by selecting it with `@SP` and
+// then writing M+1, M-1, or M plus or minus a constant which was just loaded
+// into D.
+#[derive(Default)]
+struct SpTracker {
+    a: Option<i32>,
+    d: Option<i32>,
+    offset: i32,
+}
+
+impl SpTracker {
+    fn update(&mut self, instruction: u16) {
+        let assembly = disassemble(instruction);
+        if let Some(value) = assembly.strip_prefix('@') {
+            self.a = value.parse().ok();
+            return;
+        }
+        let (dest, rest) = assembly.split_once('=').unwrap_or(("", &assembly));
+        let expression = rest.split(';').next().unwrap_or_default();
+        if dest.contains('M') && self.a == Some(SP as i32) {
+            self.offset += match (expression, self.d) {
+                ("M+1", _) => 1,
+                ("M-1", _) => -1,
+                ("D+M", Some(d)) => d,
+                ("M-D", Some(d)) => -d,
+                _ => 0,
+            };
+        }
+        if dest.contains('D') {
+            self.d = if expression == "A" { self.a } else { None };
+        }
+        if dest.contains('A') {
+            self.a = None;
+        }
+    }
+}
+
+fn locations_by_token(tokens: &[json::Token]) -> Vec<SourceLocation> {
+    let (mut line, mut column) = (1, 1);
+    let mut locations = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        locations.push(SourceLocation {
+            filename: String::new(),
+            line,
+            column,
+        });
+        for c in token.source.chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+    }
+    locations
+}
+
+impl DebugInfo {
+    // For machine code which didn't come from the compiler, so there's nothing
+    // known about it beyond the instructions.
+    pub fn without_sourcemap(rom: Vec<u16>, ram_image: RamImage) -> Self {
+        Self {
+            rom,
+            ram_image,
+            subroutines: Vec::new(),
+            pcs: Vec::new(),
+            filenames: Vec::new(),
+            sources: HashMap::new(),
+            statement_locations: HashMap::new(),
+            statement_start_pcs: HashMap::new(),
+            subroutine_idx_by_entry_pc: HashMap::new(),
+            class_layouts: HashMap::new(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let result: json::CompilerResult = serde_json::from_str(json).map_err(|err| format!("invalid debug output: {}", err))?;
+        Ok(Self::from_compiler_result(result))
+    }
+
+    fn from_compiler_result(result: json::CompilerResult) -> Self {
+        let json::JackCompilerResult {
+            sourcemaps,
+            tokens,
+            subroutines: compiled_subroutines,
+            class_layouts: json_class_layouts,
+        } = result.jack_compiler_result;
+
+        let class_layouts = json_class_layouts
+            .iter()
+            .map(|(filename, class_layout)| {
+                let class_layout = ClassLayout {
+                    name: class_layout.name.clone(),
+                    filename: filename.clone(),
+                    fields: class_layout.fields.iter().map(variable).collect(),
+                    statics: class_layout
+                        .statics
+                        .iter()
+                        .map(|static_variable| StaticVariable {
+                            name: static_variable.name.clone(),
+                            type_name: type_name(&static_variable.var_type),
+                            index: static_variable.index,
+                            address: static_variable.address,
+                        })
+                        .collect(),
+                };
+                (class_layout.name.clone(), class_layout)
+            })
+            .collect();
+
+        let mut filenames: Vec<_> = sourcemaps.keys().cloned().collect();
+        filenames.sort();
+        let file_idxs: HashMap<_, _> = filenames.iter().enumerate().map(|(idx, filename)| (filename.clone(), idx)).collect();
+
+        let mut sources = HashMap::new();
+        // For each file, the location of each jack node, and the statement
+        // each jack node is part of.
+        let mut node_locations = Vec::new();
+        let mut node_statements = Vec::new();
+        let mut statement_locations = HashMap::new();
+        for (file_idx, filename) in filenames.iter().enumerate() {
+            let file_tokens = &tokens[filename];
+            sources.insert(filename.clone(), file_tokens.iter().map(|token| token.source.as_str()).collect());
+            let token_locations = locations_by_token(file_tokens);
+            let sourcemap = &sourcemaps[filename];
+            let nodes = &sourcemap.parser_sourcemap.jack_nodes;
+
+            let locations: Vec<_> = nodes
+                .iter()
+                .map(|node| {
+                    // skip leading whitespace and comments
+                    let token_idx = node
+                        .token_range
+                        .clone()
+                        .find(|&idx| {
+                            let source = file_tokens[idx].source.trim();
+                            !source.is_empty() && !source.starts_with("//") && !source.starts_with("/*")
+                        })
+                        .unwrap_or(node.token_range.start);
+                    SourceLocation {
+                        filename: filename.clone(),
+                        ..token_locations[token_idx.min(token_locations.len() - 1)].clone()
+                    }
+                })
+                .collect();
+
+            let mut parents = HashMap::new();
+            for (node_idx, node) in nodes.iter().enumerate() {
+                for &child_idx in &node.child_node_idxs {
+                    parents.insert(child_idx, node_idx);
+                }
+            }
+            let statement_idxs: HashSet<_> = sourcemap.codegen_sourcemap.statement_node_idxs.iter().copied().collect();
+            let statements: Vec<_> = (0..nodes.len())
+                .map(|node_idx| {
+                    let mut current = Some(node_idx);
+                    while let Some(idx) = current {
+                        if statement_idxs.contains(&idx) {
+                            return Some(StatementId { file_idx, node_idx: idx });
+                        }
+                        current = parents.get(&idx).copied();
+                    }
+                    None
+                })
+                .collect();
+
+            for &node_idx in &statement_idxs {
+                statement_locations.insert(StatementId { file_idx, node_idx }, locations[node_idx].clone());
+            }
+            node_locations.push(locations);
+            node_statements.push(statements);
+        }
+
+        // Subroutines, and which subroutine each vm command is in.
+        let mut subroutines = Vec::new();
+        let mut command_subroutines: HashMap<(usize, usize), usize> = HashMap::new();
+        // How far above the start of the locals SP is at the start of each
+        // vm command.
+        let mut command_stack_offsets: HashMap<(usize, usize), i32> = HashMap::new();
+        for (file_idx, filename) in filenames.iter().enumerate() {
+            let mut vm_command_idx = 0;
+            for subroutine in compiled_subroutines.get(filename).into_iter().flatten() {
+                let mut stack_offset = 0;
+                for command in &subroutine.commands {
+                    command_subroutines.insert((file_idx, vm_command_idx), subroutines.len());
+                    command_stack_offsets.insert((file_idx, vm_command_idx), stack_offset);
+                    stack_offset += stack_effect(&command.command);
+                    vm_command_idx += 1;
+                }
+                subroutines.push(Subroutine {
+                    name: subroutine.name.clone(),
+                    filename: filename.clone(),
+                    locals_count: subroutine.locals_count,
+                    arg_count: subroutine.arg_count,
+                    arguments: subroutine.arguments.iter().map(variable).collect(),
+                    locals: subroutine.locals.iter().map(variable).collect(),
+                    saved_pointers: result
+                        .vm_compiler_result
+                        .saved_pointers
+                        .get(&subroutine.name)
+                        .cloned()
+                        .unwrap_or_default(),
+                    entry_pc: None,
+                });
+            }
+        }
+
+        let vm_commands: HashMap<(usize, usize), &str> = filenames
+            .iter()
+            .enumerate()
+            .flat_map(|(file_idx, filename)| {
+                compiled_subroutines
+                    .get(filename)
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|subroutine| subroutine.commands.iter())
+                    .enumerate()
+                    .map(move |(vm_command_idx, command)| ((file_idx, vm_command_idx), command.command.as_str()))
+            })
+            .collect();
+
+        let asm_to_vm = &result.vm_compiler_result.sourcemap.asm_instruction_idx_to_vm_cmd;
+        let mut pcs = Vec::new();
+        let mut statement_start_pcs = HashMap::new();
+        let mut subroutine_idx_by_entry_pc = HashMap::new();
+        let mut sp_tracker = SpTracker::default();
+        let mut prev_key = None;
+        for (pc, asm_idx) in result.assembly_result.sourcemap.machine_code_to_asm.iter().enumerate() {
+            let pc_info = asm_to_vm.get(asm_idx).map(|vm_command| {
+                let file_idx = file_idxs[&vm_command.filename];
+                let key = (file_idx, vm_command.vm_command_idx);
+                if prev_key != Some(key) {
+                    sp_tracker = SpTracker::default();
+                    prev_key = Some(key);
+                }
+                let stack_offset = command_stack_offsets[&key] + sp_tracker.offset;
+                sp_tracker.update(result.assembly_result.instructions[pc]);
+                let node_idx = sourcemaps[&vm_command.filename].codegen_sourcemap.vm_command_idx_to_jack_node_idx[&vm_command.vm_command_idx];
+                PcInfo {
+                    filename: vm_command.filename.clone(),
+                    subroutine_idx: command_subroutines[&key],
+                    vm_command_idx: vm_command.vm_command_idx,
+                    vm_command: vm_commands[&key].to_string(),
+                    statement: node_statements[file_idx][node_idx],
+                    location: node_locations[file_idx][node_idx].clone(),
+                    stack_offset,
+                }
+            });
+            if pc_info.is_none() {
+                prev_key = None;
+            }
+            if let Some(pc_info) = &pc_info {
+                // Code is laid out in the same order as the vm commands, so
+                // the first pc seen for each subroutine or statement is where
+                // it starts.
+                let subroutine = &mut subroutines[pc_info.subroutine_idx];
+                if subroutine.entry_pc.is_none() {
+                    subroutine.entry_pc = Some(pc as u16);
+                    subroutine_idx_by_entry_pc.insert(pc as u16, pc_info.subroutine_idx);
+                }
+                if let Some(statement) = pc_info.statement {
+                    statement_start_pcs.entry(statement).or_insert(pc as u16);
+                }
+            }
+            pcs.push(pc_info);
+        }
+
+        DebugInfo {
+            rom: result.assembly_result.instructions,
+            ram_image: result.vm_compiler_result.ram_image,
+            subroutines,
+            pcs,
+            filenames,
+            sources,
+            statement_locations,
+            statement_start_pcs,
+            subroutine_idx_by_entry_pc,
+            class_layouts,
+        }
+    }
+
+    // None for pcs which aren't part of any subroutine, like the boot code.
+    pub fn pc_info(&self, pc: u16) -> Option<&PcInfo> {
+        self.pcs.get(pc as usize).and_then(Option::as_ref)
+    }
+
+    pub fn subroutine_at(&self, pc: u16) -> Option<&Subroutine> {
+        self.pc_info(pc).map(|pc_info| &self.subroutines[pc_info.subroutine_idx])
+    }
+
+    pub fn subroutine_by_name(&self, name: &str) -> Option<&Subroutine> {
+        self.subroutines.iter().find(|subroutine| subroutine.name == name)
+    }
+
+    pub fn subroutine_entered_at(&self, pc: u16) -> Option<usize> {
+        self.subroutine_idx_by_entry_pc.get(&pc).copied()
+    }
+
+    // Where the statement that the pc is part of starts, falling back to the
+    // jack node the pc was generated from.
+    pub fn location(&self, pc: u16) -> Option<&SourceLocation> {
+        let pc_info = self.pc_info(pc)?;
+        Some(
+            pc_info
+                .statement
+                .and_then(|statement| self.statement_locations.get(&statement))
+                .unwrap_or(&pc_info.location),
+        )
+    }
+
+    pub fn is_statement_start(&self, pc: u16) -> bool {
+        self.pc_info(pc)
+            .and_then(|pc_info| pc_info.statement)
+            .is_some_and(|statement| self.statement_start_pcs[&statement] == pc)
+    }
+
+    // The first pc of every statement which made it into the ROM, and where
+    // the statement is.
+    pub fn statements(&self) -> impl Iterator<Item = (u16, &SourceLocation)> + '_ {
+        self.statement_start_pcs
+            .iter()
+            .map(|(statement, &pc)| (pc, &self.statement_locations[statement]))
+    }
+
+    // The pcs of the statements which start on a line.
+    pub fn line_pcs(&self, filename: &str, line: usize) -> Vec<u16> {
+        let mut pcs: Vec<_> = self
+            .statement_start_pcs
+            .iter()
+            .filter(|(statement, _)| {
+                let location = &self.statement_locations[statement];
+                location.filename == filename && location.line == line
+            })
+            .map(|(_, &pc)| pc)
+            .collect();
+        pcs.sort();
+        pcs
+    }
+
+    pub fn class_layout(&self, class_name: &str) -> Option<&ClassLayout> {
+        self.class_layouts.get(class_name)
+    }
+
+    // Looks up a variable by name as the subroutine of a frame sees it, which
+    // is its locals, then its arguments, then `this` and its fields, then the
+    // statics of its class. Gives the variable's type and value. Fields are
+    // found through THIS, so are only right for the innermost frame.
+    pub fn variable(&self, frame: &Frame, ram: &[u16], name: &str) -> Option<(String, u16)> {
+        let subroutine = &self.subroutines[frame.subroutine_idx];
+        let class_layout = self.class_layout(subroutine.class_name());
+        let find = |variables: &[Variable], base: u16| {
+            let var = variables.iter().find(|var| var.name == name)?;
+            Some((var.type_name.clone(), ram[(base as usize + var.index) % RAM_SIZE]))
+        };
+        let statics = class_layout.into_iter().flat_map(|class_layout| &class_layout.statics);
+        find(&subroutine.locals, frame.lcl)
+            .or_else(|| find(&subroutine.arguments, frame.arg))
+            .or_else(|| (name == "this").then(|| (subroutine.class_name().to_string(), ram[THIS])))
+            .or_else(|| find(&class_layout?.fields, ram[THIS]))
+            .or_else(|| {
+                statics
+                    .filter(|var| var.name == name)
+                    .find_map(|var| Some((var.type_name.clone(), ram[var.address? as usize])))
+            })
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    // The jack source of a file, pieced back together from its tokens.
+    pub fn source(&self, filename: &str) -> Option<&str> {
+        self.sources.get(filename).map(String::as_str)
+    }
+
+    pub fn computer(&self) -> Computer {
+        let mut rom = [0; 32768];
+        rom[..self.rom.len()].copy_from_slice(&self.rom);
+        Computer::with_ram_image(rom, &self.ram_image)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub subroutine_idx: usize,
+    // The pc of the instruction that jumped into the subroutine. None for the
+    // outermost frame, which is called by the boot code.
+    pub call_pc: Option<u16>,
+    // The subroutine's LCL and ARG pointers. Subroutines never change their
+    // own pointers, so these are the values they had on entry. They only
+    // mean something for subroutines which have locals and arguments.
+    pub lcl: u16,
+    pub arg: u16,
+}
+
+// Keeps track of the call stack as a program runs, by watching for jumps to
+// the start of subroutines from call commands, and jumps out of return
+// commands. It has to see every instruction from when the computer is
+// switched on to be accurate.
+#[derive(Clone, Debug, Default)]
+pub struct CallTracker {
+    frames: Vec<Frame>,
+}
+
+pub enum CallEvent {
+    Call,
+    Return,
+}
+
+impl CallTracker {
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Call after every tick with the pc from before the tick.
+    pub fn update(&mut self, debug_info: &DebugInfo, computer: &Computer, prev_pc: u16) -> Option<CallEvent> {
+        let pc = computer.cpu.pc;
+        let prev = debug_info.pc_info(prev_pc);
+        // Calls end with a jump, which can be to the very next pc when the
+        // callee happens to be laid out straight after the call.
+        let is_call = prev.map_or(true, |prev| prev.vm_command.starts_with("call "));
+        if is_call {
+            if let Some(subroutine_idx) = debug_info.subroutine_entered_at(pc) {
+                let ram = computer.ram.lock();
+                self.frames.push(Frame {
+                    subroutine_idx,
+                    call_pc: prev.map(|_| prev_pc),
+                    lcl: ram[LCL],
+                    arg: ram[ARG],
+                });
+                return Some(CallEvent::Call);
+            }
+        }
+        if let Some(prev) = prev {
+            if prev.vm_command == "return" && pc != prev_pc + 1 {
+                self.frames.pop();
+                return Some(CallEvent::Return);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locations_by_token() {
+        let tokens: Vec<_> = ["class", " ", "Main", "\n  ", "{"]
+            .iter()
+            .map(|source| json::Token { source: source.to_string() })
+            .collect();
+        let locations = locations_by_token(&tokens);
+        assert_eq!((locations[2].line, locations[2].column), (1, 7));
+        assert_eq!((locations[4].line, locations[4].column), (2, 3));
+    }
+
+    #[test]
+    fn test_stack_effect() {
+        assert_eq!(stack_effect("function Main.main 3"), 3);
+        assert_eq!(stack_effect("call Math.multiply 2"), -1);
+        assert_eq!(stack_effect("if-goto end_if_0"), -1);
+        assert_eq!(stack_effect("push local 0"), 1);
+        assert_eq!(stack_effect("not"), 0);
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value("int", -3_i16 as u16), "-3");
+        assert_eq!(format_value("boolean", 0xffff), "true");
+        assert_eq!(format_value("boolean", 2), "2 (not a boolean)");
+        assert_eq!(format_value("char", 65), "65 'A'");
+        assert_eq!(format_value("char", 128), "128");
+        assert_eq!(format_value("Array", 0), "null");
+        assert_eq!(format_value("Array", 2050), "Array @ 2050");
+    }
+}
@@ -0,0 +1,557 @@
+// Runs a program a jack statement at a time, stopping at breakpoints on jack
+// lines. This is the part of source level debugging which doesn't depend on
+// how the debugger is presented.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    allocations::AllocationTracker,
+    arithmetic_trap::arithmetic_error,
+    backtrace::{unwind, Backtrace},
+    breakpoints::{Breakpoint, Hit, Scope},
+    computer::{tick, Computer},
+    coverage::Coverage,
+    debug_info::{CallEvent, CallTracker, DebugInfo, Frame},
+    input_script::{InputPlayer, InputScript},
+    pointer_check::PointerChecker,
+    profiler::Profiler,
+    sanitizer::{memory_access, Sanitizer},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepKind {
+    In,
+    Over,
+    Out,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunMode {
+    Continue,
+    // The call depth when the step started is kept to know when to stop.
+    Step(StepKind, usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint,
+    Watchpoint { address: usize, old: u16, new: u16 },
+    Step,
+    Fault(String),
+    // A breakpoint's condition, or the condition of an event in the input
+    // script, couldn't be worked out.
+    ConditionError(String),
+    // Only when checking pointers. See `PointerChecker`.
+    PointerNotRestored(String),
+    // Only when sanitizing memory. See `Sanitizer`.
+    MemoryError(String),
+    // Checked arithmetic has overflowed or divided by zero, and called
+    // Sys.arithmeticError.
+    ArithmeticError(String),
+    // Sys.init has returned.
+    Exited,
+}
+
+pub struct Debugger {
+    pub debug_info: DebugInfo,
+    pub computer: Computer,
+    pub call_tracker: CallTracker,
+    line_breakpoints: HashMap<String, HashMap<u16, Breakpoint>>,
+    pc_breakpoints: HashMap<u16, Breakpoint>,
+    breakpoints: HashSet<u16>,
+    // Messages from log points which haven't been taken yet.
+    logs: Vec<String>,
+    // The value each watched address had when it was last checked.
+    watchpoints: BTreeMap<usize, u16>,
+    pointer_checker: Option<PointerChecker>,
+    allocation_tracker: Option<AllocationTracker>,
+    sanitizer: Option<Sanitizer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    input_player: Option<InputPlayer>,
+}
+
+impl Debugger {
+    pub fn new(debug_info: DebugInfo) -> Self {
+        let computer = debug_info.computer();
+        Self::with_computer(debug_info, computer)
+    }
+
+    // The computer should be freshly switched on, or the call tracker won't
+    // know what's going on.
+    pub fn with_computer(debug_info: DebugInfo, computer: Computer) -> Self {
+        Self {
+            debug_info,
+            computer,
+            call_tracker: CallTracker::default(),
+            line_breakpoints: HashMap::new(),
+            pc_breakpoints: HashMap::new(),
+            breakpoints: HashSet::new(),
+            logs: Vec::new(),
+            watchpoints: BTreeMap::new(),
+            pointer_checker: None,
+            allocation_tracker: None,
+            sanitizer: None,
+            profiler: None,
+            coverage: None,
+            input_player: None,
+        }
+    }
+
+    // Stops whenever a subroutine relies on a pointer which a call didn't
+    // restore. Like the call tracker, this has to see every instruction from
+    // when the computer is switched on.
+    pub fn check_pointers(&mut self) {
+        self.pointer_checker = Some(PointerChecker::new());
+    }
+
+    // Records what the program allocates and frees, to report leaks. This has
+    // to see every instruction from when the computer is switched on too.
+    pub fn track_allocations(&mut self) {
+        self.allocation_tracker = Some(AllocationTracker::new());
+    }
+
+    pub fn allocations(&self) -> Option<&AllocationTracker> {
+        self.allocation_tracker.as_ref()
+    }
+
+    // Stops at reads of memory which hasn't been set, accesses through null
+    // pointers and accesses to the heap outside of allocated blocks. This has
+    // to see every instruction from when the computer is switched on too.
+    pub fn sanitize_memory(&mut self) {
+        self.sanitizer = Some(Sanitizer::new());
+    }
+
+    // Counts the instructions run in each subroutine and on each line. This
+    // has to see every instruction from when the computer is switched on too.
+    pub fn profile(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    // Records which statements, branches and subroutines run. This has to
+    // see every instruction from when the computer is switched on too.
+    pub fn record_coverage(&mut self) {
+        self.coverage = Some(Coverage::new(&self.debug_info));
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    // Plays a script of key presses into the keyboard register. This has to
+    // see every instruction from when the computer is switched on too, as
+    // events happen at numbers of instructions.
+    pub fn play_input(&mut self, script: InputScript) {
+        self.input_player = Some(InputPlayer::new(script));
+    }
+
+    pub fn input_player(&self) -> Option<&InputPlayer> {
+        self.input_player.as_ref()
+    }
+
+    // Replaces the breakpoints in a file. Returns the pc of each breakpoint,
+    // or None if there's no statement on that line.
+    pub fn set_line_breakpoints(&mut self, filename: &str, lines: &[usize]) -> Vec<Option<u16>> {
+        let breakpoints: Vec<_> = lines.iter().map(|&line| (line, Breakpoint::default())).collect();
+        self.set_conditional_line_breakpoints(filename, breakpoints)
+    }
+
+    // Like `set_line_breakpoints`, with a condition, hit condition or log
+    // message for each line. A line with more than one statement gets a copy
+    // of its breakpoint on each, and they count their hits separately.
+    pub fn set_conditional_line_breakpoints(&mut self, filename: &str, breakpoints: Vec<(usize, Breakpoint)>) -> Vec<Option<u16>> {
+        let mut pcs = HashMap::new();
+        let result = breakpoints
+            .into_iter()
+            .map(|(line, breakpoint)| {
+                let line_pcs = self.debug_info.line_pcs(filename, line);
+                pcs.extend(line_pcs.iter().map(|&pc| (pc, breakpoint.clone())));
+                line_pcs.first().copied()
+            })
+            .collect();
+        self.line_breakpoints.insert(filename.to_string(), pcs);
+        self.update_breakpoints();
+        result
+    }
+
+    // Breakpoints on individual instructions, which are kept separately from
+    // the ones on lines.
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.add_conditional_breakpoint(pc, Breakpoint::default());
+    }
+
+    // Replaces any breakpoint already at the pc.
+    pub fn add_conditional_breakpoint(&mut self, pc: u16, breakpoint: Breakpoint) {
+        self.pc_breakpoints.insert(pc, breakpoint);
+        self.update_breakpoints();
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) -> bool {
+        let removed = self.pc_breakpoints.remove(&pc).is_some();
+        self.update_breakpoints();
+        removed
+    }
+
+    fn update_breakpoints(&mut self) {
+        self.breakpoints = self
+            .line_breakpoints
+            .values()
+            .flat_map(HashMap::keys)
+            .chain(self.pc_breakpoints.keys())
+            .copied()
+            .collect();
+    }
+
+    pub fn breakpoints(&self) -> &HashSet<u16> {
+        &self.breakpoints
+    }
+
+    // The breakpoint added at a pc, rather than one on a line.
+    pub fn breakpoint(&self, pc: u16) -> Option<&Breakpoint> {
+        self.pc_breakpoints.get(&pc)
+    }
+
+    // The messages from log points since this was last called.
+    pub fn take_logs(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.logs)
+    }
+
+    // Whether the breakpoints at the pc, if there are any, say to stop.
+    fn check_breakpoints(&mut self) -> Result<bool, String> {
+        let pc = self.computer.cpu.pc;
+        if !self.breakpoints.contains(&pc) {
+            return Ok(false);
+        }
+        let scope = Scope {
+            computer: &self.computer,
+            debug_info: Some(&self.debug_info),
+            frames: self.call_tracker.frames(),
+        };
+        let line_breakpoints = self.line_breakpoints.values_mut().filter_map(|breakpoints| breakpoints.get_mut(&pc));
+        let mut stop = false;
+        for breakpoint in self.pc_breakpoints.get_mut(&pc).into_iter().chain(line_breakpoints) {
+            match breakpoint.hit(&scope)? {
+                Hit::Stop => stop = true,
+                Hit::Log(message) => self.logs.push(message),
+                Hit::Continue => {}
+            }
+        }
+        Ok(stop)
+    }
+
+    // Stops execution whenever the value at an address changes.
+    pub fn add_watchpoint(&mut self, address: usize) {
+        let value = self.computer.ram.lock()[address];
+        self.watchpoints.insert(address, value);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.watchpoints.keys().copied()
+    }
+
+    fn check_watchpoints(&mut self) -> Option<StopReason> {
+        let ram = self.computer.ram.lock();
+        for (&address, old) in self.watchpoints.iter_mut() {
+            let new = ram[address];
+            if new != *old {
+                let reason = StopReason::Watchpoint { address, old: *old, new };
+                *old = new;
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    pub fn start_step(&self, step_kind: StepKind) -> RunMode {
+        RunMode::Step(step_kind, self.call_tracker.depth())
+    }
+
+    // The call stack, innermost frame first, along with the pc that each frame
+    // is currently at.
+    pub fn stack(&self) -> Vec<(&Frame, u16)> {
+        let frames = self.call_tracker.frames();
+        frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(idx, frame)| {
+                let pc = match frames.get(idx + 1) {
+                    Some(callee) => callee.call_pc.unwrap_or(self.computer.cpu.pc),
+                    None => self.computer.cpu.pc,
+                };
+                (frame, pc)
+            })
+            .collect()
+    }
+
+    // The call stack as worked out from RAM, which doesn't rely on having
+    // watched the program since it started.
+    pub fn backtrace(&self) -> Backtrace {
+        unwind(&self.debug_info, &self.computer)
+    }
+
+    // Executes a single instruction, keeping track of calls and returns.
+    pub fn tick(&mut self) -> Option<CallEvent> {
+        if let Some(input_player) = &mut self.input_player {
+            input_player.update(&Scope {
+                computer: &self.computer,
+                debug_info: Some(&self.debug_info),
+                frames: self.call_tracker.frames(),
+            });
+        }
+        let prev_pc = self.computer.cpu.pc;
+        let access = match self.sanitizer {
+            Some(_) => memory_access(self.computer.rom()[prev_pc as usize], self.computer.cpu.reg_a),
+            None => None,
+        };
+        tick(&mut self.computer);
+        let event = self.call_tracker.update(&self.debug_info, &self.computer, prev_pc);
+        if let Some(pointer_checker) = &mut self.pointer_checker {
+            pointer_checker.update(&self.debug_info, &self.computer, prev_pc, event.as_ref());
+        }
+        if let Some(allocation_tracker) = &mut self.allocation_tracker {
+            allocation_tracker.update(&self.debug_info, &self.call_tracker, &self.computer, event.as_ref());
+        }
+        if let Some(sanitizer) = &mut self.sanitizer {
+            sanitizer.update(&self.debug_info, &self.call_tracker, &self.computer, prev_pc, access, event.as_ref());
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.update(&self.call_tracker, prev_pc, event.as_ref());
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.update(&self.call_tracker, prev_pc, self.computer.cpu.pc, event.as_ref());
+        }
+        event
+    }
+
+    fn step_done(&self, mode: RunMode) -> bool {
+        let pc = self.computer.cpu.pc;
+        let depth = self.call_tracker.depth();
+        match mode {
+            RunMode::Continue => false,
+            RunMode::Step(step_kind, start_depth) => {
+                let depth_ok = match step_kind {
+                    StepKind::In => true,
+                    StepKind::Over => depth <= start_depth,
+                    StepKind::Out => depth < start_depth,
+                };
+                depth_ok && self.debug_info.is_statement_start(pc)
+            }
+        }
+    }
+
+    // Runs for up to `max_ticks` instructions, returning why it stopped, or
+    // None if it's still going. Always executes at least one instruction, so
+    // that it's possible to carry on from a breakpoint.
+    pub fn run(&mut self, mode: RunMode, max_ticks: usize) -> Option<StopReason> {
+        for _ in 0..max_ticks {
+            let event = self.tick();
+            if let Some(fault) = self.computer.fault() {
+                return Some(StopReason::Fault(fault.to_string()));
+            }
+            if let Some(violation) = self.pointer_checker.as_mut().and_then(PointerChecker::take_violation) {
+                return Some(StopReason::PointerNotRestored(violation));
+            }
+            if let Some(error) = self.sanitizer.as_mut().and_then(Sanitizer::take_error) {
+                return Some(StopReason::MemoryError(error));
+            }
+            if let Some(error) = self.input_player.as_mut().and_then(InputPlayer::take_error) {
+                return Some(StopReason::ConditionError(error));
+            }
+            if matches!(event, Some(CallEvent::Call)) {
+                if let Some(error) = arithmetic_error(&self.debug_info, &self.call_tracker, &self.computer) {
+                    return Some(StopReason::ArithmeticError(error));
+                }
+            }
+            if matches!(event, Some(CallEvent::Return)) && self.call_tracker.depth() == 0 {
+                return Some(StopReason::Exited);
+            }
+            if !self.watchpoints.is_empty() {
+                if let Some(reason) = self.check_watchpoints() {
+                    return Some(reason);
+                }
+            }
+            match self.check_breakpoints() {
+                Ok(true) => return Some(StopReason::Breakpoint),
+                Ok(false) => {}
+                Err(error) => return Some(StopReason::ConditionError(error)),
+            }
+            if self.step_done(mode) {
+                return Some(StopReason::Step);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestProgram;
+
+    const JUMP: u16 = 0b1110101010000111; // 0;JMP
+    const SET_M: u16 = 0b1110111010001000; // M=-1
+
+    // Sys.init is the boot code, which falls through into Main.main, which
+    // calls Main.helper and sets the word at 1000 before returning. The A
+    // instructions put where each jump goes in A.
+    fn debugger() -> Debugger {
+        let mut debug_info = TestProgram::new()
+            .file("Sys.jack", "class Sys {\n  function void init() {\n    do Main.main();\n  }\n}\n")
+            .subroutine("Sys.init", 0, &[], &[(2, "function Sys.init 0"), (3, "call Main.main 0")])
+            .file(
+                "Main.jack",
+                "class Main {
+  function void main() {
+    var int x;
+    let x = Main.helper();
+    let x = x + 1;
+    return;
+  }
+
+  function int helper() {
+    return 1;
+  }
+}
+",
+            )
+            .subroutine(
+                "Main.main",
+                0,
+                &["x"],
+                &[
+                    (2, "function Main.main 1"),
+                    (4, "call Main.helper 0"),
+                    (4, "pop local 0"),
+                    (5, "push local 0"),
+                    (5, "push constant 1"),
+                    (5, "add"),
+                    (5, "pop local 0"),
+                    (6, "push constant 0"),
+                    (6, "return"),
+                ],
+            )
+            .subroutine(
+                "Main.helper",
+                0,
+                &[],
+                &[(9, "function Main.helper 0"), (10, "push constant 1"), (10, "return")],
+            )
+            .build();
+        let rom = &mut debug_info.rom;
+        // Main.main calls Main.helper.
+        rom[2] = 11;
+        rom[3] = JUMP;
+        // Main.helper returns to Main.main.
+        rom[12] = 4;
+        rom[13] = JUMP;
+        // Main.main sets the word at 1000 and returns to the boot code.
+        rom[7] = 1000;
+        rom[8] = SET_M;
+        rom[9] = 0;
+        rom[10] = JUMP;
+        Debugger::new(debug_info)
+    }
+
+    fn stack(debugger: &Debugger) -> Vec<(&str, u16)> {
+        debugger
+            .stack()
+            .into_iter()
+            .map(|(frame, pc)| (debugger.debug_info.subroutines[frame.subroutine_idx].name.as_str(), pc))
+            .collect()
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.set_line_breakpoints("Main.jack", &[5, 8]), [Some(5), None]);
+        debugger.add_breakpoint(12);
+        assert_eq!(debugger.breakpoints(), &HashSet::from([5, 12]));
+
+        assert_eq!(debugger.run(RunMode::Continue, 100), Some(StopReason::Breakpoint));
+        assert_eq!(stack(&debugger), [("Main.helper", 12), ("Main.main", 3)]);
+        assert_eq!(debugger.run(RunMode::Continue, 100), Some(StopReason::Breakpoint));
+        assert_eq!(stack(&debugger), [("Main.main", 5)]);
+
+        assert!(debugger.remove_breakpoint(12));
+        assert!(!debugger.remove_breakpoint(12));
+        debugger.set_line_breakpoints("Main.jack", &[]);
+        assert!(debugger.breakpoints().is_empty());
+        assert_eq!(debugger.run(RunMode::Continue, 100), Some(StopReason::Exited));
+    }
+
+    #[test]
+    fn test_conditional_breakpoints() {
+        let mut debugger = debugger();
+        let breakpoint = |condition, hit_condition, log_message| Breakpoint::parse(condition, hit_condition, log_message).unwrap();
+        // Only logs, as Main.helper runs before the word at 1000 is set.
+        debugger.add_conditional_breakpoint(12, breakpoint(None, None, Some("RAM[1000] is {RAM[1000]}")));
+        debugger.add_conditional_breakpoint(7, breakpoint(Some("RAM[1000] != 0"), None, None));
+        debugger.add_conditional_breakpoint(9, breakpoint(Some("RAM[1000] = -1"), Some("1"), None));
+        assert_eq!(debugger.run(RunMode::Continue, 100), Some(StopReason::Breakpoint));
+        assert_eq!(debugger.computer.cpu.pc, 9);
+        assert_eq!(debugger.take_logs(), ["RAM[1000] is 0"]);
+        assert!(debugger.take_logs().is_empty());
+        assert_eq!(debugger.breakpoint(7).unwrap().hits(), 0);
+        assert_eq!(debugger.breakpoint(9).unwrap().hits(), 1);
+
+        let mut debugger = debugger_with_line_condition("x == 1");
+        assert_eq!(
+            debugger.run(RunMode::Continue, 100),
+            Some(StopReason::ConditionError(
+                "can't check the condition x == 1: there's no variable called x in Main.helper".to_string()
+            ))
+        );
+    }
+
+    fn debugger_with_line_condition(condition: &str) -> Debugger {
+        let mut debugger = debugger();
+        let breakpoint = Breakpoint::parse(Some(condition), None, None).unwrap();
+        debugger.set_conditional_line_breakpoints("Main.jack", vec![(10, breakpoint)]);
+        debugger
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut debugger = debugger();
+        let mut step = |step_kind| {
+            let mode = debugger.start_step(step_kind);
+            assert_eq!(debugger.run(mode, 100), Some(StopReason::Step));
+            debugger.computer.cpu.pc
+        };
+        assert_eq!(step(StepKind::In), 1);
+        assert_eq!(step(StepKind::In), 2);
+        assert_eq!(step(StepKind::Over), 3);
+        // Into Main.helper, and back out to the next statement in Main.main.
+        assert_eq!(step(StepKind::In), 11);
+        assert_eq!(step(StepKind::Out), 5);
+        assert_eq!(step(StepKind::Over), 9);
+        assert_eq!(debugger.run(debugger.start_step(StepKind::Over), 100), Some(StopReason::Exited));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = debugger();
+        debugger.add_watchpoint(1000);
+        assert_eq!(
+            debugger.run(RunMode::Continue, 100),
+            Some(StopReason::Watchpoint {
+                address: 1000,
+                old: 0,
+                new: 0xffff
+            })
+        );
+        assert_eq!(debugger.computer.cpu.pc, 9);
+        assert!(debugger.remove_watchpoint(1000));
+        assert_eq!(debugger.watchpoints().count(), 0);
+        assert_eq!(debugger.run(RunMode::Continue, 100), Some(StopReason::Exited));
+    }
+}
@@ -0,0 +1,82 @@
+// Turns machine code back into assembly, for showing what the computer is
+// doing when there's no sourcemap to say.
+
+use crate::computer::bit;
+
+fn expression(comp_bits: u16) -> Option<&'static str> {
+    Some(match comp_bits {
+        0b0101010 => "0",
+        0b0111111 => "1",
+        0b0111010 => "-1",
+        0b0001100 => "D",
+        0b0110000 => "A",
+        0b0001101 => "!D",
+        0b0110001 => "!A",
+        0b0001111 => "-D",
+        0b0110011 => "-A",
+        0b0011111 => "D+1",
+        0b0110111 => "A+1",
+        0b0001110 => "D-1",
+        0b0110010 => "A-1",
+        0b0000010 => "D+A",
+        0b0010011 => "D-A",
+        0b0000111 => "A-D",
+        0b0000000 => "D&A",
+        0b0010101 => "D|A",
+        0b1110000 => "M",
+        0b1110001 => "!M",
+        0b1110011 => "-M",
+        0b1110111 => "M+1",
+        0b1110010 => "M-1",
+        0b1000010 => "D+M",
+        0b1010011 => "D-M",
+        0b1000111 => "M-D",
+        0b1000000 => "D&M",
+        0b1010101 => "D|M",
+        _ => return None,
+    })
+}
+
+const JUMPS: [&str; 8] = ["", "JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"];
+
+pub fn disassemble(instruction: u16) -> String {
+    if bit(instruction, 15) == 0 {
+        return format!("@{}", instruction);
+    }
+    let Some(expression) = expression((instruction >> 6) & 0b1111111) else {
+        return format!("??? {:016b}", instruction);
+    };
+    let dest: String = [(5, 'A'), (3, 'M'), (4, 'D')]
+        .iter()
+        .filter(|(idx, _)| bit(instruction, *idx) == 1)
+        .map(|(_, register)| register)
+        .collect();
+    let jump = JUMPS[(instruction & 0b111) as usize];
+    let mut assembly = String::new();
+    if !dest.is_empty() {
+        assembly.push_str(&dest);
+        assembly.push('=');
+    }
+    assembly.push_str(expression);
+    if !jump.is_empty() {
+        assembly.push(';');
+        assembly.push_str(jump);
+    }
+    assembly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(disassemble(26624), "@26624");
+        assert_eq!(disassemble(0xefc8), "M=1");
+        assert_eq!(disassemble(0xeea0), "A=-1");
+        assert_eq!(disassemble(0b1110101010000111), "0;JMP");
+        assert_eq!(disassemble(0b1111110111111000), "AMD=M+1");
+        assert_eq!(disassemble(0b1110001100010011), "D=D;JGE");
+        assert_eq!(disassemble(0b1111111111000000), "??? 1111111111000000");
+    }
+}
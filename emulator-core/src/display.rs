@@ -0,0 +1,159 @@
+use crate::memory_layout::{DISPLAY_MODE, PALETTE, PALETTE_SIZE, SCREEN, SCREEN_SIZE};
+
+pub const SCREEN_WIDTH: usize = 512;
+pub const SCREEN_HEIGHT: usize = 256;
+
+// The default colors loaded into the palette registers when the computer is
+// switched on, in RGB565. These are the 16 CGA colors.
+pub const DEFAULT_PALETTE: [u16; PALETTE_SIZE] = [
+    0x0000, 0x0015, 0x0540, 0x0555, 0xa800, 0xa815, 0xaaa0, 0xad55, 0x52aa, 0x52bf, 0x57ea, 0x57ff, 0xfaaa, 0xfabf, 0xffea, 0xffff,
+];
+
+// Every mode uses the same 8192 words of screen memory, so the resolution
+// drops as the number of bits per pixel goes up. Pixels are packed into words
+// starting from the most significant bit, just like in monochrome mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayMode {
+    // 512x256, 1 bit per pixel.
+    Monochrome,
+    // 256x256, 2 bits per pixel, using the first 4 palette entries.
+    Color2bpp,
+    // 256x128, 4 bits per pixel, using all 16 palette entries.
+    Color4bpp,
+}
+
+impl DisplayMode {
+    pub fn from_register(value: u16) -> Self {
+        match value {
+            1 => DisplayMode::Color2bpp,
+            2 => DisplayMode::Color4bpp,
+            _ => DisplayMode::Monochrome,
+        }
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        match self {
+            DisplayMode::Monochrome => 1,
+            DisplayMode::Color2bpp => 2,
+            DisplayMode::Color4bpp => 4,
+        }
+    }
+
+    pub fn resolution(&self) -> (usize, usize) {
+        match self {
+            DisplayMode::Monochrome => (512, 256),
+            DisplayMode::Color2bpp => (256, 256),
+            DisplayMode::Color4bpp => (256, 128),
+        }
+    }
+}
+
+pub fn rgb565_to_rgb(color: u16) -> [u8; 3] {
+    let r = (color >> 11) & 0b11111;
+    let g = (color >> 5) & 0b111111;
+    let b = color & 0b11111;
+    [(r * 255 / 31) as u8, (g * 255 / 63) as u8, (b * 255 / 31) as u8]
+}
+
+fn pixel_value(ram: &[u16], mode: DisplayMode, x: usize, y: usize) -> u16 {
+    let (width, _) = mode.resolution();
+    let bits_per_pixel = mode.bits_per_pixel();
+    let pixels_per_word = 16 / bits_per_pixel;
+    let word = ram[SCREEN + (y * width + x) / pixels_per_word];
+    let shift = (pixels_per_word - 1 - x % pixels_per_word) * bits_per_pixel;
+    (word >> shift) & ((1 << bits_per_pixel) - 1)
+}
+
+// Renders the screen memory as 512x256 RGB pixels, whatever the current
+// display mode. Lower resolution modes are scaled up to fill the screen.
+pub fn render_rgb(ram: &[u16]) -> Vec<[u8; 3]> {
+    let mode = DisplayMode::from_register(ram[DISPLAY_MODE]);
+    let (width, height) = mode.resolution();
+    let (x_scale, y_scale) = (SCREEN_WIDTH / width, SCREEN_HEIGHT / height);
+    let mut pixels = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+    for screen_y in 0..SCREEN_HEIGHT {
+        for screen_x in 0..SCREEN_WIDTH {
+            let value = pixel_value(ram, mode, screen_x / x_scale, screen_y / y_scale);
+            pixels.push(match mode {
+                DisplayMode::Monochrome => {
+                    if value == 0 {
+                        [0, 0, 0]
+                    } else {
+                        [255, 255, 255]
+                    }
+                }
+                _ => rgb565_to_rgb(ram[PALETTE + value as usize]),
+            });
+        }
+    }
+    pixels
+}
+
+// Monochrome screen memory as a pbm image. This ignores the display mode, and
+// just dumps the raw bits.
+pub fn screen_pbm(ram: &[u16]) -> Vec<u8> {
+    let mut bytes: Vec<_> = format!("P4\n{} {}\n", SCREEN_WIDTH, SCREEN_HEIGHT).bytes().collect();
+    for word in &ram[SCREEN..SCREEN + SCREEN_SIZE] {
+        // pbm format is (usually) big-endian
+        let [msb, lsb] = word.to_be_bytes();
+        bytes.push(!msb);
+        bytes.push(!lsb);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_layout::RAM_SIZE;
+
+    #[test]
+    fn test_rgb565_to_rgb() {
+        assert_eq!(rgb565_to_rgb(0x0000), [0, 0, 0]);
+        assert_eq!(rgb565_to_rgb(0xffff), [255, 255, 255]);
+        assert_eq!(rgb565_to_rgb(0xf800), [255, 0, 0]);
+        assert_eq!(rgb565_to_rgb(0x07e0), [0, 255, 0]);
+        assert_eq!(rgb565_to_rgb(0x001f), [0, 0, 255]);
+    }
+
+    #[test]
+    fn test_render_monochrome() {
+        let mut ram = vec![0; RAM_SIZE];
+        ram[SCREEN] = 0b1000000000000001;
+        let pixels = render_rgb(&ram);
+        assert_eq!(pixels[0], [255, 255, 255]);
+        assert_eq!(pixels[1], [0, 0, 0]);
+        assert_eq!(pixels[15], [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_render_4bpp() {
+        let mut ram = vec![0; RAM_SIZE];
+        ram[DISPLAY_MODE] = 2;
+        ram[PALETTE + 3] = 0xf800;
+        ram[PALETTE + 15] = 0x001f;
+        // The top-left pixel is palette entry 3, and the one to the right of it
+        // is palette entry 15.
+        ram[SCREEN] = 0x3f00;
+        let pixels = render_rgb(&ram);
+        // Each 4bpp pixel covers 2x2 screen pixels.
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            assert_eq!(pixels[y * SCREEN_WIDTH + x], [255, 0, 0]);
+        }
+        assert_eq!(pixels[2], [0, 0, 255]);
+        assert_eq!(pixels[4], rgb565_to_rgb(ram[PALETTE]));
+    }
+
+    #[test]
+    fn test_render_2bpp() {
+        let mut ram = vec![0; RAM_SIZE];
+        ram[DISPLAY_MODE] = 1;
+        ram[PALETTE + 2] = 0x07e0;
+        // The second pixel on the second row.
+        ram[SCREEN + 32] = 0b0010000000000000;
+        let pixels = render_rgb(&ram);
+        assert_eq!(pixels[SCREEN_WIDTH + 2], [0, 255, 0]);
+        assert_eq!(pixels[SCREEN_WIDTH + 3], [0, 255, 0]);
+        assert_eq!(pixels[SCREEN_WIDTH + 1], [0, 0, 0]);
+    }
+}
@@ -0,0 +1,23 @@
+use std::fmt::{Display, Formatter};
+
+// Something that went wrong badly enough that the computer has stopped
+// executing instructions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    WriteProtected { pc: u16, address: u16 },
+    // Only with stack checking on. An overflow is caught at the push which
+    // would have written to the heap, or when SP is moved past the top of the
+    // stack region.
+    StackOverflow { pc: u16, sp: u16 },
+    StackUnderflow { pc: u16, sp: u16 },
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Fault::WriteProtected { pc, address } => write!(f, "write to protected address {} at pc {}", address, pc),
+            Fault::StackOverflow { pc, sp } => write!(f, "stack overflowed into the heap with SP at {} at pc {}", sp, pc),
+            Fault::StackUnderflow { pc, sp } => write!(f, "stack underflowed below its base with SP at {} at pc {}", sp, pc),
+        }
+    }
+}
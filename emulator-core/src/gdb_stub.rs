@@ -0,0 +1,434 @@
+// A stub for the GDB remote serial protocol, so that debuggers which speak it
+// can control the emulator. The protocol is described here
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+//
+// There isn't a gdb architecture for the hack computer, so this follows the
+// lead of AVR, which also has separate code and data address spaces. ROM is
+// at byte address 0 and RAM is at byte address 0x800000. Both are word
+// addressed on the computer, so each word takes up two bytes, little-endian.
+// The registers are A, D and PC, all 16-bit little-endian, where PC is a byte
+// address in ROM like any other code address.
+
+use std::{
+    collections::HashSet,
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use crate::{
+    computer::{tick, Computer},
+    memory_layout::RAM_SIZE,
+};
+
+pub const RAM_BASE: usize = 0x800000;
+
+const SIGTRAP: u8 = 5;
+const SIGINT: u8 = 2;
+const SIGSEGV: u8 = 11;
+
+// How many instructions to run between checking whether the debugger wants to
+// interrupt a continue.
+const INTERRUPT_POLL_INTERVAL: usize = 100_000;
+
+pub trait Connection: Read + Write {
+    // Whether the debugger has sent an interrupt since the program was
+    // continued. This shouldn't block.
+    fn poll_interrupt(&mut self) -> io::Result<bool>;
+}
+
+impl Connection for TcpStream {
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = match self.read(&mut byte) {
+            Ok(1) => Ok(byte[0] == 0x03),
+            Ok(_) => Ok(false),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        };
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+enum Memory {
+    Rom(usize),
+    Ram(usize),
+}
+
+// Maps a byte address to the word it's part of, and whether it's the low byte.
+fn memory_word(byte_address: usize) -> Option<(Memory, bool)> {
+    let is_low_byte = byte_address % 2 == 0;
+    if byte_address < RAM_BASE {
+        let word_address = byte_address / 2;
+        (word_address < RAM_SIZE).then_some((Memory::Rom(word_address), is_low_byte))
+    } else {
+        let word_address = (byte_address - RAM_BASE) / 2;
+        (word_address < RAM_SIZE).then_some((Memory::Ram(word_address), is_low_byte))
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).ok())
+        .collect()
+}
+
+fn parse_address_and_length(args: &str) -> Option<(usize, usize)> {
+    let (address, length) = args.split_once(',')?;
+    Some((usize::from_str_radix(address, 16).ok()?, usize::from_str_radix(length, 16).ok()?))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+pub struct GdbStub<C: Connection> {
+    connection: C,
+    breakpoints: HashSet<u16>,
+}
+
+impl<C: Connection> GdbStub<C> {
+    pub fn new(connection: C) -> Self {
+        Self {
+            connection,
+            breakpoints: HashSet::new(),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.connection.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // Returns None when the debugger hangs up.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // skip acks, interrupts while stopped, and anything else between
+            // packets
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut expected_checksum = [0; 2];
+            self.connection.read_exact(&mut expected_checksum)?;
+            let expected_checksum = std::str::from_utf8(&expected_checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if expected_checksum == Some(checksum(&data)) {
+                self.connection.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            } else {
+                self.connection.write_all(b"-")?;
+            }
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        write!(self.connection, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+        self.connection.flush()
+    }
+
+    fn stop_reply(computer: &Computer, signal: u8) -> String {
+        if computer.fault().is_some() {
+            format!("S{:02x}", SIGSEGV)
+        } else {
+            format!("S{:02x}", signal)
+        }
+    }
+
+    fn read_registers(computer: &Computer) -> String {
+        // The pc can be anywhere a jump took it, but the instruction comes from
+        // the same place in ROM as it does for `tick`.
+        let pc = (computer.cpu.pc as usize % computer.rom().len()) as u16;
+        let registers = [computer.cpu.reg_a, computer.cpu.reg_d, pc * 2];
+        registers.iter().map(|register| hex_bytes(&register.to_le_bytes())).collect()
+    }
+
+    fn write_register(computer: &mut Computer, register: usize, value: u16) -> bool {
+        match register {
+            0 => computer.cpu.reg_a = value,
+            1 => computer.cpu.reg_d = value,
+            2 => computer.cpu.pc = value / 2,
+            _ => return false,
+        }
+        true
+    }
+
+    fn read_memory(computer: &Computer, address: usize, length: usize) -> Option<Vec<u8>> {
+        let ram = computer.ram.lock();
+        (address..address.checked_add(length)?)
+            .map(|byte_address| {
+                let (memory, is_low_byte) = memory_word(byte_address)?;
+                let word = match memory {
+                    Memory::Rom(address) => computer.rom()[address],
+                    Memory::Ram(address) => ram[address],
+                };
+                let [low, high] = word.to_le_bytes();
+                Some(if is_low_byte { low } else { high })
+            })
+            .collect()
+    }
+
+    // Writes go straight to memory, ignoring write protection and memory
+    // mapped registers.
+    fn write_memory(computer: &mut Computer, address: usize, bytes: &[u8]) -> bool {
+        for (idx, &byte) in bytes.iter().enumerate() {
+            let Some((memory, is_low_byte)) = address.checked_add(idx).and_then(memory_word) else {
+                return false;
+            };
+            let with_byte = |word: u16| {
+                let [low, high] = word.to_le_bytes();
+                if is_low_byte {
+                    u16::from_le_bytes([byte, high])
+                } else {
+                    u16::from_le_bytes([low, byte])
+                }
+            };
+            match memory {
+                Memory::Rom(address) => computer.rom_mut()[address] = with_byte(computer.rom()[address]),
+                Memory::Ram(address) => {
+                    let mut ram = computer.ram.lock();
+                    ram[address] = with_byte(ram[address]);
+                }
+            }
+        }
+        true
+    }
+
+    fn continue_execution(&mut self, computer: &mut Computer) -> io::Result<String> {
+        // Always execute at least one instruction, so that continuing from a
+        // breakpoint doesn't stop straight away.
+        tick(computer);
+        let mut ticks = 0;
+        loop {
+            if computer.fault().is_some() || self.breakpoints.contains(&computer.cpu.pc) {
+                return Ok(Self::stop_reply(computer, SIGTRAP));
+            }
+            ticks += 1;
+            if ticks % INTERRUPT_POLL_INTERVAL == 0 && self.connection.poll_interrupt()? {
+                return Ok(Self::stop_reply(computer, SIGINT));
+            }
+            tick(computer);
+        }
+    }
+
+    fn handle_breakpoint(&mut self, args: &str, insert: bool) -> &'static str {
+        // Only software breakpoints are supported, which are type 0. The
+        // address is followed by a kind, which doesn't matter here.
+        let mut parts = args.split(',');
+        let (Some("0"), Some(address)) = (parts.next(), parts.next()) else {
+            return "";
+        };
+        let Ok(address) = usize::from_str_radix(address, 16) else {
+            return "E01";
+        };
+        if address >= RAM_BASE {
+            return "E01";
+        }
+        let pc = (address / 2) as u16;
+        if insert {
+            self.breakpoints.insert(pc);
+        } else {
+            self.breakpoints.remove(&pc);
+        }
+        "OK"
+    }
+
+    // Handles a single packet, returning the reply, or None if the debugger
+    // is done with the computer.
+    fn handle_packet(&mut self, computer: &mut Computer, packet: &str) -> io::Result<Option<String>> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "?" => Self::stop_reply(computer, SIGTRAP),
+            "g" => Self::read_registers(computer),
+            "G" => match parse_hex_bytes(args) {
+                Some(bytes) if bytes.len() == 6 => {
+                    for (register, value) in bytes.chunks(2).enumerate() {
+                        Self::write_register(computer, register, u16::from_le_bytes([value[0], value[1]]));
+                    }
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register) if register < 3 => Self::read_registers(computer)[register * 4..register * 4 + 4].to_string(),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let written = args.split_once('=').and_then(|(register, value)| {
+                    let register = usize::from_str_radix(register, 16).ok()?;
+                    let value = parse_hex_bytes(value).filter(|bytes| bytes.len() == 2)?;
+                    Self::write_register(computer, register, u16::from_le_bytes([value[0], value[1]])).then_some(())
+                });
+                if written.is_some() { "OK" } else { "E01" }.to_string()
+            }
+            "m" => parse_address_and_length(args)
+                .and_then(|(address, length)| Self::read_memory(computer, address, length))
+                .map_or("E01".to_string(), |bytes| hex_bytes(&bytes)),
+            "M" => {
+                let written = args.split_once(':').and_then(|(address_and_length, data)| {
+                    let (address, length) = parse_address_and_length(address_and_length)?;
+                    let bytes = parse_hex_bytes(data).filter(|bytes| bytes.len() == length)?;
+                    Self::write_memory(computer, address, &bytes).then_some(())
+                });
+                if written.is_some() { "OK" } else { "E01" }.to_string()
+            }
+            "s" => {
+                tick(computer);
+                Self::stop_reply(computer, SIGTRAP)
+            }
+            "c" => self.continue_execution(computer)?,
+            "Z" => self.handle_breakpoint(args, true).to_string(),
+            "z" => self.handle_breakpoint(args, false).to_string(),
+            "H" => "OK".to_string(),
+            "D" => {
+                self.write_packet("OK")?;
+                return Ok(None);
+            }
+            "k" => return Ok(None),
+            "q" if args.starts_with("Supported") => "PacketSize=1000;swbreak+".to_string(),
+            "q" if args == "Attached" => "1".to_string(),
+            "q" if args == "C" => "QC1".to_string(),
+            "q" if args == "fThreadInfo" => "m1".to_string(),
+            "q" if args == "sThreadInfo" => "l".to_string(),
+            // An empty reply means the packet isn't supported.
+            _ => "".to_string(),
+        };
+        Ok(Some(reply))
+    }
+
+    // Serves the debugger until it detaches or hangs up. The computer starts
+    // off stopped.
+    pub fn serve(&mut self, computer: &mut Computer) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle_packet(computer, &packet)? {
+                Some(reply) => self.write_packet(&reply)?,
+                None => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    struct TestConnection {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for TestConnection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for TestConnection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for TestConnection {
+        fn poll_interrupt(&mut self) -> io::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum(data.as_bytes()))
+    }
+
+    // Sends the packets to a stub, returning the replies.
+    fn session(computer: &mut Computer, packets: &[&str]) -> Vec<String> {
+        let input: String = packets.iter().map(|data| packet(data)).collect();
+        let mut stub = GdbStub::new(TestConnection {
+            input: Cursor::new(input.into_bytes()),
+            output: Vec::new(),
+        });
+        stub.serve(computer).unwrap();
+        let output = String::from_utf8(stub.connection.output).unwrap();
+        output
+            .split('$')
+            .skip(1)
+            .map(|reply| reply.split_once('#').unwrap().0.to_string())
+            .collect()
+    }
+
+    fn computer_with_program(program: &[u16]) -> Computer {
+        let mut rom = [0; 32768];
+        rom[..program.len()].copy_from_slice(program);
+        Computer::new(rom)
+    }
+
+    // @5, D=A, @7, M=D, then loop forever
+    const PROGRAM: [u16; 6] = [5, 0xec10, 7, 0xe308, 4, 0xea87];
+
+    #[test]
+    fn test_step_and_registers() {
+        let mut computer = computer_with_program(&PROGRAM);
+        let replies = session(&mut computer, &["?", "s", "s", "g", "p2", "P1=0900", "p1"]);
+        assert_eq!(replies, ["S05", "S05", "S05", "050005000400", "0400", "OK", "0900"]);
+        assert_eq!(computer.cpu.reg_d, 9);
+    }
+
+    #[test]
+    fn test_breakpoints_and_memory() {
+        let mut computer = computer_with_program(&PROGRAM);
+        let replies = session(
+            &mut computer,
+            &["Z0,8,2", "c", "m80000e,2", "M800000,2:3412", "m8,2", "M8,2:0a00", "z0,8,2", "D"],
+        );
+        assert_eq!(replies, ["OK", "S05", "0500", "OK", "0400", "OK", "OK", "OK"]);
+        assert_eq!(computer.cpu.pc, 4);
+        assert_eq!(computer.ram.lock()[0], 0x1234);
+        assert_eq!(computer.rom()[4], 10);
+    }
+
+    #[test]
+    fn test_high_pc() {
+        let mut computer = computer_with_program(&PROGRAM);
+        computer.cpu.pc = 0x8004;
+        let replies = session(&mut computer, &["p2", "s", "p2"]);
+        assert_eq!(replies, ["0800", "S05", "0a00"]);
+    }
+
+    #[test]
+    fn test_bad_packets() {
+        let mut computer = computer_with_program(&PROGRAM);
+        let replies = session(
+            &mut computer,
+            &["mffffffffffffffff,10", "M0,2:a\u{e9}0", "P0=\u{e9}", "Mffffffffffffffff,2:0000"],
+        );
+        assert_eq!(replies, ["E01", "E01", "E01", "E01"]);
+    }
+}
@@ -1,7 +1,14 @@
+use crate::{
+    image_format::{decode, ImageFormat},
+    ram_image::RamImage,
+};
+
 pub fn from_string(source: String) -> [u16; 32768] {
+    from_image(&decode(source.as_bytes(), ImageFormat::MemBinary).unwrap_or_else(|err| panic!("invalid machine code: {}", err)))
+}
+
+pub fn from_image(image: &RamImage) -> [u16; 32768] {
     let mut rom = [0; 32768];
-    for (idx, line) in source.lines().enumerate() {
-        rom[idx] = u16::from_str_radix(line, 2).unwrap();
-    }
+    image.load(&mut rom);
     rom
 }
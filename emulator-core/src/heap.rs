@@ -0,0 +1,343 @@
+// Decodes the heap that std_lib/Memory.jack's buddy allocator keeps, straight
+// from RAM, so it can be looked at whenever the computer is stopped. Every
+// block starts with a header of
+//
+//     free flag | order | next free block | previous free block
+//
+// where the free flag is true (-1) or false (0), the block is 2^order words
+// long, and the links are only there while the block is free. Blocks tile the
+// heap, so walking it from HEAP_BASE by block size finds all of them. Each
+// order has a list of free blocks, with the list heads kept in a block which
+// Memory.init allocates for itself.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    computer::Ram,
+    memory_layout::{HEAP_BASE, SCREEN},
+};
+
+pub const MIN_ORDER: u16 = 2;
+pub const MAX_ORDER: u16 = 14;
+
+// Memory.init's first allocation is for the list heads. It's always the lowest
+// block in the heap, so they're just after its header.
+pub const AVAIL_LISTS: usize = HEAP_BASE + 2;
+
+const HEAP_END: usize = SCREEN;
+const TRUE: u16 = 0xffff;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HeapBlock {
+    pub address: u16,
+    pub order: u16,
+    pub free: bool,
+    // The block which this one merges with when they're both free. The block
+    // covering the whole heap doesn't have one.
+    pub buddy: Option<u16>,
+}
+
+impl HeapBlock {
+    fn new(address: usize, order: u16, free: bool) -> Self {
+        let buddy = (order < MAX_ORDER).then(|| (((address - HEAP_BASE) ^ (1 << order)) + HEAP_BASE) as u16);
+        Self {
+            address: address as u16,
+            order,
+            free,
+            buddy,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        1 << self.order
+    }
+
+    // The pointer which Memory.malloc handed out for the block.
+    pub fn data_address(&self) -> u16 {
+        self.address + 2
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Heap {
+    // In address order.
+    pub blocks: Vec<HeapBlock>,
+    // The free list for each order, starting from MIN_ORDER.
+    pub free_lists: Vec<Vec<u16>>,
+    // Broken allocator invariants. A heap which Memory.init hasn't set up yet
+    // shows up as broken too.
+    pub problems: Vec<String>,
+}
+
+// Follows the blocks from the start of the heap, stopping at the first header
+// which doesn't make sense.
+fn walk_blocks(ram: &[u16], problems: &mut Vec<String>) -> Vec<HeapBlock> {
+    let mut blocks = Vec::new();
+    let mut address = HEAP_BASE;
+    while address < HEAP_END {
+        let (flag, order) = (ram[address], ram[address + 1]);
+        if !(MIN_ORDER..=MAX_ORDER).contains(&order) {
+            problems.push(format!("block at {} has order {}", address, order));
+            break;
+        }
+        let block = HeapBlock::new(address, order, flag == TRUE);
+        if (address - HEAP_BASE) % block.size() != 0 || address + block.size() > HEAP_END {
+            problems.push(format!("block at {} of order {} isn't aligned to its size", address, order));
+            break;
+        }
+        if flag != TRUE && flag != 0 {
+            problems.push(format!("block at {} has free flag {}", address, flag));
+        }
+        address += block.size();
+        blocks.push(block);
+    }
+    blocks
+}
+
+fn walk_free_list(ram: &[u16], order: u16, blocks: &[HeapBlock], problems: &mut Vec<String>) -> Vec<u16> {
+    let mut list = Vec::new();
+    let mut prev = 0;
+    let mut current = ram[AVAIL_LISTS + (order - MIN_ORDER) as usize];
+    while current != 0 {
+        if list.contains(&current) {
+            problems.push(format!("free list for order {} loops back to {}", order, current));
+            break;
+        }
+        let Ok(idx) = blocks.binary_search_by_key(&current, |block| block.address) else {
+            problems.push(format!("free list for order {} has {}, which isn't a block", order, current));
+            break;
+        };
+        let block = &blocks[idx];
+        if !block.free || block.order != order {
+            problems.push(format!(
+                "free list for order {} has {}, which is a {} block of order {}",
+                order,
+                current,
+                if block.free { "free" } else { "used" },
+                block.order
+            ));
+        }
+        let back_link = ram[current as usize + 3];
+        if back_link != prev {
+            problems.push(format!("block at {} links back to {} rather than {}", current, back_link, prev));
+        }
+        list.push(current);
+        prev = current;
+        current = ram[current as usize + 2];
+    }
+    list
+}
+
+pub fn inspect_heap(ram: &[u16]) -> Heap {
+    let mut problems = Vec::new();
+    let blocks = walk_blocks(ram, &mut problems);
+
+    // The list heads live in the first block, so they can't be trusted unless
+    // it's been allocated.
+    let free_lists = match blocks.first() {
+        Some(first) if !first.free && first.size() >= 2 + (MAX_ORDER - MIN_ORDER + 1) as usize => (MIN_ORDER..=MAX_ORDER)
+            .map(|order| walk_free_list(ram, order, &blocks, &mut problems))
+            .collect(),
+        _ => {
+            problems.push(format!("there's no block for the free lists at {}", HEAP_BASE));
+            Vec::new()
+        }
+    };
+
+    for block in blocks.iter().filter(|block| block.free) {
+        let listed = free_lists
+            .get((block.order - MIN_ORDER) as usize)
+            .is_some_and(|list| list.contains(&block.address));
+        if !listed {
+            problems.push(format!(
+                "free block at {} isn't in the free list for order {}",
+                block.address, block.order
+            ));
+        }
+        let unmerged_buddy = block
+            .buddy
+            .filter(|&buddy| buddy > block.address)
+            .and_then(|buddy| blocks.iter().find(|other| other.address == buddy))
+            .filter(|buddy| buddy.free && buddy.order == block.order);
+        if let Some(buddy) = unmerged_buddy {
+            problems.push(format!(
+                "free buddies at {} and {} of order {} haven't been merged",
+                block.address, buddy.address, block.order
+            ));
+        }
+    }
+
+    Heap {
+        blocks,
+        free_lists,
+        problems,
+    }
+}
+
+impl Heap {
+    pub fn free_words(&self) -> usize {
+        self.blocks.iter().filter(|block| block.free).map(HeapBlock::size).sum()
+    }
+
+    pub fn used_words(&self) -> usize {
+        self.blocks.iter().filter(|block| !block.free).map(HeapBlock::size).sum()
+    }
+
+    pub fn largest_free_block(&self) -> Option<&HeapBlock> {
+        self.blocks.iter().filter(|block| block.free).max_by_key(|block| block.size())
+    }
+
+    // The fraction of free memory which isn't in the largest free block, so 0
+    // when the free memory is all in one piece.
+    pub fn fragmentation(&self) -> f64 {
+        let free_words = self.free_words();
+        match self.largest_free_block() {
+            Some(largest) => 1.0 - largest.size() as f64 / free_words as f64,
+            None => 0.0,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let count = |free: bool| self.blocks.iter().filter(|block| block.free == free).count();
+        let mut lines = vec![
+            format!("{} used blocks, {} words", count(false), self.used_words()),
+            format!("{} free blocks, {} words", count(true), self.free_words()),
+        ];
+        if let Some(largest) = self.largest_free_block() {
+            lines.push(format!(
+                "largest free block {} words at {}, fragmentation {:.0}%",
+                largest.size(),
+                largest.address,
+                self.fragmentation() * 100.0
+            ));
+        }
+        lines.extend(self.problems.iter().map(|problem| format!("problem: {}", problem)));
+        lines.join("\n")
+    }
+
+    pub fn format_blocks(&self) -> String {
+        let lines: Vec<_> = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let buddy = block.buddy.map(|buddy| format!("  buddy {}", buddy)).unwrap_or_default();
+                let state = if block.free {
+                    "free".to_string()
+                } else {
+                    format!("used, data at {}", block.data_address())
+                };
+                format!(
+                    "{:>5}  order {:>2}  {:>5} words  {}{}",
+                    block.address,
+                    block.order,
+                    block.size(),
+                    state,
+                    buddy
+                )
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
+
+// For drawing the heap in the browser.
+#[wasm_bindgen]
+pub fn heap_json(ram: &Ram) -> String {
+    serde_json::to_string(&inspect_heap(&*ram.lock())).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_layout::RAM_SIZE;
+
+    // Lays out blocks from the start of the heap, given as (order, free), and
+    // links up the free ones.
+    fn heap(layout: &[(u16, bool)]) -> Vec<u16> {
+        let mut ram = vec![0; RAM_SIZE];
+        let mut address = HEAP_BASE;
+        for &(order, free) in layout {
+            ram[address] = if free { TRUE } else { 0 };
+            ram[address + 1] = order;
+            if free {
+                let head = AVAIL_LISTS + (order - MIN_ORDER) as usize;
+                let old_head = ram[head];
+                if old_head != 0 {
+                    ram[old_head as usize + 3] = address as u16;
+                }
+                ram[address + 2] = old_head;
+                ram[address + 3] = 0;
+                ram[head] = address as u16;
+            }
+            address += 1 << order;
+        }
+        ram
+    }
+
+    // What Memory.init leaves behind, with the list heads in the first block.
+    const INITIAL_LAYOUT: [(u16, bool); 11] = [
+        (4, false),
+        (4, true),
+        (5, true),
+        (6, true),
+        (7, true),
+        (8, true),
+        (9, true),
+        (10, true),
+        (11, true),
+        (12, true),
+        (13, true),
+    ];
+
+    #[test]
+    fn test_initial_heap() {
+        let heap = inspect_heap(&heap(&INITIAL_LAYOUT));
+        assert_eq!(heap.problems, Vec::<String>::new());
+        assert_eq!(heap.blocks.len(), 11);
+        assert_eq!(heap.blocks[0], HeapBlock::new(2048, 4, false));
+        assert_eq!(heap.blocks[1].buddy, Some(2048));
+        assert_eq!(heap.blocks[10].buddy, Some(2048));
+        assert_eq!(heap.free_lists[2], vec![2064]);
+        assert_eq!(heap.used_words(), 16);
+        assert_eq!(heap.free_words(), 16384 - 16);
+        assert_eq!(heap.largest_free_block().unwrap().address, 10240);
+        assert!((heap.fragmentation() - (1.0 - 8192.0 / 16368.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_unmerged_buddies() {
+        let mut layout = vec![(4, false), (2, true), (2, true), (3, false)];
+        layout.extend_from_slice(&INITIAL_LAYOUT[2..]);
+        let heap = inspect_heap(&heap(&layout));
+        assert_eq!(heap.free_lists[0], vec![2068, 2064]);
+        assert_eq!(heap.problems, vec!["free buddies at 2064 and 2068 of order 2 haven't been merged"]);
+    }
+
+    #[test]
+    fn test_broken_free_lists() {
+        let mut ram = heap(&INITIAL_LAYOUT);
+        // Take the order 5 block off its list without marking it as used, and
+        // put a used block on the order 6 list.
+        ram[AVAIL_LISTS + 3] = 0;
+        ram[2112] = 0;
+        let heap = inspect_heap(&ram);
+        assert_eq!(
+            heap.problems,
+            vec![
+                "free list for order 6 has 2112, which is a used block of order 6",
+                "free block at 2080 isn't in the free list for order 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uninitialised_heap() {
+        let heap = inspect_heap(&[0; RAM_SIZE]);
+        assert!(heap.blocks.is_empty());
+        assert_eq!(
+            heap.problems,
+            vec!["block at 2048 has order 0", "there's no block for the free lists at 2048"]
+        );
+    }
+}
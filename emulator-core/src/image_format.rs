@@ -0,0 +1,507 @@
+// Reading and writing ROM and RAM images in the formats used by the various
+// tools that might want to load them - the emulators, verilog simulators and
+// FPGA toolchains.
+//
+// Images are always read into a `RamImage`, since some formats can place data
+// at arbitrary addresses. Addresses are word addresses, apart from in Intel
+// HEX, which is byte addressed, so each word takes up two addresses there.
+
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use crate::{
+    memory_layout::RAM_SIZE,
+    ram_image::{RamImage, RamSegment},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    // Two bytes per word, starting at address 0.
+    RawBigEndian,
+    RawLittleEndian,
+    IntelHex,
+    // For verilog's $readmemb and $readmemh. The binary version with one word
+    // per line is also what the compiler has always output.
+    MemBinary,
+    MemHex,
+    // Xilinx coefficient file, for initializing block RAM.
+    Coe,
+    // Hand-written machine code, where each line that starts with binary
+    // digits (which can be split up with spaces) is a word, and anything else
+    // is a comment. Can only be read.
+    Annotated,
+}
+
+pub const FORMAT_NAMES: [&str; 7] = ["bin-be", "bin-le", "ihex", "memb", "memh", "coe", "annotated"];
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "bin-be" => Ok(ImageFormat::RawBigEndian),
+            "bin-le" => Ok(ImageFormat::RawLittleEndian),
+            "ihex" => Ok(ImageFormat::IntelHex),
+            "memb" => Ok(ImageFormat::MemBinary),
+            "memh" => Ok(ImageFormat::MemHex),
+            "coe" => Ok(ImageFormat::Coe),
+            "annotated" => Ok(ImageFormat::Annotated),
+            _ => Err(format!("unknown image format {}, expected one of {}", name, FORMAT_NAMES.join(", "))),
+        }
+    }
+}
+
+impl Display for ImageFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            ImageFormat::RawBigEndian => "bin-be",
+            ImageFormat::RawLittleEndian => "bin-le",
+            ImageFormat::IntelHex => "ihex",
+            ImageFormat::MemBinary => "memb",
+            ImageFormat::MemHex => "memh",
+            ImageFormat::Coe => "coe",
+            ImageFormat::Annotated => "annotated",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ImageFormat {
+    // Guesses the format from the file extension, falling back to looking at
+    // the contents. There's no way of telling the byte order of raw binary, so
+    // that's assumed to be big-endian.
+    pub fn detect(path: Option<&Path>, bytes: &[u8]) -> Self {
+        match path.and_then(|path| path.extension()).and_then(|extension| extension.to_str()) {
+            Some("coe") => return ImageFormat::Coe,
+            Some("ihex") => return ImageFormat::IntelHex,
+            Some("bin") => return ImageFormat::RawBigEndian,
+            _ => {}
+        }
+
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => text,
+            _ => return ImageFormat::RawBigEndian,
+        };
+        if text.trim_start().starts_with(':') {
+            ImageFormat::IntelHex
+        } else if text.to_lowercase().contains("memory_initialization_vector") {
+            ImageFormat::Coe
+        } else {
+            match mem_tokens(text) {
+                Ok(tokens) => {
+                    let is_binary = |token: &str| token.len() == 16 && token.chars().all(|c| "01xXzZ".contains(c));
+                    if tokens.iter().all(|token| token.starts_with('@') || is_binary(&token.replace('_', ""))) {
+                        ImageFormat::MemBinary
+                    } else if tokens
+                        .iter()
+                        .all(|token| token.chars().all(|c| c == '@' || c == '_' || c.is_ascii_hexdigit()))
+                    {
+                        ImageFormat::MemHex
+                    } else {
+                        ImageFormat::Annotated
+                    }
+                }
+                Err(_) => ImageFormat::Annotated,
+            }
+        }
+    }
+}
+
+fn parse_word(digits: &str, radix: u32) -> Result<u16, String> {
+    let digits: String = digits
+        .chars()
+        .filter(|&c| c != '_')
+        // unknown and high impedance bits are just treated as 0
+        .map(|c| if "xXzZ".contains(c) { '0' } else { c })
+        .collect();
+    u16::from_str_radix(&digits, radix).map_err(|_| format!("invalid word {}", digits))
+}
+
+// Splits up $readmem style text into addresses and values, skipping comments.
+fn mem_tokens(text: &str) -> Result<Vec<String>, String> {
+    let mut without_comments = String::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("//") {
+            rest = after.find('\n').map_or("", |idx| &after[idx..]);
+        } else if let Some(after) = rest.strip_prefix("/*") {
+            let end = after.find("*/").ok_or("unterminated comment")?;
+            rest = &after[end + 2..];
+            without_comments.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            without_comments.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    let tokens: Vec<_> = without_comments.split_whitespace().map(str::to_string).collect();
+    match tokens
+        .iter()
+        .find(|token| !token.chars().all(|c| c == '@' || c == '_' || c.is_ascii_alphanumeric()))
+    {
+        Some(token) => Err(format!("unexpected {}", token)),
+        None => Ok(tokens),
+    }
+}
+
+// Collects words into segments, starting a new segment whenever the address
+// jumps.
+#[derive(Default)]
+struct SegmentBuilder {
+    image: RamImage,
+    address: usize,
+    words: Vec<u16>,
+}
+
+impl SegmentBuilder {
+    fn push(&mut self, word: u16) -> Result<(), String> {
+        if self.address + self.words.len() >= RAM_SIZE {
+            return Err(format!("image is bigger than {} words", RAM_SIZE));
+        }
+        self.words.push(word);
+        Ok(())
+    }
+
+    fn set_address(&mut self, address: usize) {
+        if address != self.address + self.words.len() {
+            self.finish_segment();
+            self.address = address;
+        }
+    }
+
+    fn finish_segment(&mut self) {
+        let words = std::mem::take(&mut self.words);
+        let len = words.len();
+        if len > 0 {
+            self.image.add_segment(self.address, words);
+        }
+        self.address += len;
+    }
+
+    fn finish(mut self) -> RamImage {
+        self.finish_segment();
+        self.image
+    }
+}
+
+fn decode_mem(text: &str, radix: u32) -> Result<RamImage, String> {
+    let mut builder = SegmentBuilder::default();
+    for token in mem_tokens(text)? {
+        if let Some(address) = token.strip_prefix('@') {
+            let address = usize::from_str_radix(address, 16).map_err(|_| format!("invalid address {}", token))?;
+            builder.set_address(address);
+        } else {
+            builder.push(parse_word(&token, radix)?)?;
+        }
+    }
+    Ok(builder.finish())
+}
+
+fn decode_annotated(text: &str) -> Result<RamImage, String> {
+    let mut builder = SegmentBuilder::default();
+    for line in text.lines() {
+        let digits: String = line.chars().take_while(|c| "01 \t".contains(*c)).filter(|c| !c.is_whitespace()).collect();
+        if !digits.is_empty() {
+            builder.push(parse_word(&digits, 2)?)?;
+        }
+    }
+    Ok(builder.finish())
+}
+
+fn decode_raw(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Result<RamImage, String> {
+    if bytes.len() % 2 != 0 {
+        return Err("raw binary image has an odd number of bytes".to_string());
+    }
+    let mut builder = SegmentBuilder::default();
+    for pair in bytes.chunks(2) {
+        builder.push(from_bytes([pair[0], pair[1]]))?;
+    }
+    Ok(builder.finish())
+}
+
+fn decode_intel_hex(text: &str) -> Result<RamImage, String> {
+    let mut builder = SegmentBuilder::default();
+    let mut base_address = 0;
+    // Words can in theory be split across records, so keep hold of the high
+    // byte until the low one turns up.
+    let mut high_byte: Option<(usize, u8)> = None;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let hex = line.strip_prefix(':').ok_or_else(|| format!("expected a record, got {}", line))?;
+        if hex.len() % 2 != 0 {
+            return Err(format!("invalid record {}", line));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid record {}", line))?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(format!("invalid record length {}", line));
+        }
+        if bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+            return Err(format!("invalid checksum {}", line));
+        }
+        let data = &bytes[4..bytes.len() - 1];
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        match bytes[3] {
+            0x00 => {
+                for (idx, &byte) in data.iter().enumerate() {
+                    let byte_address = base_address + offset + idx;
+                    match high_byte.take() {
+                        Some((high_address, high)) if high_address + 1 == byte_address => {
+                            builder.set_address(high_address / 2);
+                            builder.push(u16::from_be_bytes([high, byte]))?;
+                        }
+                        Some(_) => return Err(format!("missing low byte before {}", line)),
+                        None if byte_address % 2 == 0 => high_byte = Some((byte_address, byte)),
+                        None => return Err(format!("data doesn't start on a word boundary {}", line)),
+                    }
+                }
+            }
+            0x01 => break,
+            0x02 | 0x04 if data.len() != 2 => return Err(format!("invalid extended address record {}", line)),
+            0x02 => base_address = u16::from_be_bytes([data[0], data[1]]) as usize * 16,
+            0x04 => base_address = (u16::from_be_bytes([data[0], data[1]]) as usize) << 16,
+            // start addresses don't mean anything here
+            0x03 | 0x05 => {}
+            record_type => return Err(format!("unsupported record type {}", record_type)),
+        }
+    }
+    if high_byte.is_some() {
+        return Err("image ends half way through a word".to_string());
+    }
+    Ok(builder.finish())
+}
+
+fn decode_coe(text: &str) -> Result<RamImage, String> {
+    let without_comments = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with(';'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut radix = None;
+    let mut vector = None;
+    for statement in without_comments.split(';') {
+        if let Some((key, value)) = statement.split_once('=') {
+            match key.trim().to_lowercase().as_str() {
+                "memory_initialization_radix" => radix = Some(value.trim().parse::<u32>().map_err(|_| format!("invalid radix {}", value.trim()))?),
+                "memory_initialization_vector" => vector = Some(value),
+                _ => {}
+            }
+        }
+    }
+    let radix = radix.ok_or("coe file is missing memory_initialization_radix")?;
+    if ![2, 10, 16].contains(&radix) {
+        return Err(format!("unsupported radix {}", radix));
+    }
+    let vector = vector.ok_or("coe file is missing memory_initialization_vector")?;
+    let mut builder = SegmentBuilder::default();
+    for value in vector.split(|c: char| c == ',' || c.is_whitespace()).filter(|value| !value.is_empty()) {
+        builder.push(parse_word(value, radix)?)?;
+    }
+    Ok(builder.finish())
+}
+
+pub fn decode(bytes: &[u8], format: ImageFormat) -> Result<RamImage, String> {
+    let text = || std::str::from_utf8(bytes).map_err(|_| format!("{} images should be text", format));
+    match format {
+        ImageFormat::RawBigEndian => decode_raw(bytes, u16::from_be_bytes),
+        ImageFormat::RawLittleEndian => decode_raw(bytes, u16::from_le_bytes),
+        ImageFormat::IntelHex => decode_intel_hex(text()?),
+        ImageFormat::MemBinary => decode_mem(text()?, 2),
+        ImageFormat::MemHex => decode_mem(text()?, 16),
+        ImageFormat::Coe => decode_coe(text()?),
+        ImageFormat::Annotated => decode_annotated(text()?),
+    }
+}
+
+// Formats without addresses can only represent a single block of words
+// starting at 0, so any gaps get filled in with zeros.
+fn contiguous_words(image: &RamImage) -> Vec<u16> {
+    let len = image
+        .segments
+        .iter()
+        .map(|segment| segment.address + segment.words.len())
+        .max()
+        .unwrap_or(0);
+    let mut words = vec![0; len];
+    for RamSegment {
+        address,
+        words: segment_words,
+    } in &image.segments
+    {
+        words[*address..*address + segment_words.len()].copy_from_slice(segment_words);
+    }
+    words
+}
+
+// The image as a single block of words starting at 0, padded with zeros up to
+// `depth` words. Useful for tools which expect the whole of memory.
+pub fn padded(image: &RamImage, depth: usize) -> RamImage {
+    let mut words = contiguous_words(image);
+    if words.len() < depth {
+        words.resize(depth, 0);
+    }
+    let mut padded = RamImage::default();
+    padded.add_segment(0, words);
+    padded
+}
+
+fn encode_mem(image: &RamImage, word_to_string: fn(u16) -> String) -> String {
+    let mut lines = Vec::new();
+    for (idx, RamSegment { address, words }) in image.segments.iter().enumerate() {
+        // Loading starts at address 0 anyway, and leaving the address out
+        // keeps rom images readable by tools that don't understand them.
+        if idx != 0 || *address != 0 {
+            lines.push(format!("@{:04x}", address));
+        }
+        lines.extend(words.iter().map(|&word| word_to_string(word)));
+    }
+    lines.join("\n")
+}
+
+fn intel_hex_record(record_type: u8, offset: u16, data: &[u8]) -> String {
+    let [offset_high, offset_low] = offset.to_be_bytes();
+    let mut bytes = vec![data.len() as u8, offset_high, offset_low, record_type];
+    bytes.extend(data);
+    let checksum = bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)).wrapping_neg();
+    bytes.push(checksum);
+    format!(":{}", bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<String>())
+}
+
+fn encode_intel_hex(image: &RamImage) -> String {
+    let mut lines = Vec::new();
+    let mut upper_address = 0;
+    for RamSegment { address, words } in &image.segments {
+        let bytes: Vec<_> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        for (idx, chunk) in bytes.chunks(16).enumerate() {
+            let byte_address = address * 2 + idx * 16;
+            if byte_address >> 16 != upper_address {
+                upper_address = byte_address >> 16;
+                lines.push(intel_hex_record(0x04, 0, &(upper_address as u16).to_be_bytes()));
+            }
+            lines.push(intel_hex_record(0x00, byte_address as u16, chunk));
+        }
+    }
+    lines.push(intel_hex_record(0x01, 0, &[]));
+    lines.join("\n")
+}
+
+fn encode_coe(image: &RamImage) -> String {
+    let words: Vec<_> = contiguous_words(image).iter().map(|word| format!("{:016b}", word)).collect();
+    format!("memory_initialization_radix=2;\nmemory_initialization_vector=\n{};\n", words.join(",\n"))
+}
+
+pub fn encode(image: &RamImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    Ok(match format {
+        ImageFormat::RawBigEndian => contiguous_words(image).iter().flat_map(|word| word.to_be_bytes()).collect(),
+        ImageFormat::RawLittleEndian => contiguous_words(image).iter().flat_map(|word| word.to_le_bytes()).collect(),
+        ImageFormat::IntelHex => encode_intel_hex(image).into_bytes(),
+        ImageFormat::MemBinary => encode_mem(image, |word| format!("{:016b}", word)).into_bytes(),
+        ImageFormat::MemHex => encode_mem(image, |word| format!("{:04x}", word)).into_bytes(),
+        ImageFormat::Coe => encode_coe(image).into_bytes(),
+        ImageFormat::Annotated => return Err("annotated images can only be read".to_string()),
+    })
+}
+
+// Reads an image in the given format, or whatever format it looks like if
+// there isn't one.
+pub fn read(path: &Path, format: Option<ImageFormat>) -> Result<RamImage, String> {
+    let bytes = std::fs::read(path).map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    let format = format.unwrap_or_else(|| ImageFormat::detect(Some(path), &bytes));
+    decode(&bytes, format).map_err(|err| format!("failed to read {} as {}: {}", path.display(), format, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_image() -> RamImage {
+        let mut image = RamImage::default();
+        image.add_segment(0, vec![0x1234, 0xffff, 0]);
+        image.add_segment(0x6801, vec![0xabcd; 20]);
+        image
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in [ImageFormat::IntelHex, ImageFormat::MemBinary, ImageFormat::MemHex] {
+            let encoded = encode(&example_image(), format).unwrap();
+            assert_eq!(decode(&encoded, format).unwrap(), example_image(), "{}", format);
+            assert_eq!(ImageFormat::detect(None, &encoded), format);
+        }
+        // formats without addresses fill in the gaps
+        let contiguous = RamImage {
+            segments: vec![RamSegment {
+                address: 0,
+                words: contiguous_words(&example_image()),
+            }],
+        };
+        for format in [ImageFormat::RawBigEndian, ImageFormat::RawLittleEndian, ImageFormat::Coe] {
+            let encoded = encode(&example_image(), format).unwrap();
+            assert_eq!(decode(&encoded, format).unwrap(), contiguous, "{}", format);
+        }
+    }
+
+    #[test]
+    fn test_raw_byte_order() {
+        let image = decode(&[0x12, 0x34], ImageFormat::RawBigEndian).unwrap();
+        assert_eq!(image.segments[0].words, [0x1234]);
+        let image = decode(&[0x12, 0x34], ImageFormat::RawLittleEndian).unwrap();
+        assert_eq!(image.segments[0].words, [0x3412]);
+        assert_eq!(ImageFormat::detect(None, &[0x12, 0x34]), ImageFormat::RawBigEndian);
+    }
+
+    #[test]
+    fn test_intel_hex() {
+        let hex = ":0400000012340001B5\n:00000001FF";
+        assert_eq!(decode(hex.as_bytes(), ImageFormat::IntelHex).unwrap().segments[0].words, [0x1234, 0x0001]);
+        assert!(decode(":0400000012340001B6".as_bytes(), ImageFormat::IntelHex).is_err());
+        for record in [":00000004FC", ":00000002FE", ":03000004000000F9"] {
+            assert_eq!(
+                decode(record.as_bytes(), ImageFormat::IntelHex).unwrap_err(),
+                format!("invalid extended address record {}", record)
+            );
+        }
+        assert_eq!(
+            String::from_utf8(encode(&decode(hex.as_bytes(), ImageFormat::IntelHex).unwrap(), ImageFormat::IntelHex).unwrap()).unwrap(),
+            hex
+        );
+    }
+
+    #[test]
+    fn test_mem_comments_and_addresses() {
+        let mem = "// glyphs\n@10 0000_0000_0000_0001 /* two */ 000000000000001x\n@20\n1111111111111111";
+        let image = decode(mem.as_bytes(), ImageFormat::MemBinary).unwrap();
+        assert_eq!(
+            image.segments,
+            [
+                RamSegment {
+                    address: 0x10,
+                    words: vec![1, 2]
+                },
+                RamSegment {
+                    address: 0x20,
+                    words: vec![0xffff]
+                },
+            ]
+        );
+        assert_eq!(ImageFormat::detect(None, mem.as_bytes()), ImageFormat::MemBinary);
+        assert_eq!(ImageFormat::detect(None, b"@10 dead beef"), ImageFormat::MemHex);
+    }
+
+    #[test]
+    fn test_coe() {
+        let coe = "; a comment\nmemory_initialization_radix=16;\nmemory_initialization_vector=\n1234, ffff,\n0001;";
+        let image = decode(coe.as_bytes(), ImageFormat::Coe).unwrap();
+        assert_eq!(image.segments[0].words, [0x1234, 0xffff, 1]);
+        assert_eq!(ImageFormat::detect(None, coe.as_bytes()), ImageFormat::Coe);
+    }
+
+    #[test]
+    fn test_annotated() {
+        let program = "IF LED IS ON, JUMP TO 8\n\n0111 0110 1101 1001 // load 30425 into A | 0\n0000 0000 0000 1010 | 6\n";
+        assert_eq!(ImageFormat::detect(None, program.as_bytes()), ImageFormat::Annotated);
+        let image = decode(program.as_bytes(), ImageFormat::Annotated).unwrap();
+        assert_eq!(image.segments[0].words, [30425, 10]);
+    }
+}
@@ -0,0 +1,379 @@
+// Scripts of keys to press, which are played into the keyboard register so
+// that programs which read the keyboard can be run the same way every time,
+// and tested. Each line of a script is an event, which puts a key down or
+// lets it up once its trigger happens:
+//
+//     # Types a capital H, then presses enter once something has been drawn.
+//     down shift+H at 200000
+//     up after 50000
+//     down Enter when RAM[18432] != 0
+//     up after 50000
+//
+// `at` is a number of instructions since the program started, `after` is a
+// number of instructions since the event before, and `when` waits for a
+// condition, written like a breakpoint condition (see `breakpoints`). The
+// events happen in order, so a trigger isn't looked at until the event before
+// it has happened.
+//
+// The keyboard register only holds one key at a time, along with the
+// modifiers, so `down` replaces whatever key was down and `up` lets go of all
+// of them. Keys are named like `A`, `7`, `Enter` or `[`, with modifiers in
+// front like `ctrl+shift+A`, or given as the number to put in the register.
+
+use std::fmt;
+
+use crate::{
+    breakpoints::{Expression, Scope},
+    memory_layout::KBD,
+};
+
+// The keys by scancode, starting from 1.
+const KEY_NAMES: [&str; 55] = [
+    "0",
+    "1",
+    "2",
+    "3",
+    "4",
+    "5",
+    "6",
+    "7",
+    "8",
+    "9",
+    "A",
+    "B",
+    "C",
+    "D",
+    "E",
+    "F",
+    "G",
+    "H",
+    "I",
+    "J",
+    "K",
+    "L",
+    "M",
+    "N",
+    "O",
+    "P",
+    "Q",
+    "R",
+    "S",
+    "T",
+    "U",
+    "V",
+    "W",
+    "X",
+    "Y",
+    "Z",
+    "Left",
+    "Up",
+    "Right",
+    "Down",
+    "Enter",
+    "Backspace",
+    "'",
+    "`",
+    "\\",
+    ",",
+    "=",
+    "[",
+    "]",
+    "-",
+    ".",
+    ";",
+    "/",
+    "Space",
+    "Tab",
+];
+
+// The modifiers by their bit in the keyboard register.
+const MODIFIERS: [(&str, u16); 4] = [("ctrl", 1 << 15), ("alt", 1 << 14), ("super", 1 << 13), ("shift", 1 << 12)];
+
+const SCANCODE_MASK: u16 = 0x0fff;
+
+pub fn parse_key(text: &str) -> Result<u16, String> {
+    parse_key_name(text).or_else(|err| text.parse().map_err(|_| err))
+}
+
+fn parse_key_name(text: &str) -> Result<u16, String> {
+    let mut value = 0;
+    let mut parts = text.split('+').peekable();
+    while let Some(part) = parts.next() {
+        if let Some((_, bit)) = MODIFIERS.iter().find(|(name, _)| name.eq_ignore_ascii_case(part)) {
+            value |= bit;
+        } else if parts.peek().is_none() {
+            let idx = KEY_NAMES
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| format!("there's no key called {}", part))?;
+            value |= idx as u16 + 1;
+        } else {
+            return Err(format!("there's no modifier called {}", part));
+        }
+    }
+    Ok(value)
+}
+
+// Keys which don't have a name are shown as the number in the register.
+pub fn format_key(value: u16) -> String {
+    let scancode = value & SCANCODE_MASK;
+    let key = match scancode {
+        0 => None,
+        _ => match KEY_NAMES.get(scancode as usize - 1) {
+            Some(name) => Some(*name),
+            None => return value.to_string(),
+        },
+    };
+    let modifiers = MODIFIERS.iter().filter(|(_, bit)| value & bit != 0).map(|(name, _)| *name);
+    modifiers.chain(key).collect::<Vec<_>>().join("+")
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    At(u64),
+    After(u64),
+    When(Expression),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    // What to put in the keyboard register, which is 0 to let go.
+    pub value: u16,
+    pub trigger: Trigger,
+    // Where it is in the script, for errors.
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputScript {
+    pub events: Vec<InputEvent>,
+}
+
+fn parse_event(text: &str, line: usize) -> Result<InputEvent, String> {
+    let words: Vec<_> = text.split_whitespace().collect();
+    let (value, trigger) = match words.as_slice() {
+        ["down", key, trigger @ ..] => (parse_key(key)?, trigger),
+        ["up", trigger @ ..] => (0, trigger),
+        _ => return Err("expected down <key> or up".to_string()),
+    };
+    let count = |count: &str| count.parse().map_err(|_| format!("{} isn't a number of instructions", count));
+    let trigger = match trigger {
+        ["at", tick] => Trigger::At(count(tick)?),
+        ["after", ticks] => Trigger::After(count(ticks)?),
+        ["when", condition @ ..] if !condition.is_empty() => Trigger::When(Expression::parse(&condition.join(" "))?),
+        _ => return Err("expected at <tick>, after <ticks> or when <condition>".to_string()),
+    };
+    Ok(InputEvent { value, trigger, line })
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            events.push(parse_event(line, idx + 1).map_err(|err| format!("line {}: {}", idx + 1, err))?);
+        }
+        Ok(Self { events })
+    }
+}
+
+impl fmt::Display for InputScript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            match event.value {
+                0 => write!(f, "up")?,
+                value => write!(f, "down {}", format_key(value))?,
+            }
+            match &event.trigger {
+                Trigger::At(tick) => writeln!(f, " at {}", tick)?,
+                Trigger::After(ticks) => writeln!(f, " after {}", ticks)?,
+                Trigger::When(condition) => writeln!(f, " when {}", condition.source())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Plays a script into the keyboard register, as the program runs.
+pub struct InputPlayer {
+    script: InputScript,
+    next_event: usize,
+    ticks: u64,
+    // When the last event happened.
+    last_event_tick: u64,
+    error: Option<String>,
+}
+
+impl InputPlayer {
+    pub fn new(script: InputScript) -> Self {
+        Self {
+            script,
+            next_event: 0,
+            ticks: 0,
+            last_event_tick: 0,
+            error: None,
+        }
+    }
+
+    // Call before every tick, from when the computer is switched on. Stops
+    // playing if a condition can't be worked out.
+    pub fn update(&mut self, scope: &Scope) {
+        while let Some(event) = self.script.events.get(self.next_event) {
+            let happened = match &event.trigger {
+                Trigger::At(tick) => self.ticks >= *tick,
+                Trigger::After(ticks) => self.ticks >= self.last_event_tick.saturating_add(*ticks),
+                Trigger::When(condition) => match condition.evaluate(scope) {
+                    Ok(value) => value != 0,
+                    Err(err) => {
+                        self.error = Some(format!("input script line {}: {}", event.line, err));
+                        self.next_event = self.script.events.len();
+                        break;
+                    }
+                },
+            };
+            if !happened {
+                break;
+            }
+            scope.computer.ram.lock()[KBD] = event.value;
+            self.last_event_tick = self.ticks;
+            self.next_event += 1;
+        }
+        self.ticks += 1;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_event == self.script.events.len()
+    }
+
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+}
+
+// Records the keyboard register as a program runs, as a script which plays
+// it back at the same instructions.
+#[derive(Default)]
+pub struct InputRecorder {
+    script: InputScript,
+    ticks: u64,
+    value: u16,
+}
+
+impl InputRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Call before every tick, from when the computer is switched on, with
+    // what's in the keyboard register. Returns whether it has changed.
+    pub fn update(&mut self, value: u16) -> bool {
+        let changed = value != self.value;
+        if changed {
+            self.script.events.push(InputEvent {
+                value,
+                trigger: Trigger::At(self.ticks),
+                line: self.script.events.len() + 1,
+            });
+            self.value = value;
+        }
+        self.ticks += 1;
+        changed
+    }
+
+    pub fn script(&self) -> &InputScript {
+        &self.script
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::Computer;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(parse_key("A"), Ok(11));
+        assert_eq!(parse_key("enter"), Ok(41));
+        assert_eq!(parse_key("shift+a"), Ok(4096 + 11));
+        assert_eq!(parse_key("ctrl+alt+-"), Ok(0xc000 + 50));
+        assert_eq!(parse_key("shift"), Ok(4096));
+        assert_eq!(parse_key("7"), Ok(8));
+        assert_eq!(parse_key("100"), Ok(100));
+        assert!(parse_key("F1").is_err());
+        assert!(parse_key("A+B").is_err());
+        for value in [11, 4096 + 11, 0xc000 + 50, 4096, 100, 54] {
+            assert_eq!(parse_key(&format_key(value)), Ok(value));
+        }
+        assert_eq!(format_key(0x9000 + 41), "ctrl+shift+Enter");
+    }
+
+    #[test]
+    fn test_script_round_trip() {
+        let text = "\
+# a comment
+down shift+H at 10  # and another
+
+up after 5
+down Space when RAM[100] > 0 && D = 1
+";
+        let script = InputScript::parse(text).unwrap();
+        assert_eq!(script.events.len(), 3);
+        assert_eq!(script.events[2].line, 5);
+        assert_eq!(
+            script.to_string(),
+            "down shift+H at 10\nup after 5\ndown Space when RAM[100] > 0 && D = 1\n"
+        );
+        assert_eq!(
+            InputScript::parse("down A\n").unwrap_err(),
+            "line 1: expected at <tick>, after <ticks> or when <condition>"
+        );
+        assert!(InputScript::parse("press A at 1").is_err());
+        assert!(InputScript::parse("up when").is_err());
+    }
+
+    #[test]
+    fn test_player() {
+        let computer = Computer::new([0; 32768]);
+        let scope = Scope {
+            computer: &computer,
+            debug_info: None,
+            frames: &[],
+        };
+        let script = InputScript::parse("down A at 2\nup after 3\ndown B when RAM[100] = 7\nup after 1").unwrap();
+        let mut player = InputPlayer::new(script);
+        let mut values = Vec::new();
+        for tick in 0..10 {
+            if tick == 8 {
+                computer.ram.lock()[100] = 7;
+            }
+            player.update(&scope);
+            values.push(computer.ram.lock()[KBD]);
+        }
+        assert_eq!(values, [0, 0, 11, 11, 11, 0, 0, 0, 12, 0]);
+        assert!(player.is_finished());
+        assert_eq!(player.take_error(), None);
+
+        let mut player = InputPlayer::new(InputScript::parse("up when 1 / 0").unwrap());
+        player.update(&scope);
+        assert_eq!(player.take_error(), Some("input script line 1: division by zero".to_string()));
+        assert!(player.is_finished());
+
+        // An `after` too far off to ever happen just waits.
+        let mut player = InputPlayer::new(InputScript::parse("down A at 1\nup after 18446744073709551615").unwrap());
+        for _ in 0..3 {
+            player.update(&scope);
+        }
+        assert!(!player.is_finished());
+    }
+
+    #[test]
+    fn test_recorder() {
+        let mut recorder = InputRecorder::new();
+        let changes: Vec<_> = [0, 11, 11, 0, 4096 + 12, 12].into_iter().map(|value| recorder.update(value)).collect();
+        assert_eq!(changes, [false, true, false, true, true, true]);
+        assert_eq!(recorder.script().to_string(), "down A at 1\nup at 3\ndown shift+B at 4\ndown B at 5\n");
+    }
+}
@@ -1,3 +1,27 @@
+pub mod allocations;
+pub mod arithmetic_trap;
+pub mod backtrace;
+pub mod banks;
+pub mod breakpoints;
 pub mod computer;
+pub mod coverage;
+pub mod debug_info;
+pub mod debugger;
+pub mod disassemble;
+pub mod display;
+pub mod fault;
+pub mod gdb_stub;
 pub mod generate_rom;
+pub mod heap;
+pub mod image_format;
+pub mod input_script;
+pub mod memory_layout;
+pub mod object_graph;
+pub mod png;
+pub mod pointer_check;
+pub mod profiler;
+pub mod ram_image;
 pub mod run;
+pub mod sanitizer;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
@@ -0,0 +1,48 @@
+use std::ops::Range;
+
+// Addresses of the regions and memory-mapped registers described in the
+// memory layout table in the README.
+
+pub const RAM_SIZE: usize = 32768;
+
+pub const SP: usize = 0;
+pub const LCL: usize = 1;
+pub const ARG: usize = 2;
+pub const THIS: usize = 3;
+pub const THAT: usize = 4;
+
+pub const STATIC_BASE: usize = 16;
+pub const STACK_BASE: usize = 256;
+pub const HEAP_BASE: usize = 2048;
+pub const SCREEN: usize = 18432;
+pub const SCREEN_SIZE: usize = 8192;
+pub const KBD: usize = 26624;
+pub const GLYPHS: usize = 26625;
+pub const GLYPHS_SIZE: usize = 3800;
+pub const LEDS: usize = 30425;
+
+// Selects how the screen memory is interpreted. See `display::DisplayMode`.
+pub const DISPLAY_MODE: usize = 30426;
+pub const PALETTE: usize = 30427;
+pub const PALETTE_SIZE: usize = 16;
+
+// Writing a bank number here swaps a different bank of RAM into the bank
+// window. See `banks::MemoryBanks`.
+pub const BANK_SELECT: usize = 30443;
+
+// Writing a non-zero value here arms write protection. Once armed, writing to
+// any protected region is a fault. See `Computer::set_protected_regions`.
+pub const WRITE_PROTECT: usize = 30444;
+
+// Used by `compiler test` to run jack tests. The runner writes which test to
+// run to the first word, and the test writes its result to the second, with
+// the actual and expected values of a failed assertion in the two after that.
+pub const TEST_PORT: usize = 30445;
+pub const TEST_PORT_SIZE: usize = 4;
+pub const UNUSED_BASE: usize = 30449;
+
+pub const BANK_WINDOW: usize = 30720;
+pub const BANK_SIZE: usize = 2048;
+pub const BANK_COUNT: usize = 16;
+
+pub const DEFAULT_PROTECTED_REGIONS: [Range<usize>; 2] = [GLYPHS..GLYPHS + GLYPHS_SIZE, UNUSED_BASE..BANK_WINDOW];
@@ -0,0 +1,250 @@
+// Shows a jack object as a tree of its fields, using the class layouts from
+// the compiler. Fields whose type is a class with a layout are followed into
+// the objects they point to. Everything else, like ints, nulls and Arrays,
+// is shown as a value. Each object only appears in full once, so that cycles
+// and objects shared between fields don't make the tree go on forever.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    computer::Ram,
+    debug_info::{format_value, DebugInfo},
+    memory_layout::RAM_SIZE,
+};
+
+// Strings are shown with their text too, as long as it isn't too long.
+const MAX_STRING_LENGTH: usize = 200;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ObjectTree {
+    Value {
+        type_name: String,
+        value: u16,
+    },
+    Object {
+        class_name: String,
+        address: u16,
+        fields: Vec<ObjectField>,
+        // The characters of a String.
+        text: Option<String>,
+    },
+    // An object which is already in the tree. It's a cycle when the object
+    // is one of the ones that this is inside of.
+    Seen {
+        class_name: String,
+        address: u16,
+        cycle: bool,
+    },
+    // An object which is further down than the depth limit.
+    Elided {
+        class_name: String,
+        address: u16,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ObjectField {
+    pub name: String,
+    pub value: ObjectTree,
+}
+
+struct ObjectGraph<'a> {
+    debug_info: &'a DebugInfo,
+    ram: &'a [u16],
+    max_depth: usize,
+    shown: HashSet<u16>,
+    // The objects that the one being built is inside of.
+    path: Vec<u16>,
+}
+
+impl ObjectGraph<'_> {
+    fn word(&self, address: usize) -> u16 {
+        self.ram[address % RAM_SIZE]
+    }
+
+    fn string_text(&self, fields: &[ObjectField]) -> Option<String> {
+        let field = |name: &str| {
+            fields.iter().find_map(|field| match (&field.value, field.name == name) {
+                (ObjectTree::Value { value, .. }, true) => Some(*value as usize),
+                _ => None,
+            })
+        };
+        let (buffer, length) = (field("buffer")?, field("length")?);
+        if buffer == 0 || length > MAX_STRING_LENGTH {
+            return None;
+        }
+        let chars: Vec<_> = (buffer..buffer + length).map(|address| self.word(address)).collect();
+        Some(String::from_utf16_lossy(&chars))
+    }
+
+    fn tree(&mut self, type_name: &str, value: u16) -> ObjectTree {
+        let class_layout = self.debug_info.class_layout(type_name);
+        let (Some(class_layout), false) = (class_layout, value == 0) else {
+            return ObjectTree::Value {
+                type_name: type_name.to_string(),
+                value,
+            };
+        };
+        let class_name = class_layout.name.clone();
+        if self.shown.contains(&value) {
+            return ObjectTree::Seen {
+                class_name,
+                address: value,
+                cycle: self.path.contains(&value),
+            };
+        }
+        if self.path.len() >= self.max_depth {
+            return ObjectTree::Elided { class_name, address: value };
+        }
+        self.shown.insert(value);
+        self.path.push(value);
+        let fields: Vec<_> = class_layout
+            .fields
+            .iter()
+            .map(|field| ObjectField {
+                name: field.name.clone(),
+                value: self.tree(&field.type_name, self.word(value as usize + field.index)),
+            })
+            .collect();
+        self.path.pop();
+        let text = match class_name.as_str() {
+            "String" => self.string_text(&fields),
+            _ => None,
+        };
+        ObjectTree::Object {
+            class_name,
+            address: value,
+            fields,
+            text,
+        }
+    }
+}
+
+// The tree for a value of a type, which is only more than the value itself
+// when the type is a class with a layout. Objects more than `max_depth` levels
+// down are left out.
+pub fn object_tree(debug_info: &DebugInfo, ram: &[u16], type_name: &str, value: u16, max_depth: usize) -> ObjectTree {
+    let mut graph = ObjectGraph {
+        debug_info,
+        ram,
+        max_depth,
+        shown: HashSet::new(),
+        path: Vec::new(),
+    };
+    graph.tree(type_name, value)
+}
+
+impl ObjectTree {
+    // One line for the value, then one for each field, indented under it.
+    pub fn format(&self, name: &str) -> String {
+        let mut lines = Vec::new();
+        self.format_lines(name, 0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn format_lines(&self, name: &str, indent: usize, lines: &mut Vec<String>) {
+        let description = match self {
+            ObjectTree::Value { type_name, value } => format_value(type_name, *value),
+            ObjectTree::Object {
+                class_name, address, text, ..
+            } => match text {
+                Some(text) => format!("{} @ {} {:?}", class_name, address, text),
+                None => format!("{} @ {}", class_name, address),
+            },
+            ObjectTree::Seen {
+                class_name,
+                address,
+                cycle: true,
+            } => format!("{} @ {} (cycle)", class_name, address),
+            ObjectTree::Seen { class_name, address, .. } => format!("{} @ {} (shown above)", class_name, address),
+            ObjectTree::Elided { class_name, address } => format!("{} @ {} ...", class_name, address),
+        };
+        lines.push(format!("{:indent$}{} = {}", "", name, description, indent = indent));
+        if let ObjectTree::Object { fields, .. } = self {
+            for field in fields {
+                field.value.format_lines(&field.name, indent + 2, lines);
+            }
+        }
+    }
+}
+
+// Looks at objects in the browser, with the class layouts from the debug
+// output of the compiler.
+#[wasm_bindgen]
+pub struct ObjectViewer {
+    debug_info: DebugInfo,
+}
+
+#[wasm_bindgen]
+impl ObjectViewer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(debug_json: &str) -> Result<ObjectViewer, JsValue> {
+        let debug_info = DebugInfo::from_json(debug_json).map_err(|err| JsValue::from_str(&err))?;
+        Ok(ObjectViewer { debug_info })
+    }
+
+    // The tree as JSON, for the object of a class at an address.
+    pub fn object_json(&self, ram: &Ram, class_name: &str, address: u16, max_depth: usize) -> String {
+        let tree = object_tree(&self.debug_info, &*ram.lock(), class_name, address, max_depth);
+        serde_json::to_string(&tree).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestProgram;
+
+    // A list of two nodes which point back at each other, both holding the
+    // same String.
+    fn debug_info_and_ram() -> (DebugInfo, Vec<u16>) {
+        let debug_info = TestProgram::new()
+            .class("Node", &[("value", "int"), ("label", "String"), ("next", "Node"), ("items", "Array")])
+            .class("String", &[("buffer", "int"), ("buffer_length", "int"), ("length", "int")])
+            .build();
+        let mut ram = vec![0; RAM_SIZE];
+        ram[3000..3004].copy_from_slice(&[7, 3100, 3010, 4000]);
+        ram[3010..3014].copy_from_slice(&[-1_i16 as u16, 3100, 3000, 0]);
+        ram[3100..3103].copy_from_slice(&[3200, 4, 2]);
+        ram[3200..3202].copy_from_slice(&[104, 105]);
+        (debug_info, ram)
+    }
+
+    #[test]
+    fn test_object_tree() {
+        let (debug_info, ram) = debug_info_and_ram();
+        let tree = object_tree(&debug_info, &ram, "Node", 3000, 8);
+        assert_eq!(
+            tree.format("head"),
+            "\
+head = Node @ 3000
+  value = 7
+  label = String @ 3100 \"hi\"
+    buffer = 3200
+    buffer_length = 4
+    length = 2
+  next = Node @ 3010
+    value = -1
+    label = String @ 3100 (shown above)
+    next = Node @ 3000 (cycle)
+    items = null
+  items = Array @ 4000"
+        );
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(json["fields"][2]["value"]["fields"][2]["value"]["kind"], "seen");
+        assert_eq!(json["fields"][2]["value"]["fields"][2]["value"]["cycle"], true);
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let (debug_info, ram) = debug_info_and_ram();
+        let tree = object_tree(&debug_info, &ram, "Node", 3000, 1);
+        assert!(tree.format("head").contains("  next = Node @ 3010 ...\n"));
+        assert_eq!(object_tree(&debug_info, &ram, "int", 3000, 1).format("x"), "x = 3000");
+        assert_eq!(object_tree(&debug_info, &ram, "Node", 0, 1).format("x"), "x = null");
+    }
+}
@@ -0,0 +1,111 @@
+// A minimal png encoder for screen exports. The image data is stored using
+// uncompressed deflate blocks, which keeps this simple at the cost of file
+// size - a 512x256 screenshot comes out at about 400KB.
+// The format is described here https://www.w3.org/TR/png/
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+const MAX_STORED_BLOCK_LEN: usize = 65535;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let crc_start = png.len();
+    png.extend(chunk_type);
+    png.extend(data);
+    let crc = crc32(&png[crc_start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window, no preset dictionary and the fastest
+    // compression level, which is the header that fits an uncompressed stream.
+    let mut result = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK_LEN).peekable();
+    if blocks.peek().is_none() {
+        result.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        result.push(is_final as u8);
+        result.extend(len.to_le_bytes());
+        result.extend((!len).to_le_bytes());
+        result.extend(block);
+    }
+    result.extend(adler32(data).to_be_bytes());
+    result
+}
+
+pub fn encode_rgb(width: usize, height: usize, pixels: &[[u8; 3]]) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height, "expected {} pixels", width * height);
+
+    let mut header = Vec::new();
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, default compression, filtering and no
+    // interlacing
+    header.extend([8, 2, 0, 0, 0]);
+
+    let mut scanlines = Vec::with_capacity(height * (1 + width * 3));
+    for row in pixels.chunks(width) {
+        // filter type "none"
+        scanlines.push(0);
+        scanlines.extend(row.iter().flatten());
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn test_encode_rgb() {
+        let png = encode_rgb(2, 1, &[[255, 0, 0], [0, 0, 255]]);
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(png[12..16], *b"IHDR");
+        assert_eq!(png[png.len() - 12..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+
+        let idat_start = 8 + 12 + 13;
+        assert_eq!(png[idat_start + 4..idat_start + 8], *b"IDAT");
+        let zlib = &png[idat_start + 8..png.len() - 16];
+        // a single final stored block containing the filter byte and 6 bytes of pixels
+        assert_eq!(zlib[2..7], [1, 7, 0, !7, 0xff]);
+        assert_eq!(zlib[7..14], [0, 255, 0, 0, 0, 0, 255]);
+    }
+}
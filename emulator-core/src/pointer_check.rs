@@ -0,0 +1,295 @@
+// Checks that calls give back the pointers that their callers rely on. The
+// compiler only saves and restores the pointers that the call graph analysis
+// says are needed, so a mistake there shows up as a subroutine reading LCL,
+// ARG, THIS or THAT after a call has left it pointing somewhere else.
+//
+// This keeps a shadow stack of each caller's pointers as they were when a call
+// command started. When the callee returns, the pointers it saved in its frame
+// have to be back as they were, or the saved copies have been trampled on. Any
+// other pointer with a different value is marked as clobbered for the caller,
+// and it's only a problem if the caller goes on to read it before setting it
+// again.
+
+use crate::{
+    computer::Computer,
+    debug_info::{CallEvent, DebugInfo},
+    memory_layout::{ARG, LCL, THAT, THIS},
+};
+
+const POINTERS: [(&str, usize); 4] = [("LCL", LCL), ("ARG", ARG), ("THIS", THIS), ("THAT", THAT)];
+
+// A pointer which wasn't restored after a call.
+#[derive(Clone, Debug)]
+struct Clobbered {
+    pointer_idx: usize,
+    callee: String,
+    before: u16,
+    after: u16,
+}
+
+#[derive(Default)]
+pub struct PointerChecker {
+    // For each frame, its caller's pointers from when the call started.
+    saved_pointers: Vec<[u16; 4]>,
+    // For each frame, the pointers which its callees haven't restored.
+    clobbered: Vec<Vec<Clobbered>>,
+    // The pointers from the start of the call command which is running.
+    pending_call: Option<[u16; 4]>,
+    violation: Option<String>,
+}
+
+// The pointer which a vm command uses, and whether it only sets it.
+fn pointer_access(vm_command: &str) -> Option<(usize, bool)> {
+    let words: Vec<_> = vm_command.split_whitespace().collect();
+    let [operation @ ("push" | "pop"), segment, index] = words.as_slice() else {
+        return None;
+    };
+    match *segment {
+        "LCL" => Some((0, false)),
+        "ARG" => Some((1, false)),
+        "THIS" => Some((2, false)),
+        "THAT" => Some((3, false)),
+        "pointer" => Some((2 + index.parse::<usize>().ok()?, *operation == "pop")),
+        _ => None,
+    }
+}
+
+fn current_pointers(computer: &Computer) -> [u16; 4] {
+    let ram = computer.ram.lock();
+    POINTERS.map(|(_, address)| ram[address])
+}
+
+impl PointerChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The first time a caller relied on a pointer which wasn't restored, if
+    // that's happened since this was last called.
+    pub fn take_violation(&mut self) -> Option<String> {
+        self.violation.take()
+    }
+
+    // Call after every tick, with the pc from before the tick and what the
+    // call tracker made of it.
+    pub fn update(&mut self, debug_info: &DebugInfo, computer: &Computer, prev_pc: u16, event: Option<&CallEvent>) {
+        let pc = computer.cpu.pc;
+        match event {
+            Some(CallEvent::Call) => {
+                let pointers = self.pending_call.take().unwrap_or_else(|| current_pointers(computer));
+                self.saved_pointers.push(pointers);
+                self.clobbered.push(Vec::new());
+            }
+            Some(CallEvent::Return) => {
+                self.clobbered.pop();
+                let (Some(before), Some(clobbered)) = (self.saved_pointers.pop(), self.clobbered.last_mut()) else {
+                    return;
+                };
+                let callee = debug_info.subroutine_at(prev_pc);
+                let saved = callee.map_or(&[][..], |subroutine| &subroutine.saved_pointers[..]);
+                let callee = callee.map_or("", |subroutine| &subroutine.name);
+                for (pointer_idx, after) in current_pointers(computer).into_iter().enumerate() {
+                    clobbered.retain(|clobbered| clobbered.pointer_idx != pointer_idx);
+                    if after != before[pointer_idx] {
+                        let name = POINTERS[pointer_idx].0;
+                        if saved.iter().any(|saved| saved == name) && self.violation.is_none() {
+                            let caller = debug_info.subroutine_at(pc).map_or("", |subroutine| &subroutine.name);
+                            self.violation = Some(format!(
+                                "{} saves {} for its caller, but it wasn't restored on returning to {} (it was {} before the call and {} after)",
+                                callee, name, caller, before[pointer_idx], after
+                            ));
+                        }
+                        clobbered.push(Clobbered {
+                            pointer_idx,
+                            callee: callee.to_string(),
+                            before: before[pointer_idx],
+                            after,
+                        });
+                    }
+                }
+            }
+            None => {}
+        }
+
+        // Vm commands are checked as they start.
+        let Some(pc_info) = debug_info.pc_info(pc) else {
+            return;
+        };
+        let continuing = pc == prev_pc.wrapping_add(1)
+            && debug_info
+                .pc_info(prev_pc)
+                .is_some_and(|prev| (&prev.filename, prev.vm_command_idx) == (&pc_info.filename, pc_info.vm_command_idx));
+        if continuing {
+            return;
+        }
+        if pc_info.vm_command.starts_with("call ") {
+            self.pending_call = Some(current_pointers(computer));
+        }
+        let (Some((pointer_idx, sets)), Some(clobbered)) = (pointer_access(&pc_info.vm_command), self.clobbered.last_mut()) else {
+            return;
+        };
+        let Some(position) = clobbered.iter().position(|clobbered| clobbered.pointer_idx == pointer_idx) else {
+            return;
+        };
+        let clobbered = clobbered.remove(position);
+        if !sets && self.violation.is_none() {
+            let subroutine = &debug_info.subroutines[pc_info.subroutine_idx];
+            let location = debug_info
+                .location(pc)
+                .map_or(format!("pc {}", pc), |location| format!("{}:{}", location.filename, location.line));
+            self.violation = Some(format!(
+                "{} relies on {} at {}, but it wasn't restored after calling {} (it was {} before the call and {} after)",
+                subroutine.name, POINTERS[pointer_idx].0, location, clobbered.callee, clobbered.before, clobbered.after
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        debugger::{Debugger, RunMode, StopReason},
+        testing::TestProgram,
+    };
+
+    use super::*;
+
+    const JUMP: u16 = 0b1110101010000111; // 0;JMP
+
+    // Main.main calls Main.helper, which messes up ARG and THIS. Main.main sets
+    // THIS again before using it, but then uses ARG as it is.
+    fn debugger() -> Debugger {
+        let mut debug_info = TestProgram::new()
+            .file("Sys.jack", "class Sys {\n  function void init() {\n    do Main.main(1);\n  }\n}\n")
+            .subroutine("Sys.init", 0, &[], &[(2, "function Sys.init 0"), (3, "call Main.main 1")])
+            .file(
+                "Main.jack",
+                "class Main {
+  function void main(int x) {
+    do Main.helper();
+    let this = 0;
+    let x = this.y + x;
+    return;
+  }
+
+  function void helper() {
+    return;
+  }
+}
+",
+            )
+            .subroutine(
+                "Main.main",
+                1,
+                &[],
+                &[
+                    (2, "function Main.main 0"),
+                    (3, "call Main.helper 0"),
+                    (4, "pop pointer 0"),
+                    (5, "push THIS 0"),
+                    (5, "push ARG 0"),
+                    (6, "return"),
+                ],
+            )
+            .subroutine("Main.helper", 0, &[], &[(9, "function Main.helper 0"), (10, "return")])
+            .build();
+        let rom = &mut debug_info.rom;
+        // Sys.init calls Main.main, which calls Main.helper.
+        rom[0] = 2;
+        rom[1] = JUMP;
+        rom[2] = 8;
+        rom[3] = JUMP;
+        // Main.helper returns to Main.main.
+        rom[8] = 4;
+        rom[9] = JUMP;
+        let mut debugger = Debugger::new(debug_info);
+        debugger.check_pointers();
+        debugger
+    }
+
+    #[test]
+    fn test_unrestored_pointer() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(9);
+        assert_eq!(debugger.run(RunMode::Continue, 100), Some(StopReason::Breakpoint));
+        {
+            let mut ram = debugger.computer.ram.lock();
+            ram[ARG] = 300;
+            ram[THIS] = 2048;
+        }
+        assert_eq!(
+            debugger.run(RunMode::Continue, 100),
+            Some(StopReason::PointerNotRestored(
+                "Main.main relies on ARG at Main.jack:5, but it wasn't restored after calling Main.helper (it was 0 before the call and 300 after)"
+                    .to_string()
+            ))
+        );
+        assert_eq!(debugger.computer.cpu.pc, 6);
+    }
+
+    #[test]
+    fn test_trampled_saved_pointer() {
+        let mut debug_info = TestProgram::new()
+            .file("Sys.jack", "class Sys {\n  function void init() {\n    do Main.main();\n  }\n}\n")
+            .subroutine("Sys.init", 0, &[], &[(2, "function Sys.init 0"), (3, "call Main.main 0")])
+            .file(
+                "Main.jack",
+                "class Main {
+  function void main() {
+    do Main.helper();
+    let this = 0;
+    return;
+  }
+
+  function void helper() {
+    return;
+  }
+}
+",
+            )
+            .subroutine(
+                "Main.main",
+                0,
+                &[],
+                &[
+                    (2, "function Main.main 0"),
+                    (3, "call Main.helper 0"),
+                    (4, "pop pointer 0"),
+                    (5, "return"),
+                ],
+            )
+            .subroutine("Main.helper", 0, &[], &[(8, "function Main.helper 0"), (9, "return")])
+            .saved_pointers(&["THIS"])
+            .build();
+        let rom = &mut debug_info.rom;
+        rom[0] = 2;
+        rom[1] = JUMP;
+        rom[2] = 6;
+        rom[3] = JUMP;
+        rom[6] = 4;
+        rom[7] = JUMP;
+        let mut debugger = Debugger::new(debug_info);
+        debugger.check_pointers();
+        debugger.add_breakpoint(7);
+        assert_eq!(debugger.run(RunMode::Continue, 100), Some(StopReason::Breakpoint));
+        // As if the copy of THIS in the frame had been overwritten. Main.main
+        // sets THIS straight away, so it doesn't rely on it, but Main.helper
+        // says it gives it back.
+        debugger.computer.ram.lock()[THIS] = 2048;
+        assert_eq!(
+            debugger.run(RunMode::Continue, 100),
+            Some(StopReason::PointerNotRestored(
+                "Main.helper saves THIS for its caller, but it wasn't restored on returning to Main.main (it was 0 before the call and 2048 after)"
+                    .to_string()
+            ))
+        );
+        assert_eq!(debugger.computer.cpu.pc, 4);
+    }
+
+    #[test]
+    fn test_restored_pointers() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(6);
+        assert_eq!(debugger.run(RunMode::Continue, 100), Some(StopReason::Breakpoint));
+    }
+}
@@ -0,0 +1,233 @@
+// Counts how many instructions run at each pc, and how many run under each
+// call stack, so that they can be added up through the sourcemaps into time
+// spent in each jack subroutine and on each jack line.
+//
+// Each instruction belongs to the subroutine which is running it, which is its
+// self time. The inclusive time of a subroutine also has everything it calls,
+// which comes from the call stacks. A subroutine which is on a stack more than
+// once, by recursion, only gets each instruction counted once.
+//
+// The stacks are kept as a tree, with one node per distinct path of calls
+// from the outermost subroutine, so counting an instruction is just adding to
+// the node for the stack it's on. The tree also gives folded stacks, which
+// flamegraph tools take as lines like `Sys.init;Main.main;Math.multiply 1234`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::debug_info::{CallEvent, CallTracker, DebugInfo};
+
+const ROM_SIZE: usize = 32768;
+
+struct StackNode {
+    subroutine_idx: usize,
+    parent: Option<usize>,
+    ticks: u64,
+    calls: u64,
+}
+
+pub struct Profiler {
+    // Indexed by pc.
+    pc_ticks: Vec<u64>,
+    nodes: Vec<StackNode>,
+    children: HashMap<(Option<usize>, usize), usize>,
+    // The node for the stack which is running. None before Sys.init has been
+    // called, while the boot code runs.
+    current: Option<usize>,
+    // Instructions which didn't run in any subroutine.
+    outside_ticks: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubroutineProfile {
+    pub name: String,
+    pub self_ticks: u64,
+    pub inclusive_ticks: u64,
+    pub calls: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineProfile {
+    pub filename: String,
+    pub line: usize,
+    pub ticks: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub total_ticks: u64,
+    // Most inclusive time first.
+    pub subroutines: Vec<SubroutineProfile>,
+    // Most time first.
+    pub lines: Vec<LineProfile>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            pc_ticks: vec![0; ROM_SIZE],
+            nodes: Vec::new(),
+            children: HashMap::new(),
+            current: None,
+            outside_ticks: 0,
+        }
+    }
+
+    // Call after every tick, with the pc from before the tick and what the
+    // call tracker made of it.
+    pub fn update(&mut self, call_tracker: &CallTracker, prev_pc: u16, event: Option<&CallEvent>) {
+        self.pc_ticks[prev_pc as usize % ROM_SIZE] += 1;
+        match self.current {
+            Some(node) => self.nodes[node].ticks += 1,
+            None => self.outside_ticks += 1,
+        }
+        match event {
+            Some(CallEvent::Call) => {
+                let Some(frame) = call_tracker.frames().last() else {
+                    return;
+                };
+                let node = self.child(self.current, frame.subroutine_idx);
+                self.nodes[node].calls += 1;
+                self.current = Some(node);
+            }
+            Some(CallEvent::Return) => {
+                self.current = self.current.and_then(|node| self.nodes[node].parent);
+            }
+            None => {}
+        }
+    }
+
+    fn child(&mut self, parent: Option<usize>, subroutine_idx: usize) -> usize {
+        let nodes = &mut self.nodes;
+        *self.children.entry((parent, subroutine_idx)).or_insert_with(|| {
+            nodes.push(StackNode {
+                subroutine_idx,
+                parent,
+                ticks: 0,
+                calls: 0,
+            });
+            nodes.len() - 1
+        })
+    }
+
+    // The subroutines on the stack of a node, outermost first.
+    fn stack(&self, node: usize) -> Vec<usize> {
+        let mut stack = Vec::new();
+        let mut current = Some(node);
+        while let Some(node) = current {
+            stack.push(self.nodes[node].subroutine_idx);
+            current = self.nodes[node].parent;
+        }
+        stack.reverse();
+        stack
+    }
+
+    pub fn profile(&self, debug_info: &DebugInfo) -> Profile {
+        let mut self_ticks: HashMap<usize, u64> = HashMap::new();
+        let mut line_ticks: HashMap<(&str, usize), u64> = HashMap::new();
+        for (pc, &ticks) in (0..).zip(&self.pc_ticks).filter(|(_, &ticks)| ticks > 0) {
+            if let Some(pc_info) = debug_info.pc_info(pc) {
+                *self_ticks.entry(pc_info.subroutine_idx).or_default() += ticks;
+            }
+            if let Some(location) = debug_info.location(pc) {
+                *line_ticks.entry((&location.filename, location.line)).or_default() += ticks;
+            }
+        }
+
+        let mut inclusive_ticks: HashMap<usize, u64> = HashMap::new();
+        let mut calls: HashMap<usize, u64> = HashMap::new();
+        for (idx, node) in self.nodes.iter().enumerate() {
+            *calls.entry(node.subroutine_idx).or_default() += node.calls;
+            let on_stack: HashSet<_> = self.stack(idx).into_iter().collect();
+            for subroutine_idx in on_stack {
+                *inclusive_ticks.entry(subroutine_idx).or_default() += node.ticks;
+            }
+        }
+
+        let mut subroutines: Vec<_> = inclusive_ticks
+            .iter()
+            .map(|(&subroutine_idx, &inclusive_ticks)| SubroutineProfile {
+                name: debug_info.subroutines[subroutine_idx].name.clone(),
+                self_ticks: self_ticks.get(&subroutine_idx).copied().unwrap_or_default(),
+                inclusive_ticks,
+                calls: calls[&subroutine_idx],
+            })
+            .collect();
+        subroutines.sort_by(|a, b| b.inclusive_ticks.cmp(&a.inclusive_ticks).then_with(|| a.name.cmp(&b.name)));
+        let mut lines: Vec<_> = line_ticks
+            .into_iter()
+            .map(|((filename, line), ticks)| LineProfile {
+                filename: filename.to_string(),
+                line,
+                ticks,
+            })
+            .collect();
+        lines.sort_by(|a, b| b.ticks.cmp(&a.ticks).then_with(|| (&a.filename, a.line).cmp(&(&b.filename, b.line))));
+
+        Profile {
+            total_ticks: self.pc_ticks.iter().sum(),
+            subroutines,
+            lines,
+        }
+    }
+
+    // One line per call stack that ran any instructions, with how many it ran.
+    pub fn folded_stacks(&self, debug_info: &DebugInfo) -> String {
+        let mut stacks = BTreeMap::new();
+        for (idx, node) in self.nodes.iter().enumerate().filter(|(_, node)| node.ticks > 0) {
+            let names: Vec<_> = self
+                .stack(idx)
+                .into_iter()
+                .map(|subroutine_idx| debug_info.subroutines[subroutine_idx].name.as_str())
+                .collect();
+            stacks.insert(names.join(";"), node.ticks);
+        }
+        if self.outside_ticks > 0 {
+            stacks.insert("(boot)".to_string(), self.outside_ticks);
+        }
+        stacks.iter().map(|(stack, ticks)| format!("{} {}\n", stack, ticks)).collect()
+    }
+}
+
+fn percentage(ticks: u64, total_ticks: u64) -> f64 {
+    ticks as f64 * 100.0 / total_ticks.max(1) as f64
+}
+
+impl Profile {
+    // The top `rows` subroutines and lines.
+    pub fn format(&self, rows: usize) -> String {
+        let mut lines = vec![
+            format!("{} instructions", self.total_ticks),
+            String::new(),
+            format!("{:>12} {:>6} {:>12} {:>6} {:>8}  subroutine", "inclusive", "%", "self", "%", "calls"),
+        ];
+        for subroutine in self.subroutines.iter().take(rows) {
+            lines.push(format!(
+                "{:>12} {:>5.1}% {:>12} {:>5.1}% {:>8}  {}",
+                subroutine.inclusive_ticks,
+                percentage(subroutine.inclusive_ticks, self.total_ticks),
+                subroutine.self_ticks,
+                percentage(subroutine.self_ticks, self.total_ticks),
+                subroutine.calls,
+                subroutine.name
+            ));
+        }
+        lines.push(String::new());
+        lines.push(format!("{:>12} {:>6}  line", "self", "%"));
+        for line in self.lines.iter().take(rows) {
+            lines.push(format!(
+                "{:>12} {:>5.1}%  {}:{}",
+                line.ticks,
+                percentage(line.ticks, self.total_ticks),
+                line.filename,
+                line.line
+            ));
+        }
+        lines.join("\n")
+    }
+}
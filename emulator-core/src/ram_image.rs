@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+use crate::memory_layout::RAM_SIZE;
+
+// Data which is already in RAM when the computer is switched on, so that
+// programs don't have to spend ROM and startup time writing it there.
+//
+// Most of RAM starts off zeroed, so the image is a list of segments of
+// contiguous words. See `image_format` for reading and writing images.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RamImage {
+    pub segments: Vec<RamSegment>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RamSegment {
+    pub address: usize,
+    pub words: Vec<u16>,
+}
+
+impl RamImage {
+    pub fn add_segment(&mut self, address: usize, words: Vec<u16>) {
+        assert!(address + words.len() <= RAM_SIZE, "segment at {} doesn't fit in RAM", address);
+        self.segments.push(RamSegment { address, words });
+    }
+
+    pub fn load(&self, ram: &mut [u16; RAM_SIZE]) {
+        for RamSegment { address, words } in &self.segments {
+            ram[*address..*address + words.len()].copy_from_slice(words);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let mut image = RamImage::default();
+        image.add_segment(16, vec![1, 2, 3]);
+        image.add_segment(0x6801, vec![0xffff]);
+
+        let mut ram = [0; RAM_SIZE];
+        image.load(&mut ram);
+        assert_eq!(ram[15..20], [0, 1, 2, 3, 0]);
+        assert_eq!(ram[0x6801], 0xffff);
+    }
+}
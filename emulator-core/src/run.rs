@@ -1,20 +1,76 @@
-use std::{thread, time::Instant};
+use std::{fs, path::PathBuf, thread, time::Instant};
 
-use crate::computer::{tick, Computer, Ram};
+use crate::{
+    backtrace::unwind,
+    breakpoints::Scope,
+    computer::{tick, Computer, Ram},
+    debug_info::{CallTracker, DebugInfo},
+    input_script::{InputPlayer, InputRecorder},
+    memory_layout::KBD,
+};
 
 pub trait IO {
     fn refresh(&mut self, ram: &Ram);
 }
 
-pub fn run(mut computer: Computer, io: &mut dyn IO) {
+// Where key presses come from, besides the window.
+pub enum Input {
+    Window,
+    // Plays a script. The window shouldn't touch the keyboard register.
+    Script(InputPlayer),
+    // Records what's typed into the window, and writes it out as a script
+    // whenever it changes.
+    Record(InputRecorder, PathBuf),
+}
+
+// With debug info, faults are followed by a backtrace of the jack code, and
+// a script's conditions can use the variables of the subroutines being run.
+pub fn run(mut computer: Computer, io: &mut dyn IO, debug_info: Option<DebugInfo>, mut input: Input) {
     let cloned_ram = computer.ram.clone();
+    // Only scripts look at the frames, so nothing else pays for tracking them.
+    let mut call_tracker = match (&input, &debug_info) {
+        (Input::Script(_), Some(_)) => Some(CallTracker::default()),
+        _ => None,
+    };
 
     let mut count = 0u64;
     let start_time = Instant::now();
     thread::spawn(move || loop {
+        if let Some(fault) = computer.fault() {
+            println!("computer faulted: {}", fault);
+            if let Some(debug_info) = &debug_info {
+                println!("{}", unwind(debug_info, &computer).format(debug_info, &computer));
+            }
+            return;
+        }
+        match &mut input {
+            Input::Window => {}
+            Input::Script(player) => {
+                player.update(&Scope {
+                    computer: &computer,
+                    debug_info: debug_info.as_ref(),
+                    frames: call_tracker.as_ref().map_or(&[], |call_tracker| call_tracker.frames()),
+                });
+                if let Some(error) = player.take_error() {
+                    println!("{}", error);
+                }
+            }
+            Input::Record(recorder, path) => {
+                let value = computer.ram.lock()[KBD];
+                if recorder.update(value) {
+                    if let Err(err) = fs::write(&*path, recorder.script().to_string()) {
+                        println!("failed to write {}: {}", path.display(), err);
+                    }
+                }
+            }
+        }
+        let prev_pc = computer.cpu.pc;
         tick(&mut computer);
+        if let (Some(call_tracker), Some(debug_info)) = (&mut call_tracker, &debug_info) {
+            call_tracker.update(debug_info, &computer, prev_pc);
+        }
         count += 1;
-        if count == 1_00_000_0000 {
+        if count == 1_000_000_000 {
             dbg!(Instant::now().duration_since(start_time));
         }
     });
@@ -0,0 +1,279 @@
+// A tiny valgrind for jack programs. It keeps shadow state for every word of
+// RAM - whether it's been written since it was last made undefined, and which
+// heap block owns it - and checks each read and write that instructions make
+// against it. It catches
+//
+//  - reads of locals before they've been set, since the compiler doesn't zero
+//    them, and of heap memory from malloc which hasn't been written yet
+//  - `this` and `that` accesses through a null pointer
+//  - heap accesses outside of any allocated block, like going off the end of
+//    an Array
+//  - accesses to blocks which have been given back with Memory.deAlloc
+//
+// Blocks are found with the allocation tracker. The allocator itself has to
+// poke around in blocks' headers and in freed blocks, so the heap isn't
+// checked while code in Memory is running.
+
+use crate::{
+    allocations::{AllocationEvent, AllocationTracker},
+    computer::{bit, Computer},
+    debug_info::{CallEvent, CallTracker, DebugInfo},
+    memory_layout::{HEAP_BASE, RAM_SIZE, SCREEN, THAT, THIS},
+};
+
+// The word that an instruction reads or writes through M.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: usize,
+    pub read: bool,
+    pub write: bool,
+}
+
+// Works out what an instruction is going to do to memory, given what's in A
+// before it runs.
+pub fn memory_access(instruction: u16, reg_a: u16) -> Option<MemoryAccess> {
+    if bit(instruction, 15) == 0 {
+        return None;
+    }
+    let access = MemoryAccess {
+        address: reg_a as usize % RAM_SIZE,
+        read: bit(instruction, 12) == 1,
+        write: bit(instruction, 3) == 1,
+    };
+    (access.read || access.write).then_some(access)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Owner {
+    Nothing,
+    Block(usize),
+    Freed(usize),
+}
+
+struct Block {
+    address: usize,
+    size: usize,
+    // The words up to the end of the buddy block it was given, which is where
+    // running off the end of it goes first.
+    capacity: usize,
+    allocated_at: String,
+    freed_at: Option<String>,
+}
+
+pub struct Sanitizer {
+    defined: Vec<bool>,
+    owners: Vec<Owner>,
+    blocks: Vec<Block>,
+    allocation_tracker: AllocationTracker,
+    error: Option<String>,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The usable words in the block that Memory.malloc hands out for a size.
+fn block_capacity(size: usize) -> usize {
+    (size + 2).next_power_of_two().max(4) - 2
+}
+
+fn is_heap(address: usize) -> bool {
+    (HEAP_BASE..SCREEN).contains(&address)
+}
+
+impl Sanitizer {
+    // The heap starts off undefined, and everything else, including the stack,
+    // starts off defined. Locals are made undefined each time a subroutine is
+    // called.
+    pub fn new() -> Self {
+        Self {
+            defined: (0..RAM_SIZE).map(|address| !is_heap(address)).collect(),
+            owners: vec![Owner::Nothing; RAM_SIZE],
+            blocks: Vec::new(),
+            allocation_tracker: AllocationTracker::new(),
+            error: None,
+        }
+    }
+
+    // The first problem found since this was last called.
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    fn report(&mut self, debug_info: &DebugInfo, pc: u16, problem: String) {
+        if self.error.is_some() {
+            return;
+        }
+        let place = match (debug_info.location(pc), debug_info.subroutine_at(pc)) {
+            (Some(location), Some(subroutine)) => {
+                format!("pc {}, {}:{} in {}", pc, location.filename, location.line, subroutine.name)
+            }
+            _ => format!("pc {}", pc),
+        };
+        self.error = Some(format!("{} at {}", problem, place));
+    }
+
+    fn describe_unowned(&self, address: usize) -> String {
+        let block = self
+            .blocks
+            .iter()
+            .rev()
+            .find(|block| block.freed_at.is_none() && (block.address..block.address + block.capacity).contains(&address));
+        match block {
+            Some(block) => format!(
+                "RAM[{}] is past the end of the {} word block at {} allocated by {}",
+                address, block.size, block.address, block.allocated_at
+            ),
+            None => format!("RAM[{}] isn't in an allocated block", address),
+        }
+    }
+
+    fn describe_undefined(&self, debug_info: &DebugInfo, call_tracker: &CallTracker, address: usize) -> String {
+        if let Some(frame) = call_tracker.frames().last() {
+            let subroutine = &debug_info.subroutines[frame.subroutine_idx];
+            let local = address
+                .checked_sub(frame.lcl as usize)
+                .and_then(|index| subroutine.locals.iter().find(|local| local.index == index));
+            if let Some(local) = local {
+                return format!("read of local {} before it was set", local.name);
+            }
+        }
+        match self.owners[address] {
+            Owner::Block(idx) => format!(
+                "read of RAM[{}] in the {} word block at {} before it was set",
+                address, self.blocks[idx].size, self.blocks[idx].address
+            ),
+            _ => format!("read of RAM[{}] before it was set", address),
+        }
+    }
+
+    fn check_access(&mut self, debug_info: &DebugInfo, call_tracker: &CallTracker, computer: &Computer, pc: u16, access: MemoryAccess) {
+        let Some(pc_info) = debug_info.pc_info(pc) else {
+            return;
+        };
+        let address = access.address;
+
+        let words: Vec<_> = pc_info.vm_command.split_whitespace().collect();
+        if let ["push" | "pop", segment @ ("THIS" | "THAT"), index] = words.as_slice() {
+            let pointer = computer.ram.lock()[if *segment == "THIS" { THIS } else { THAT }];
+            if pointer == 0 && index.parse() == Ok(address) {
+                let problem = format!("access to RAM[{}] through null {}", address, segment);
+                self.report(debug_info, pc, problem);
+            }
+        }
+
+        let in_allocator = debug_info.subroutines[pc_info.subroutine_idx].class_name() == "Memory";
+        if is_heap(address) && !in_allocator {
+            match self.owners[address] {
+                Owner::Nothing => {
+                    let problem = format!("access outside of the heap's blocks: {}", self.describe_unowned(address));
+                    self.report(debug_info, pc, problem);
+                }
+                Owner::Freed(idx) => {
+                    let block = &self.blocks[idx];
+                    let problem = format!(
+                        "access to RAM[{}] in the block at {} allocated by {}, which was freed by {}",
+                        address,
+                        block.address,
+                        block.allocated_at,
+                        block.freed_at.as_deref().unwrap_or_default()
+                    );
+                    self.report(debug_info, pc, problem);
+                }
+                Owner::Block(_) => {}
+            }
+        }
+
+        if access.read && !self.defined[address] && !(is_heap(address) && in_allocator) {
+            let problem = self.describe_undefined(debug_info, call_tracker, address);
+            self.report(debug_info, pc, problem);
+            // Only reported once.
+            self.defined[address] = true;
+        }
+        if access.write {
+            self.defined[address] = true;
+        }
+    }
+
+    // Call after every tick, with the pc from before the tick, the access that
+    // the instruction there made, and what the call tracker made of it.
+    pub fn update(
+        &mut self,
+        debug_info: &DebugInfo,
+        call_tracker: &CallTracker,
+        computer: &Computer,
+        prev_pc: u16,
+        access: Option<MemoryAccess>,
+        event: Option<&CallEvent>,
+    ) {
+        if let Some(access) = access {
+            self.check_access(debug_info, call_tracker, computer, prev_pc, access);
+        }
+
+        if let (Some(CallEvent::Call), Some(frame)) = (event, call_tracker.frames().last()) {
+            let locals_count = debug_info.subroutines[frame.subroutine_idx].locals_count;
+            for address in frame.lcl as usize..frame.lcl as usize + locals_count {
+                self.defined[address % RAM_SIZE] = false;
+            }
+        }
+
+        match self.allocation_tracker.update(debug_info, call_tracker, computer, event) {
+            Some(AllocationEvent::Allocated(allocation)) => {
+                let idx = self.blocks.len();
+                let (address, size) = (allocation.address as usize, allocation.size as usize);
+                self.blocks.push(Block {
+                    address,
+                    size,
+                    capacity: block_capacity(size),
+                    allocated_at: allocation.site,
+                    freed_at: None,
+                });
+                for address in address..(address + size).min(SCREEN) {
+                    self.defined[address] = allocation.zeroed;
+                    self.owners[address] = Owner::Block(idx);
+                }
+            }
+            Some(AllocationEvent::Freed(allocation, freed_at)) => {
+                let (address, size) = (allocation.address as usize, allocation.size as usize);
+                let Owner::Block(idx) = self.owners[address] else {
+                    return;
+                };
+                self.blocks[idx].freed_at = Some(freed_at);
+                for owner in &mut self.owners[address..(address + size).min(SCREEN)] {
+                    *owner = Owner::Freed(idx);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const D_EQ_M: u16 = 0b1111110000010000;
+    const M_EQ_D: u16 = 0b1110001100001000;
+    const M_EQ_M_PLUS_1: u16 = 0b1111110111001000;
+    const D_EQ_A: u16 = 0b1110110000010000;
+
+    #[test]
+    fn test_memory_access() {
+        let access = |read, write| Some(MemoryAccess { address: 300, read, write });
+        assert_eq!(memory_access(D_EQ_M, 300), access(true, false));
+        assert_eq!(memory_access(M_EQ_D, 300), access(false, true));
+        assert_eq!(memory_access(M_EQ_M_PLUS_1, 300), access(true, true));
+        assert_eq!(memory_access(D_EQ_A, 300), None);
+        assert_eq!(memory_access(300, 0), None);
+    }
+
+    #[test]
+    fn test_block_capacity() {
+        assert_eq!(block_capacity(1), 2);
+        assert_eq!(block_capacity(2), 2);
+        assert_eq!(block_capacity(3), 6);
+        assert_eq!(block_capacity(14), 14);
+    }
+}
@@ -0,0 +1,181 @@
+// Debug info for made up programs, so that the things which need to know about
+// subroutines and source lines can be tested without running the compiler.
+// Each vm command is one instruction, all of them zero until a test puts
+// something else in the ROM, and each line of jack is one statement.
+
+use serde_json::{json, Value};
+
+use crate::debug_info::DebugInfo;
+
+struct TestFile {
+    filename: String,
+    source: String,
+    subroutines: Vec<Value>,
+    // The line each vm command in the file came from.
+    command_lines: Vec<usize>,
+}
+
+#[derive(Default)]
+pub struct TestProgram {
+    files: Vec<TestFile>,
+    // The file and vm command of each pc.
+    pcs: Vec<(usize, usize)>,
+    class_layouts: serde_json::Map<String, Value>,
+    saved_pointers: serde_json::Map<String, Value>,
+}
+
+impl TestProgram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file(mut self, filename: &str, source: &str) -> Self {
+        self.files.push(TestFile {
+            filename: filename.to_string(),
+            source: source.to_string(),
+            subroutines: Vec::new(),
+            command_lines: Vec::new(),
+        });
+        self
+    }
+
+    // Adds a subroutine to the last file, laid out after everything added so
+    // far. Its commands are each given with the line they came from, and its
+    // locals are all ints.
+    pub fn subroutine(mut self, name: &str, arg_count: usize, locals: &[&str], commands: &[(usize, &str)]) -> Self {
+        let file_idx = self.files.len() - 1;
+        let file = self.files.last_mut().expect("subroutines go in a file");
+        for &(line, _) in commands {
+            self.pcs.push((file_idx, file.command_lines.len()));
+            file.command_lines.push(line);
+        }
+        let locals: Vec<_> = locals
+            .iter()
+            .enumerate()
+            .map(|(index, name)| json!({ "name": name, "var_type": "Int", "index": index }))
+            .collect();
+        file.subroutines.push(json!({
+            "name": name,
+            "commands": commands.iter().map(|(_, command)| json!({ "command": command })).collect::<Vec<_>>(),
+            "locals_count": locals.len(),
+            "arg_count": arg_count,
+            "locals": locals,
+        }));
+        self
+    }
+
+    // Sets which of its caller's pointers the last subroutine saves, like
+    // `THIS`. Subroutines don't save any unless this is called.
+    pub fn saved_pointers(mut self, pointers: &[&str]) -> Self {
+        let subroutine = self
+            .files
+            .last()
+            .and_then(|file| file.subroutines.last())
+            .expect("saved pointers go with a subroutine");
+        let name = subroutine["name"].as_str().unwrap().to_string();
+        self.saved_pointers.insert(name, json!(pointers));
+        self
+    }
+
+    // Adds the layout of a class, with its fields given as names and types
+    // like `int` or `Point`. It doesn't need a file.
+    pub fn class(mut self, name: &str, fields: &[(&str, &str)]) -> Self {
+        let fields: Vec<_> = fields
+            .iter()
+            .enumerate()
+            .map(|(index, (name, type_name))| {
+                let var_type = match *type_name {
+                    "int" => json!("Int"),
+                    "char" => json!("Char"),
+                    "boolean" => json!("Boolean"),
+                    class_name => json!({ "ClassName": class_name }),
+                };
+                json!({ "name": name, "var_type": var_type, "index": index })
+            })
+            .collect();
+        self.class_layouts
+            .insert(format!("{}.jack", name), json!({ "name": name, "fields": fields, "statics": [] }));
+        self
+    }
+
+    pub fn build(self) -> DebugInfo {
+        let mut sourcemaps = serde_json::Map::new();
+        let mut tokens = serde_json::Map::new();
+        let mut subroutines = serde_json::Map::new();
+        for file in &self.files {
+            let lines: Vec<_> = file.source.split_inclusive('\n').collect();
+            let nodes: Vec<_> = (0..lines.len())
+                .map(|idx| json!({ "token_range": { "start": idx, "end": idx + 1 }, "child_node_idxs": [] }))
+                .collect();
+            let mut statement_node_idxs: Vec<_> = file.command_lines.iter().map(|line| line - 1).collect();
+            statement_node_idxs.sort();
+            statement_node_idxs.dedup();
+            let command_nodes: serde_json::Map<_, _> = file
+                .command_lines
+                .iter()
+                .enumerate()
+                .map(|(vm_command_idx, line)| (vm_command_idx.to_string(), json!(line - 1)))
+                .collect();
+            sourcemaps.insert(
+                file.filename.clone(),
+                json!({
+                    "parser_sourcemap": { "jack_nodes": nodes },
+                    "codegen_sourcemap": {
+                        "vm_command_idx_to_jack_node_idx": command_nodes,
+                        "statement_node_idxs": statement_node_idxs,
+                    },
+                }),
+            );
+            tokens.insert(file.filename.clone(), lines.iter().map(|line| json!({ "source": line })).collect());
+            subroutines.insert(file.filename.clone(), json!(file.subroutines));
+        }
+        let asm_to_vm: serde_json::Map<_, _> = self
+            .pcs
+            .iter()
+            .enumerate()
+            .map(|(pc, &(file_idx, vm_command_idx))| {
+                (
+                    pc.to_string(),
+                    json!({ "filename": self.files[file_idx].filename, "vm_command_idx": vm_command_idx }),
+                )
+            })
+            .collect();
+        let result = json!({
+            "jack_compiler_result": {
+                "sourcemaps": sourcemaps,
+                "tokens": tokens,
+                "subroutines": subroutines,
+                "class_layouts": self.class_layouts,
+            },
+            "vm_compiler_result": {
+                "sourcemap": { "asm_instruction_idx_to_vm_cmd": asm_to_vm },
+                "saved_pointers": self.saved_pointers,
+            },
+            "assembly_result": {
+                "instructions": vec![0; self.pcs.len()],
+                "sourcemap": { "machine_code_to_asm": (0..self.pcs.len()).collect::<Vec<_>>() },
+            },
+        });
+        DebugInfo::from_json(&result.to_string()).expect("test program should make valid debug info")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_layout() {
+        let debug_info = TestProgram::new()
+            .file("Main.jack", "class Main {\n  function void main() {\n    return;\n  }\n}\n")
+            .subroutine("Main.main", 0, &[], &[(2, "function Main.main 0"), (3, "push constant 0"), (3, "return")])
+            .build();
+        assert_eq!(debug_info.rom, [0, 0, 0]);
+        assert_eq!(debug_info.subroutine_entered_at(0), Some(0));
+        assert_eq!(debug_info.pc_info(2).unwrap().vm_command, "return");
+        assert_eq!(debug_info.location(1).unwrap().line, 3);
+        assert_eq!(debug_info.line_pcs("Main.jack", 3), [1]);
+        assert!(!debug_info.is_statement_start(2));
+        assert_eq!(debug_info.source("Main.jack").unwrap().lines().nth(2), Some("    return;"));
+    }
+}
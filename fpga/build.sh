@@ -6,7 +6,7 @@
 
 # # cd fpga
 
-# # node ./verilog/romgen.js blinky >counter_test/components/rom.mem
+# # cargo run --package compiler -- convert-image ./verilog/programs/blinky counter_test/components/rom.mem --depth 32768
 
 # # ICARUS_OUT_FILENAME="icarus.out"
 # # ALL_VERILOG_FILES=$(find counter_test/components -name '*.v')
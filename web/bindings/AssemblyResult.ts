@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { AssemblySourcemap } from "./AssemblySourcemap";
 
-export interface AssemblyResult { instructions: Array<number>, sourcemap: AssemblySourcemap, }
\ No newline at end of file
+export interface AssemblyResult { instructions: Array<number>, sourcemap: AssemblySourcemap, static_addresses: Record<string, number>, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { StaticVariable } from "./StaticVariable";
+import type { Variable } from "./Variable";
+
+export interface ClassLayout { name: string, fields: Array<Variable>, statics: Array<StaticVariable>, }
\ No newline at end of file
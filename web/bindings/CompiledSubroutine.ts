@@ -1,4 +1,5 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { SourcemappedCommand } from "./SourcemappedCommand";
+import type { Variable } from "./Variable";
 
-export interface CompiledSubroutine { name: string, commands: Array<SourcemappedCommand>, locals_count: number, arg_count: number, }
\ No newline at end of file
+export interface CompiledSubroutine { name: string, commands: Array<SourcemappedCommand>, locals_count: number, arg_count: number, arguments: Array<Variable>, locals: Array<Variable>, }
\ No newline at end of file
@@ -3,4 +3,4 @@ import type { JackCompilerSourceMap } from "./JackCompilerSourceMap";
 import type { Token } from "./Token";
 import type { TokenKind } from "./TokenKind";
 
-export interface JackCompilerResult { sourcemaps: Record<string, JackCompilerSourceMap>, tokens: Record<string, Array<Token<TokenKind>>>, subroutines: Record<string, Array<CompiledSubroutine>>, }
\ No newline at end of file
+export interface JackCompilerResult { sourcemaps: Record<string, JackCompilerSourceMap>, tokens: Record<string, Array<Token<TokenKind>>>, subroutines: Record<string, Array<CompiledSubroutine>>, class_layouts: Record<string, ClassLayout>, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Type } from "./Type";
+
+export interface StaticVariable { name: string, var_type: Type, index: number, address: number | null, }
\ No newline at end of file
@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { SourceMap } from "./SourceMap";
 
-export interface VMCompilerResult { sourcemap: SourceMap, instructions: Array<string>, }
\ No newline at end of file
+export interface VMCompilerResult { sourcemap: SourceMap, instructions: Array<string>, ram_image: { segments: Array<{ address: number, words: Array<number> }> }, saved_pointers: Record<string, Array<string>>, }
\ No newline at end of file
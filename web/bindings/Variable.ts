@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Type } from "./Type";
+
+export interface Variable { name: string, var_type: Type, index: number, }
\ No newline at end of file
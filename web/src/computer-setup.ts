@@ -1,4 +1,7 @@
-import { make_computer as makeComputer } from "../../web-emulator/pkg/web_emulator";
+import {
+  make_computer as makeComputer,
+  load_ram_segment as loadRamSegment,
+} from "../../web-emulator/pkg/web_emulator";
 
 import data from "../debug-output.json";
 import { CompilerResult } from "../bindings/CompilerResult";
@@ -6,10 +9,14 @@ import { CompilerResult } from "../bindings/CompilerResult";
 const compilerResult = data as CompilerResult;
 const {
   assembly_result: { instructions },
+  vm_compiler_result: { ram_image },
 } = compilerResult;
 
 const rom = new Uint16Array(instructions);
 const computer = makeComputer(rom);
+for (const { address, words } of ram_image.segments) {
+  loadRamSegment(computer, address, new Uint16Array(words));
+}
 
 // eslint-disable-next-line @typescript-eslint/no-unsafe-member-access
 (window as any).computer = computer;